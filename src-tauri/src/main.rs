@@ -2,21 +2,17 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 use event_emitter::EventEmitter;
 use tauri::{api::private::OnceCell, AppHandle, Manager};
+use transfer_engine::shared::priority::set_thread_priority;
 #[macro_use]
 pub mod utils;
-mod compression;
-mod errnos;
-mod fs;
 mod locale;
 mod notifications;
-mod path;
-mod shared;
 mod transfer;
 mod ui;
 mod window;
 use transfer::ffi::{
-    init, is_complete, is_dir_status_calculated, set_next_worker, start, update_progress,
-    TransferState,
+    add_job, apply_profile, get_settings, is_complete, job_info, list_jobs, list_profiles,
+    move_job, remove_profile, save_profile, set_settings, TransferState,
 };
 
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
@@ -28,9 +24,28 @@ fn greet(name: &str) -> String {
 pub static APP: OnceCell<AppHandle> = OnceCell::new();
 
 fn main() {
+    let context = tauri::generate_context!();
+    let config_dir = tauri::api::path::app_config_dir(context.config())
+        .expect("failed to resolve the config directory");
+    let state = TransferState::new(config_dir);
+
+    // the copies run on these threads so they carry the priority from the settings
+    let priority = state.priority();
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .on_thread_start(move || {
+            if let Err(err) = set_thread_priority(priority) {
+                log::warn!("failed to set the thread priority: {}", err);
+            }
+        })
+        .build()
+        .expect("failed to build the runtime");
+    tauri::async_runtime::set(runtime.handle().clone());
+
     tauri::Builder::default()
-        .setup(|app| {
+        .setup(move |app| {
             APP.set(app.handle()).unwrap();
+            app.manage(state);
             // TODO manage decortation of the window
             let window = app.handle().get_window("main").unwrap();
             window
@@ -46,17 +61,21 @@ fn main() {
 
             Ok(())
         })
-        .manage(TransferState::default())
         .invoke_handler(tauri::generate_handler![
             greet,
-            init,
-            start,
-            set_next_worker,
-            update_progress,
-            is_dir_status_calculated,
-            is_complete // set_dir_status_calculated
+            add_job,
+            list_jobs,
+            job_info,
+            move_job,
+            is_complete,
+            get_settings,
+            set_settings,
+            list_profiles,
+            save_profile,
+            remove_profile,
+            apply_profile
         ])
-        .run(tauri::generate_context!())
+        .run(context)
         .expect("error while running tauri application");
 }
 
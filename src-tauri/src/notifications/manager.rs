@@ -7,10 +7,9 @@ use std::{
 
 use lazy_static::lazy_static;
 
-use crate::{
-    fs::decision::{Decision, DecisionEntry, UserDecision},
-    window::bring_window_focus,
-};
+use transfer_engine::fs::decision::{Decision, DecisionEntry, UserDecision};
+
+use crate::window::bring_window_focus;
 
 use walkdir::DirEntry as WalkDirEntry;
 
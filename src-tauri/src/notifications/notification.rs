@@ -3,12 +3,13 @@ use std::path::Path;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::{
+use transfer_engine::{
     errnos::{Errno, PropErrno, PropErrnoParams},
     path::PathExt,
-    transfer::ffi::send_log,
 };
 
+use crate::transfer::ffi::send_log;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum NotificationKind {
     /// Informational notification
@@ -1,50 +1,54 @@
-use std::{
-    path::{self, Path, PathBuf},
-    thread::JoinHandle,
-};
+use std::{collections::BTreeMap, path::Path};
 
-use hashbrown::HashMap;
 use parking_lot::Mutex;
 use tauri::Manager;
+use transfer_engine::{
+    errnos::ErrnoResult,
+    shared::priority::IoPriority,
+    transfer::{
+        JobId, JobInfo, JobManager, Settings, SettingsStore, TransferBuilder, SETTINGS_FILE,
+    },
+};
 
 use crate::{
-    errnos::{Errno, ErrnoResult},
-    fs::traversal::DirTraversal,
     notifications::{Notification, NOTIFICATION_MANAGER},
-    path::PathExt,
-    shared::progress::{Progress, ProgressUpdater},
     APP,
 };
 
-use super::{dst_path::DstPath, settings::USER_SETTINGS, worker::Worker};
+use super::observer::TauriObserver;
 
-#[derive(Default)]
 pub struct TransferState {
-    progress: Mutex<Progress>,
-    id: Mutex<u8>,
-    dst_path: Mutex<Option<DstPath>>,
-    traversal: Mutex<Option<DirTraversal>>,
-    state: Mutex<HashMap<u8, Worker>>,
-}
-
-pub fn progress_from_other_thread(processed: u64) {
-    let handle = APP.get().unwrap();
-
-    handle
-        .get_window("main")
-        .unwrap()
-        .emit("processed", processed)
-        .expect("worker done failed to send");
+    manager: JobManager,
+    settings: Mutex<SettingsStore>,
 }
 
-fn update_progress_ui(percent: u8) {
-    let handle = APP.get().unwrap();
+impl TransferState {
+    /// loads the user settings from the config directory
+    /// if they cannot be read the user is notified and the defaults are used
+    pub fn new<P: AsRef<Path>>(config_dir: P) -> Self {
+        let path = config_dir.as_ref().join(SETTINGS_FILE);
+        let settings = SettingsStore::load(&path).unwrap_or_else(|err| {
+            NOTIFICATION_MANAGER
+                .write()
+                .push(Notification::new_from_errno(err));
+            SettingsStore::new(&path)
+        });
+
+        let manager = JobManager::default()
+            .set_observer(TauriObserver)
+            .set_memory_budget(settings.settings().memory_budget());
+        manager.set_bandwidth_limit(settings.settings().bandwidth_limit());
+
+        Self {
+            manager,
+            settings: Mutex::new(settings),
+        }
+    }
 
-    handle
-        .get_window("main")
-        .unwrap()
-        .emit("progress", percent)
-        .expect("worker done failed to send");
+    /// the priority the runtime threads should start with
+    pub fn priority(&self) -> IoPriority {
+        self.settings.lock().settings().priority()
+    }
 }
 
 pub fn send_log(log: String) {
@@ -57,130 +61,95 @@ pub fn send_log(log: String) {
         .expect("fail to send log");
 }
 
-pub fn worker_done(id: u8) {
-    let handle = APP.get().unwrap();
-
-    handle
-        .get_window("main")
-        .unwrap()
-        .emit("worker-done", id)
-        .expect("worker done failed to send");
+/// queues a copy of `src` into `dst`, it starts right away if there is a free slot
+/// # Arguments
+/// * `password` - encrypts the archive or the parts, it is never saved
+/// * `recipients` - the public keys to encrypt the archive to instead of the ones of the settings
+#[tauri::command]
+pub async fn add_job(
+    src: &str,
+    dst: &str,
+    password: Option<String>,
+    recipients: Option<Vec<String>>,
+    state: tauri::State<'_, TransferState>,
+) -> ErrnoResult<JobId> {
+    let mut settings = state.inner().settings.lock().settings().clone();
+    if let Some(recipients) = recipients {
+        settings = settings.set_recipients(recipients);
+    }
+    state.inner().manager.push(
+        TransferBuilder::new(src, dst)
+            .set_settings(settings)
+            .set_password(password),
+    )
 }
 
 #[tauri::command]
-pub async fn init(src: &str, dst: &str, state: tauri::State<'_, TransferState>) -> ErrnoResult<()> {
-    let dst =
-        DstPath::new(PathBuf::from(dst)).ok_or_else(|| Errno::path_normalize(dst.to_string()))?; // this is the destination path (where the files will be copied to
-
-    *state.inner().dst_path.lock() = Some(dst);
-
-    let mut progress = Progress::new_no_total();
-
-    progress.set_progress_tracker(update_progress_ui);
+pub fn list_jobs(state: tauri::State<'_, TransferState>) -> Vec<JobInfo> {
+    state.inner().manager.jobs()
+}
 
-    *state.inner().progress.lock() = progress;
-    *state.inner().traversal.lock() = Some(DirTraversal::new(src));
-    Ok(())
+#[tauri::command]
+pub fn job_info(id: JobId, state: tauri::State<'_, TransferState>) -> ErrnoResult<JobInfo> {
+    state.inner().manager.job(id)
 }
 
+/// moves a queued job to `position` in the queue, 0 being the next one to run
 #[tauri::command]
-pub fn start(state: tauri::State<'_, TransferState>) {
-    println!("start called");
-    let worker_count = USER_SETTINGS.read().as_ref().unwrap().worker_threads();
-    for id in 0..worker_count {
-        if !set_next_worker(state.clone()) {
-            println!("no more workers to start {}", id);
-            // traversal is done
-            break;
-        }
-    }
+pub fn move_job(
+    id: JobId,
+    position: usize,
+    state: tauri::State<'_, TransferState>,
+) -> ErrnoResult<()> {
+    state.inner().manager.move_job(id, position)
 }
 
 #[tauri::command]
 pub fn is_complete(state: tauri::State<'_, TransferState>) -> bool {
-    (*state.inner().state.lock()).len() == 0
+    state.inner().manager.is_idle()
 }
 
 #[tauri::command]
-pub fn set_next_worker(state: tauri::State<'_, TransferState>) -> bool {
-    let id = *state.inner().id.lock();
-    if let Some(worker) = get_next(id, &state) {
-        state.inner().state.lock().insert(id, worker);
-        *state.inner().id.lock() += 1;
-        return true;
-    }
-
-    false
+pub fn get_settings(state: tauri::State<'_, TransferState>) -> Settings {
+    state.inner().settings.lock().settings().clone()
 }
 
+/// only applies to the jobs added after this, except the bandwidth limit which applies right away
+/// and the priority and memory budget which apply on the next start
 #[tauri::command]
-pub fn update_progress(processed: u64, state: tauri::State<'_, TransferState>) {
-    state.inner().progress.lock().update(processed);
+pub fn set_settings(settings: Settings, state: tauri::State<'_, TransferState>) -> ErrnoResult<()> {
+    let limit = settings.bandwidth_limit();
+    state.inner().settings.lock().set_settings(settings)?;
+    state.inner().manager.set_bandwidth_limit(limit);
+    Ok(())
 }
 
 #[tauri::command]
-pub fn completed_worker(id: u8, state: tauri::State<'_, TransferState>) -> bool {
-    println!("Worker {} removed", id);
-    state.inner().state.lock().remove(&id);
-    return set_next_worker(state);
+pub fn list_profiles(state: tauri::State<'_, TransferState>) -> BTreeMap<String, Settings> {
+    state.inner().settings.lock().profiles().clone()
 }
 
 #[tauri::command]
-pub fn is_dir_status_calculated(state: tauri::State<'_, TransferState>) -> bool {
-    state
-        .inner()
-        .traversal
-        .lock()
-        .as_ref()
-        .unwrap()
-        .is_complete()
+pub fn save_profile(
+    name: String,
+    settings: Settings,
+    state: tauri::State<'_, TransferState>,
+) -> ErrnoResult<()> {
+    state.inner().settings.lock().save_profile(name, settings)
 }
 
-// #[tauri::command]
-// pub fn set_dir_status_calculated(state: tauri::State<'_, TransferState>) {
-//     state
-//         .inner()
-//         .traversal
-//         .lock()
-//         .as_mut()
-//         .unwrap()
-//         .mut_status()
-//         .calculate();
-// }
-
-fn get_next(id: u8, state: &tauri::State<'_, TransferState>) -> Option<Worker> {
-    // *state.s.lock().unwrap() = "new string".into();
-    // state.t.lock().unwrap().insert("key".into(), "value".into());
-    // get next entry
-    let entry = (*state.inner().traversal.lock())
-        .as_mut()
-        .unwrap()
-        .get_next();
-    if let None = entry {
-        // traversal is done
-        return None;
-    }
-
-    let entry = entry.unwrap();
-    if let Err(err) = entry {
-        println!("{}", err);
-        // Add notification
-        NOTIFICATION_MANAGER
-            .write()
-            .push(Notification::new_from_properrno(
-                err,
-                Path::unknown_path(),
-                Path::unknown_path(),
-            )); // these are empty strings because error will be populated with the correct paths
-        return get_next(id, state);
-    }
-
-    let entry = entry.unwrap();
+#[tauri::command]
+pub fn remove_profile(name: &str, state: tauri::State<'_, TransferState>) -> ErrnoResult<()> {
+    state.inner().settings.lock().remove_profile(name)
+}
 
-    let dst = (*state.inner().dst_path.lock())
-        .as_mut()
-        .unwrap()
-        .build_dst(&entry)
-        .to_path_buf();
-    return Some(Worker::create_new_copier(id, entry.into_path(), dst));
+/// returns the settings that are now in use
+#[tauri::command]
+pub fn apply_profile(name: &str, state: tauri::State<'_, TransferState>) -> ErrnoResult<Settings> {
+    let settings = state.inner().settings.lock().apply_profile(name).cloned()?;
+    state
+        .inner()
+        .manager
+        .set_bandwidth_limit(settings.bandwidth_limit());
+    Ok(settings)
 }
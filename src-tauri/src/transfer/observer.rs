@@ -0,0 +1,47 @@
+use serde::Serialize;
+use tauri::Manager;
+use transfer_engine::transfer::{JobId, JobObserver, TransferEvent};
+
+use crate::{
+    notifications::{Notification, NOTIFICATION_MANAGER},
+    APP,
+};
+
+/// Payload of every transfer event so the UI can tell the jobs apart
+#[derive(Clone, Serialize)]
+struct JobPayload<T: Serialize + Clone> {
+    id: JobId,
+    value: T,
+}
+
+/// Forwards the events of the transfer engine to the main window
+/// errors are pushed to the notification manager instead
+pub struct TauriObserver;
+
+impl JobObserver for TauriObserver {
+    fn notify(&self, id: JobId, event: TransferEvent) {
+        match event {
+            TransferEvent::Processed(processed) => emit("processed", id, processed),
+            TransferEvent::Progress(percent) => emit("progress", id, percent),
+            TransferEvent::WorkerDone(worker) => emit("worker-done", id, worker),
+            TransferEvent::Memory(memory) => emit("memory", id, memory),
+            TransferEvent::Compression(summary) => emit("compression", id, summary),
+            TransferEvent::Dedup(report) => emit("dedup", id, report),
+            TransferEvent::Pool(stats) => emit("pool", id, stats),
+            TransferEvent::Error(errno) => NOTIFICATION_MANAGER
+                .write()
+                .push(Notification::new_from_errno(errno)),
+            TransferEvent::Completed => emit("complete", id, ()),
+        }
+    }
+}
+
+fn emit<T: Serialize + Clone>(event: &str, id: JobId, value: T) {
+    let handle = APP.get().unwrap();
+
+    handle
+        .get_window("main")
+        .unwrap()
+        .emit(event, JobPayload { id, value })
+        .expect("failed to send transfer event");
+}
@@ -5,7 +5,6 @@ pub mod array;
 // pub mod behold;
 pub mod event_emitter;
 pub mod log;
-pub mod strings;
 extern crate alloc;
 /// Calls a function and aborts if it panics.
 ///
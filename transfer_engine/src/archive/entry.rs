@@ -0,0 +1,219 @@
+use std::{
+    fs::Metadata,
+    path::{Component, Path},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{compression::algorithm::Algorithm, encryption::NONCE_LEN};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EntryKind {
+    File,
+    Dir,
+    /// the target as it was read from the link, it is not resolved
+    Symlink(String),
+}
+
+/// What is kept of an entry of the source besides its data,
+/// by the index of an archive and by the snapshots of a chunk store
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryMeta {
+    /// relative to the archive or the snapshot, the components are separated by `/`
+    path: String,
+    kind: EntryKind,
+    /// seconds since the unix epoch
+    mtime: Option<u64>,
+    /// unix permission bits
+    mode: Option<u32>,
+}
+
+impl EntryMeta {
+    pub fn new(path: String, kind: EntryKind, meta: Option<&Metadata>) -> Self {
+        let mtime = meta
+            .and_then(|meta| meta.modified().ok())
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_secs());
+
+        Self {
+            path,
+            kind,
+            mtime,
+            mode: meta.and_then(mode),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn kind(&self) -> &EntryKind {
+        &self.kind
+    }
+
+    pub fn mtime(&self) -> Option<SystemTime> {
+        self.mtime
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    pub fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+}
+
+/// An entry of the index of an archive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    #[serde(flatten)]
+    meta: EntryMeta,
+    algorithm: Algorithm,
+    /// where the compressed data starts in the archive
+    offset: u64,
+    compressed_len: u64,
+    /// size before compression, 0 when the data is encrypted
+    len: u64,
+    /// crc32 of the data before compression, 0 when the data is encrypted.
+    /// they would give away what is in it, the encryption authenticates the data anyway
+    crc: u32,
+    /// the compressed data is an encrypted stream
+    #[serde(default)]
+    encrypted: bool,
+    /// the nonce of the encrypted stream, the data of another entry has another one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nonce: Option<[u8; NONCE_LEN]>,
+    /// the group of the dictionary the data was compressed with, see `ArchiveIndex::dictionaries`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dictionary: Option<String>,
+}
+
+impl ArchiveEntry {
+    /// an entry with no data yet
+    pub fn new(path: String, kind: EntryKind, meta: Option<&Metadata>) -> Self {
+        Self {
+            meta: EntryMeta::new(path, kind, meta),
+            algorithm: Algorithm::None,
+            offset: 0,
+            compressed_len: 0,
+            len: 0,
+            crc: 0,
+            encrypted: false,
+            nonce: None,
+            dictionary: None,
+        }
+    }
+
+    pub fn set_data(
+        &mut self,
+        algorithm: Algorithm,
+        offset: u64,
+        compressed_len: u64,
+        len: u64,
+        crc: u32,
+    ) {
+        self.algorithm = algorithm;
+        self.offset = offset;
+        self.compressed_len = compressed_len;
+        self.len = len;
+        self.crc = crc;
+    }
+
+    pub fn meta(&self) -> &EntryMeta {
+        &self.meta
+    }
+
+    pub fn path(&self) -> &str {
+        self.meta.path()
+    }
+
+    pub fn kind(&self) -> &EntryKind {
+        self.meta.kind()
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn compressed_len(&self) -> u64 {
+        self.compressed_len
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// the data is an encrypted stream with `nonce`, None when it is not encrypted
+    pub fn set_nonce(&mut self, nonce: Option<[u8; NONCE_LEN]>) {
+        self.encrypted = nonce.is_some();
+        self.nonce = nonce;
+    }
+
+    pub fn nonce(&self) -> Option<&[u8; NONCE_LEN]> {
+        self.nonce.as_ref()
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    pub fn set_dictionary(&mut self, dictionary: Option<String>) {
+        self.dictionary = dictionary;
+    }
+
+    pub fn dictionary(&self) -> Option<&str> {
+        self.dictionary.as_deref()
+    }
+
+    /// whether the entry is `prefix` or somewhere under it
+    pub fn is_under(&self, prefix: &str) -> bool {
+        let path = self.path();
+        let prefix = prefix.trim_end_matches('/');
+        prefix.is_empty()
+            || path == prefix
+            || (path.starts_with(prefix) && path[prefix.len()..].starts_with('/'))
+    }
+}
+
+impl AsRef<EntryMeta> for ArchiveEntry {
+    fn as_ref(&self) -> &EntryMeta {
+        &self.meta
+    }
+}
+
+/// the name a path is stored under, None if it is not made only of plain names
+/// NOTE: names that are not valid utf-8 are stored lossy
+pub fn entry_name<P: AsRef<Path>>(path: P) -> Option<String> {
+    let mut names = Vec::new();
+    for component in path.as_ref().components() {
+        match component {
+            Component::Normal(name) => names.push(name.to_string_lossy()),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    (!names.is_empty()).then(|| names.join("/"))
+}
+
+#[cfg(unix)]
+fn mode(meta: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(meta.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn mode(_meta: &Metadata) -> Option<u32> {
+    None
+}
@@ -0,0 +1,47 @@
+// A single file holding a whole compressed transfer.
+// Every entry is compressed on its own, one after the other, so the archive is written as a
+// stream. The index of the entries is written at the end, which lets an archive be listed
+// and extracted in part without reading through all of it:
+//
+// | magic 4 | version 1 | entry data ... | index (json) | index mac | index offset 8 |
+// | index len 8 | index crc32 4 | magic 4 |
+//
+// the index of version 1 is only the list of the entries,
+// version 3 adds the zstd dictionaries some of the entries are compressed with,
+// version 4 adds the mac of the index (see `encryption`) when the entries are encrypted.
+// the names and the metadata stay readable, the mac keeps them from being changed and
+// the nonces of the streams in the index keep the data of the entries from being swapped
+pub mod entry;
+pub mod reader;
+pub mod writer;
+
+pub use entry::{ArchiveEntry, EntryKind, EntryMeta};
+pub use reader::ArchiveReader;
+pub use writer::ArchiveWriter;
+
+use serde::{Deserialize, Serialize};
+
+pub const ARCHIVE_MAGIC: [u8; 4] = *b"SPPA";
+pub const ARCHIVE_VERSION: u8 = 4;
+pub const ARCHIVE_EXT: &str = "sppa";
+const ARCHIVE_HEADER_LEN: usize = ARCHIVE_MAGIC.len() + 1;
+const TRAILER_LEN: usize = 8 + 8 + 4 + ARCHIVE_MAGIC.len();
+/// the first version with the mac of the index
+const MAC_VERSION: u8 = 4;
+/// size of the reads of the sources and of the writes of the extracted files
+const BUFFER_SIZE: usize = 64 * 1024; // 64KB
+/// what a writer holds besides its compressors: the buffer of the reads and of the archive
+pub const WRITER_MEMORY: u64 = 2 * BUFFER_SIZE as u64;
+
+/// What is written at the end of the archive
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ArchiveIndex {
+    entries: Vec<ArchiveEntry>,
+    /// the data key of the encrypted entries, wrapped to the public keys of the recipients.
+    /// None when the archive is encrypted with a password, or not at all
+    #[serde(default)]
+    wrapped_key: Option<Vec<u8>>,
+    /// stored like the data of the files, the path of the entry is the group of the dictionary
+    #[serde(default)]
+    dictionaries: Vec<ArchiveEntry>,
+}
@@ -0,0 +1,1143 @@
+use std::{
+    ffi::OsStr,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use hashbrown::HashMap;
+
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, BufReader},
+};
+
+use super::{
+    entry::ArchiveEntry, ArchiveIndex, ARCHIVE_HEADER_LEN, ARCHIVE_MAGIC, ARCHIVE_VERSION,
+    BUFFER_SIZE, MAC_VERSION, TRAILER_LEN,
+};
+use crate::{
+    compression::{decompress::Decomprossor, dictionary::DICTIONARY_SIZE},
+    encryption::{DecryptReader, EncryptionHeader, EncryptionKey, Identity, HEADER_LEN, MAC_LEN},
+    errnos::{PropErrno, PropErrnoResult},
+    path::{
+        unpack::{UnpackRoot, UnpackSource},
+        PathExt,
+    },
+};
+
+/// Lists and extracts the entries of an archive written by `ArchiveWriter`
+/// NOTE: the entries of an encrypted archive can be listed without the key,
+/// but they are only trusted once the key checks the mac of the index
+pub struct ArchiveReader {
+    path: PathBuf,
+    file: File,
+    entries: Vec<ArchiveEntry>,
+    /// the index as it was read and its mac, until the key checks them
+    mac: Option<(Vec<u8>, [u8; MAC_LEN])>,
+    is_verified: bool,
+    /// the data key of the entries encrypted to public keys
+    wrapped_key: Option<Vec<u8>>,
+    /// needed by the entries encrypted with a password
+    password: Option<String>,
+    /// needed by the entries encrypted to public keys
+    identities: Vec<Identity>,
+    /// the keys derived so far, the entries of an archive usually share one
+    keys: Vec<EncryptionKey>,
+    /// where the dictionaries are in the archive
+    dictionaries: Vec<ArchiveEntry>,
+    /// the dictionaries read so far by group
+    loaded: HashMap<String, Arc<Vec<u8>>>,
+}
+
+impl ArchiveReader {
+    /// reads the index at the end of the archive, the entries are not read yet
+    pub async fn open<P: AsRef<Path>>(path: P) -> PropErrnoResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let unpack_err = |reason: &str| {
+            log::error!("{}: {}", path.to_string_lossy(), reason);
+            PropErrno::UnpackVal(path.parent_and_current())
+        };
+
+        let mut file = PropErrno::from_io_result(File::open(&path).await, Some(&path))?;
+        let len = PropErrno::from_io_result(file.metadata().await, Some(&path))?.len();
+        if len < (ARCHIVE_HEADER_LEN + TRAILER_LEN) as u64 {
+            return Err(unpack_err("too short to be an archive"));
+        }
+
+        let mut header = [0; ARCHIVE_HEADER_LEN];
+        PropErrno::from_io_result(file.read_exact(&mut header).await, Some(&path))?;
+        if header[..ARCHIVE_MAGIC.len()] != ARCHIVE_MAGIC {
+            return Err(unpack_err("not an archive"));
+        }
+        let version = header[ARCHIVE_MAGIC.len()];
+        if version == 0 || version > ARCHIVE_VERSION {
+            return Err(unpack_err("written by a newer version"));
+        }
+
+        let mut trailer = [0; TRAILER_LEN];
+        let res = async {
+            file.seek(SeekFrom::End(-(TRAILER_LEN as i64))).await?;
+            file.read_exact(&mut trailer).await
+        };
+        PropErrno::from_io_result(res.await, Some(&path))?;
+        if trailer[TRAILER_LEN - ARCHIVE_MAGIC.len()..] != ARCHIVE_MAGIC {
+            // the writer never got to `finish`
+            return Err(unpack_err("the index is missing"));
+        }
+
+        let index_offset = u64::from_be_bytes(trailer[0..8].try_into().unwrap());
+        let index_len = u64::from_be_bytes(trailer[8..16].try_into().unwrap());
+        let index_crc = u32::from_be_bytes(trailer[16..20].try_into().unwrap());
+        // the mac is between the index and the trailer
+        let mac_len = index_offset
+            .checked_add(index_len)
+            .and_then(|index_end| (len - TRAILER_LEN as u64).checked_sub(index_end));
+        let mac_len = match mac_len {
+            Some(0) => 0,
+            Some(mac_len) if version >= MAC_VERSION && mac_len == MAC_LEN as u64 => MAC_LEN,
+            _ => return Err(unpack_err("the index is out of the archive")),
+        };
+        if index_offset < ARCHIVE_HEADER_LEN as u64 {
+            return Err(unpack_err("the index is out of the archive"));
+        }
+
+        let mut index = vec![0; index_len as usize + mac_len];
+        let res = async {
+            file.seek(SeekFrom::Start(index_offset)).await?;
+            file.read_exact(&mut index).await
+        };
+        PropErrno::from_io_result(res.await, Some(&path))?;
+        let mac = index.split_off(index_len as usize);
+        if crc32fast::hash(&index) != index_crc {
+            return Err(unpack_err("the index is corrupted"));
+        }
+        // SAFE because the mac is either empty or `MAC_LEN` long
+        let mac = (!mac.is_empty()).then(|| mac.try_into().unwrap());
+
+        let parsed = match version {
+            1 => serde_json::from_slice(&index).map(|entries| ArchiveIndex {
+                entries,
+                ..Default::default()
+            }),
+            _ => serde_json::from_slice::<ArchiveIndex>(&index),
+        };
+        let ArchiveIndex {
+            entries,
+            wrapped_key,
+            dictionaries,
+        } = parsed.map_err(|_| unpack_err("the index is corrupted"))?;
+        let mac = mac.map(|mac| (index, mac));
+        if entries
+            .iter()
+            .chain(&dictionaries)
+            .any(|entry| entry.offset().saturating_add(entry.compressed_len()) > index_offset)
+        {
+            return Err(unpack_err("an entry is out of the archive"));
+        }
+
+        Ok(Self {
+            path,
+            file,
+            entries,
+            mac,
+            is_verified: false,
+            wrapped_key,
+            password: None,
+            identities: Vec::new(),
+            keys: Vec::new(),
+            dictionaries,
+            loaded: HashMap::new(),
+        })
+    }
+
+    pub fn set_password(mut self, password: Option<String>) -> Self {
+        self.password = password;
+        self.keys.clear();
+        self
+    }
+
+    /// the private keys that might open an archive encrypted to public keys
+    pub fn set_identities(mut self, identities: Vec<Identity>) -> Self {
+        self.identities = identities;
+        self.keys.clear();
+        self
+    }
+
+    /// whether the entries are encrypted to public keys
+    pub fn has_recipients(&self) -> bool {
+        self.wrapped_key.is_some()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// the entries in the order they were written, parents before their children
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    pub fn find(&self, path: &str) -> Option<&ArchiveEntry> {
+        self.entries.iter().find(|entry| entry.path() == path)
+    }
+
+    /// decompresses the data of a file entry into `writer` and checks its crc
+    /// returns the number of bytes written
+    pub async fn read_entry<W: AsyncWrite + Unpin>(
+        &mut self,
+        entry: &ArchiveEntry,
+        writer: &mut W,
+    ) -> PropErrnoResult<u64> {
+        self.read_entry_at_most(entry, writer, u64::MAX).await
+    }
+
+    /// same as `read_entry`, the entry is corrupted when it decompresses to more than `limit` bytes
+    async fn read_entry_at_most<W: AsyncWrite + Unpin>(
+        &mut self,
+        entry: &ArchiveEntry,
+        writer: &mut W,
+        limit: u64,
+    ) -> PropErrnoResult<u64> {
+        let archive = self.path.to_string_lossy().into_owned();
+        let corrupted = || {
+            log::error!("{}: {} is corrupted", archive, entry.path());
+            PropErrno::CorruptedFileVal(entry.path().to_string())
+        };
+
+        self.verify_index().await?;
+        let dictionary = match entry.dictionary() {
+            Some(group) => Some(self.dictionary(group, entry).await?),
+            None => None,
+        };
+        let dictionary = dictionary.as_ref().map(|dictionary| dictionary.as_slice());
+
+        let seek_res = self.file.seek(SeekFrom::Start(entry.offset())).await;
+        PropErrno::from_io_result(seek_res, Some(&self.path))?;
+
+        let len = if entry.is_encrypted() {
+            let header = EncryptionHeader::read(&mut (&mut self.file).take(entry.compressed_len()))
+                .await
+                .map_err(|_| corrupted())?;
+            // the data of another entry is another stream
+            if entry.nonce() != Some(header.nonce()) {
+                return Err(corrupted());
+            }
+            let key = self.key(&header, entry.path()).await?;
+            let sealed_len = entry.compressed_len() - HEADER_LEN as u64;
+            let data = BufReader::new((&mut self.file).take(sealed_len));
+            let data = DecryptReader::new(data, &header, &key);
+            decompress_entry(data, writer, entry, dictionary, limit, &corrupted).await?
+        } else {
+            let data = BufReader::new((&mut self.file).take(entry.compressed_len()));
+            decompress_entry(data, writer, entry, dictionary, limit, &corrupted).await?
+        };
+
+        PropErrno::from_io_result(writer.flush().await, None)?;
+        Ok(len)
+    }
+
+    /// the dictionary `entry` was compressed with, it is only read when no entry before used it
+    async fn dictionary(
+        &mut self,
+        group: &str,
+        entry: &ArchiveEntry,
+    ) -> PropErrnoResult<Arc<Vec<u8>>> {
+        if let Some(dictionary) = self.loaded.get(group) {
+            return Ok(Arc::clone(dictionary));
+        }
+
+        let stored = self
+            .dictionaries
+            .iter()
+            .find(|dictionary| dictionary.path() == group)
+            // a dictionary is stored as is, it can not have a dictionary itself
+            .filter(|dictionary| dictionary.dictionary().is_none())
+            // never bigger than the ones the trainer makes
+            .filter(|dictionary| dictionary.len() <= DICTIONARY_SIZE as u64)
+            .cloned()
+            .ok_or_else(|| {
+                log::error!(
+                    "{}: the dictionary of {} is missing or too big",
+                    self.path.to_string_lossy(),
+                    entry.path()
+                );
+                PropErrno::CorruptedFileVal(entry.path().to_string())
+            })?;
+        let mut dictionary = Vec::new();
+        let limit = DICTIONARY_SIZE as u64;
+        Box::pin(self.read_entry_at_most(&stored, &mut dictionary, limit)).await?;
+        let dictionary = Arc::new(dictionary);
+        self.loaded
+            .insert(group.to_string(), Arc::clone(&dictionary));
+        Ok(dictionary)
+    }
+
+    /// checks the mac of the index with the key of the entries, once.
+    /// an archive without a mac is only trusted when nothing in it is encrypted
+    /// and no key is given for it, the mac could have been cut off
+    async fn verify_index(&mut self) -> PropErrnoResult<()> {
+        if self.is_verified {
+            return Ok(());
+        }
+
+        let name = self.path.parent_and_current();
+        match self.mac.take() {
+            Some((index, mac)) => {
+                // SAFE because the mac starts with a header
+                let header = EncryptionHeader::from_bytes(mac[..HEADER_LEN].try_into().unwrap());
+                let key = match header {
+                    Ok(header) => self.key(&header, &name).await,
+                    Err(err) => Err(err),
+                };
+                let res = match key {
+                    Ok(key) if key.verify_mac(&index, &mac) => Ok(()),
+                    // a wrong password can't be told apart from a changed index either
+                    Ok(_) => {
+                        log::error!("{}: the index was changed", self.path.to_string_lossy());
+                        Err(PropErrno::InvalidPassOrCorruptVal(name))
+                    }
+                    Err(err) => Err(err),
+                };
+                if res.is_err() {
+                    // another password or identity might do
+                    self.mac = Some((index, mac));
+                    return res;
+                }
+            }
+            None => {
+                let is_encrypted = self
+                    .entries
+                    .iter()
+                    .chain(&self.dictionaries)
+                    .any(ArchiveEntry::is_encrypted);
+                let has_key = self.password.is_some()
+                    || !self.identities.is_empty()
+                    || self.wrapped_key.is_some();
+                if is_encrypted || has_key {
+                    log::error!(
+                        "{}: the index is not authenticated",
+                        self.path.to_string_lossy()
+                    );
+                    return Err(PropErrno::CorruptedFileVal(name));
+                }
+            }
+        }
+
+        self.is_verified = true;
+        Ok(())
+    }
+
+    /// the key of an encrypted stream, it is only derived when no stream before used it
+    /// # Arguments
+    /// * `name` - what the stream is, for the errors
+    async fn key(
+        &mut self,
+        header: &EncryptionHeader,
+        name: &str,
+    ) -> PropErrnoResult<EncryptionKey> {
+        if let Some(key) = self.keys.iter().find(|key| key.matches(header)) {
+            return Ok(key.clone());
+        }
+
+        let key = match &self.wrapped_key {
+            Some(wrapped) if header.params().is_none() => {
+                EncryptionKey::from_wrapped(wrapped, &self.identities).map_err(|_| {
+                    log::error!(
+                        "{}: none of the identities can open the key",
+                        self.path.to_string_lossy()
+                    );
+                    PropErrno::DecryptVal(name.to_string())
+                })?
+            }
+            _ => {
+                let password = self.password.as_deref().ok_or_else(|| {
+                    log::error!("{}: {} is encrypted", self.path.to_string_lossy(), name);
+                    PropErrno::InvalidPasswordVal(name.to_string())
+                })?;
+                EncryptionKey::from_header(password, header).await?
+            }
+        };
+        self.keys.push(key.clone());
+        Ok(key)
+    }
+
+    /// extracts every entry into `dst`
+    pub async fn unpack<P: AsRef<Path>>(&mut self, dst: P) -> PropErrnoResult<()> {
+        self.extract(dst, |_| true).await
+    }
+
+    /// extracts the entries `filter` keeps into `dst`
+    /// the parents of an extracted entry are created even if they are not kept.
+    /// an entry that would end up outside of `dst` stops the extraction
+    pub async fn extract<P, F>(&mut self, dst: P, filter: F) -> PropErrnoResult<()>
+    where
+        P: AsRef<Path>,
+        F: Fn(&ArchiveEntry) -> bool,
+    {
+        self.verify_index().await?;
+        let root = UnpackRoot::create(dst).await?;
+        let entries: Vec<ArchiveEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| filter(entry))
+            .cloned()
+            .collect();
+
+        root.unpack(self, &entries).await
+    }
+}
+
+#[async_trait]
+impl UnpackSource for ArchiveReader {
+    type Entry = ArchiveEntry;
+
+    async fn write_file(
+        &mut self,
+        entry: &ArchiveEntry,
+        _dst: &Path,
+        file: &mut File,
+    ) -> PropErrnoResult<()> {
+        self.read_entry(entry, file).await.map(|_| ())
+    }
+}
+
+/// decompresses the data of `entry` into `writer`, the bytes have to agree with the algorithm
+/// of the index.
+/// NOTE: a stored entry is read as is, the file it came from can be compressed data itself
+async fn decompress_entry<R, W, F>(
+    data: R,
+    writer: &mut W,
+    entry: &ArchiveEntry,
+    dictionary: Option<&[u8]>,
+    limit: u64,
+    corrupted: &F,
+) -> PropErrnoResult<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    F: Fn() -> PropErrno,
+{
+    if let Some(dictionary) = dictionary {
+        let reader = Decomprossor::from_dictionary(data, dictionary).map_err(|_| corrupted())?;
+        return decompress(reader, writer, entry, limit, corrupted).await;
+    }
+
+    let algorithm = entry.algorithm();
+    match algorithm.get_ext() {
+        Some(ext) => {
+            let reader = Decomprossor::detect(data, Some(OsStr::new(ext)))
+                .await
+                .map_err(|err| entry_err(err, entry, corrupted))?;
+            decompress(reader, writer, entry, limit, corrupted).await
+        }
+        None => {
+            let reader = Decomprossor::new(algorithm, data);
+            decompress(reader, writer, entry, limit, corrupted).await
+        }
+    }
+}
+
+/// the data fails to decompress when it was changed
+fn entry_err<F: Fn() -> PropErrno>(
+    err: PropErrno,
+    entry: &ArchiveEntry,
+    corrupted: &F,
+) -> PropErrno {
+    match err {
+        // only a wrong password fails the first chunk
+        PropErrno::InvalidPassOrCorrupt => {
+            PropErrno::InvalidPassOrCorruptVal(entry.path().to_string())
+        }
+        _ => corrupted(),
+    }
+}
+
+/// decompresses the data of `entry` into `writer` and checks its crc
+/// returns the number of bytes written
+/// # Arguments
+/// * `limit` - the most bytes the entry may decompress to, it stops reading past it
+async fn decompress<R, W, F>(
+    reader: Decomprossor<R>,
+    writer: &mut W,
+    entry: &ArchiveEntry,
+    limit: u64,
+    corrupted: &F,
+) -> PropErrnoResult<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    F: Fn() -> PropErrno,
+{
+    // one byte more tells a full entry from a bigger one
+    let mut reader = reader.take(limit.saturating_add(1));
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = vec![0; BUFFER_SIZE];
+    let mut len = 0;
+    loop {
+        let n = reader
+            .read(&mut buf)
+            .await
+            .map_err(|err| entry_err(PropErrno::from_io_error(&err, None), entry, corrupted))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        let write_res = writer.write_all(&buf[..n]).await;
+        PropErrno::from_io_result(write_res, None)?;
+        len += n as u64;
+        if len > limit {
+            return Err(corrupted());
+        }
+    }
+
+    // the encryption already authenticated the data, it has no crc
+    if !entry.is_encrypted() && (len != entry.len() || hasher.finalize() != entry.crc()) {
+        return Err(corrupted());
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tmp::tmp_dir;
+    use crate::{
+        archive::{ArchiveWriter, EntryKind},
+        compression::{algorithm::Algorithm, dictionary::Dictionaries, policy::CompressionPolicy},
+        encryption::KdfParams,
+    };
+
+    /// writes an archive by hand, `ArchiveWriter` refuses to write bad names.
+    /// it is a version 1 archive, they are still read
+    fn write_raw(path: &Path, entries: Vec<RawEntry>) {
+        let mut bytes = ARCHIVE_MAGIC.to_vec();
+        bytes.push(1);
+        let mut index = Vec::new();
+        for (mut entry, data) in entries {
+            let len = data.len() as u64;
+            let crc = crc32fast::hash(data);
+            entry.set_data(Algorithm::None, bytes.len() as u64, len, len, crc);
+            bytes.extend_from_slice(data);
+            index.push(entry);
+        }
+
+        let index_offset = bytes.len() as u64;
+        let index = serde_json::to_vec(&index).unwrap();
+        bytes.extend_from_slice(&index);
+        bytes.extend_from_slice(&index_offset.to_be_bytes());
+        bytes.extend_from_slice(&(index.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&crc32fast::hash(&index).to_be_bytes());
+        bytes.extend_from_slice(&ARCHIVE_MAGIC);
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    /// an archive of `src` with a file per algorithm
+    async fn write_archive(name: &str) -> (PathBuf, PathBuf) {
+        write_archive_with(name, |writer| writer).await
+    }
+
+    async fn write_archive_with(
+        name: &str,
+        encrypt: impl FnOnce(ArchiveWriter) -> ArchiveWriter,
+    ) -> (PathBuf, PathBuf) {
+        let dir = tmp_dir(name);
+        let src = dir.join("src");
+        std::fs::create_dir_all(src.join("docs")).unwrap();
+        let text = "the quick brown fox jumps over the lazy dog. ".repeat(512);
+        std::fs::write(src.join("docs/a.txt"), &text).unwrap();
+        std::fs::write(src.join("b.bin"), [7u8; 1000]).unwrap();
+        std::fs::write(src.join("empty"), []).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("docs/a.txt", src.join("link")).unwrap();
+
+        let path = dir.join("src.sppa");
+        let writer = ArchiveWriter::create(&path, CompressionPolicy::default())
+            .await
+            .unwrap();
+        let mut writer = encrypt(writer);
+        writer.add_dir(Path::new("src"), &src).await.unwrap();
+        writer
+            .add_dir(Path::new("src/docs"), &src.join("docs"))
+            .await
+            .unwrap();
+        writer
+            .add_file(
+                Path::new("src/docs/a.txt"),
+                &src.join("docs/a.txt"),
+                &Algorithm::Zstd,
+            )
+            .await
+            .unwrap();
+        writer
+            .add_file(Path::new("src/b.bin"), &src.join("b.bin"), &Algorithm::Xz)
+            .await
+            .unwrap();
+        writer
+            .add_file(Path::new("src/empty"), &src.join("empty"), &Algorithm::None)
+            .await
+            .unwrap();
+        #[cfg(unix)]
+        writer
+            .add_symlink(Path::new("src/link"), &src.join("link"))
+            .await
+            .unwrap();
+        writer.finish().await.unwrap();
+
+        (dir, src)
+    }
+
+    #[tokio::test]
+    async fn test_archive_roundtrip() {
+        let (dir, src) = write_archive("transfer_engine_archive").await;
+        let mut reader = ArchiveReader::open(dir.join("src.sppa")).await.unwrap();
+        let a = reader.find("src/docs/a.txt").unwrap().clone();
+        assert_eq!(a.algorithm(), Algorithm::Zstd);
+        assert!(a.compressed_len() < a.len());
+        assert_eq!(reader.find("src/b.bin").unwrap().algorithm(), Algorithm::Xz);
+
+        // partial extraction
+        let part = dir.join("part");
+        reader
+            .extract(&part, |entry| entry.is_under("src/docs"))
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(part.join("src/docs/a.txt")).unwrap(),
+            std::fs::read(src.join("docs/a.txt")).unwrap()
+        );
+        assert!(!part.join("src/b.bin").exists());
+
+        let all = dir.join("all");
+        reader.unpack(&all).await.unwrap();
+        for name in ["docs/a.txt", "b.bin", "empty"] {
+            assert_eq!(
+                std::fs::read(all.join("src").join(name)).unwrap(),
+                std::fs::read(src.join(name)).unwrap()
+            );
+        }
+        let modified = |path: PathBuf| std::fs::metadata(path).unwrap().modified().unwrap();
+        assert_eq!(
+            modified(all.join("src/b.bin"))
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            modified(src.join("b.bin"))
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            std::fs::read_link(all.join("src/link")).unwrap(),
+            PathBuf::from("docs/a.txt")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stored_compressed_file() {
+        let dir = tmp_dir("transfer_engine_archive_stored");
+        // looks like zstd but it is stored as is
+        let stored = [0x28, 0xB5, 0x2F, 0xFD, 1, 2, 3, 4, 5, 6];
+        std::fs::write(dir.join("file.zst"), stored).unwrap();
+        let path = dir.join("src.sppa");
+        let mut writer = ArchiveWriter::create(&path, CompressionPolicy::default())
+            .await
+            .unwrap();
+        writer
+            .add_file(
+                Path::new("file.zst"),
+                &dir.join("file.zst"),
+                &Algorithm::None,
+            )
+            .await
+            .unwrap();
+        writer.finish().await.unwrap();
+
+        let mut reader = ArchiveReader::open(&path).await.unwrap();
+        let entry = reader.find("file.zst").unwrap().clone();
+        let mut read = Vec::new();
+        reader.read_entry(&entry, &mut read).await.unwrap();
+        assert_eq!(read, stored);
+    }
+
+    #[tokio::test]
+    async fn test_unfinished_archive() {
+        let (dir, src) = write_archive("transfer_engine_archive_unfinished").await;
+        let path = dir.join("src.sppa");
+        let archive = std::fs::read(&path).unwrap();
+
+        // the old archive is only replaced once the new one is finished
+        let mut writer = ArchiveWriter::create(&path, CompressionPolicy::default())
+            .await
+            .unwrap();
+        writer
+            .add_file(Path::new("src/b.bin"), &src.join("b.bin"), &Algorithm::None)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), archive);
+        drop(writer);
+        assert_eq!(std::fs::read(&path).unwrap(), archive);
+
+        let names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names.len(), 2, "{:?}", names);
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_archive() {
+        let (dir, _) = write_archive("transfer_engine_archive_corrupted").await;
+        let path = dir.join("src.sppa");
+        let mut bytes = std::fs::read(&path).unwrap();
+
+        // a changed byte in the data of an entry
+        let offset = {
+            let reader = ArchiveReader::open(&path).await.unwrap();
+            reader.find("src/b.bin").unwrap().offset() as usize
+        };
+        bytes[offset + 20] ^= 0xFF;
+        std::fs::write(&path, &bytes).unwrap();
+        let mut reader = ArchiveReader::open(&path).await.unwrap();
+        let entry = reader.find("src/b.bin").unwrap().clone();
+        let res = reader.read_entry(&entry, &mut Vec::new()).await;
+        assert!(matches!(res, Err(PropErrno::CorruptedFileVal(_))));
+
+        // the writer never finished
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        let res = ArchiveReader::open(&path).await;
+        assert!(matches!(res, Err(PropErrno::UnpackVal(_))));
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_archive() {
+        let password = "correct horse battery";
+        let key = EncryptionKey::from_password_with(password, KdfParams::new(64, 1, 1))
+            .await
+            .unwrap();
+        let (dir, src) = write_archive_with("transfer_engine_archive_encrypted", |writer| {
+            writer.set_encryption(Some(key))
+        })
+        .await;
+        let path = dir.join("src.sppa");
+
+        // the names can be listed without the password, the data can't be read
+        let mut reader = ArchiveReader::open(&path).await.unwrap();
+        let entry = reader.find("src/docs/a.txt").unwrap().clone();
+        assert!(entry.is_encrypted());
+        let res = reader.read_entry(&entry, &mut Vec::new()).await;
+        assert!(matches!(res, Err(PropErrno::InvalidPasswordVal(_))));
+
+        let mut reader = reader.set_password(Some("wrong password".to_string()));
+        let res = reader.read_entry(&entry, &mut Vec::new()).await;
+        assert!(matches!(res, Err(PropErrno::InvalidPassOrCorruptVal(_))));
+
+        let mut reader = reader.set_password(Some(password.to_string()));
+        let all = dir.join("all");
+        reader.unpack(&all).await.unwrap();
+        for name in ["docs/a.txt", "b.bin", "empty"] {
+            assert_eq!(
+                std::fs::read(all.join("src").join(name)).unwrap(),
+                std::fs::read(src.join(name)).unwrap()
+            );
+        }
+        // derived once for the whole archive
+        assert_eq!(reader.keys.len(), 1);
+
+        // the entry is a single chunk, a change can't be told apart from a wrong password
+        let mut bytes = std::fs::read(&path).unwrap();
+        let entry = reader.find("src/b.bin").unwrap().clone();
+        bytes[(entry.offset() + entry.compressed_len()) as usize - 1] ^= 0xFF;
+        std::fs::write(&path, &bytes).unwrap();
+        let mut reader = ArchiveReader::open(&path)
+            .await
+            .unwrap()
+            .set_password(Some(password.to_string()));
+        let res = reader.read_entry(&entry, &mut Vec::new()).await;
+        assert!(matches!(res, Err(PropErrno::InvalidPassOrCorruptVal(_))));
+    }
+
+    /// changes the index the way someone without the key could, the crc is made again
+    /// and the mac is kept unless `keep_mac` is false
+    fn rewrite_index(path: &Path, keep_mac: bool, change: impl FnOnce(&mut String)) {
+        let bytes = std::fs::read(path).unwrap();
+        let trailer = &bytes[bytes.len() - TRAILER_LEN..];
+        let offset = u64::from_be_bytes(trailer[0..8].try_into().unwrap()) as usize;
+        let len = u64::from_be_bytes(trailer[8..16].try_into().unwrap()) as usize;
+        let mut index = String::from_utf8(bytes[offset..offset + len].to_vec()).unwrap();
+        change(&mut index);
+
+        let mut changed = bytes[..offset].to_vec();
+        changed.extend_from_slice(index.as_bytes());
+        if keep_mac {
+            changed.extend_from_slice(&bytes[offset + len..bytes.len() - TRAILER_LEN]);
+        }
+        changed.extend_from_slice(&(offset as u64).to_be_bytes());
+        changed.extend_from_slice(&(index.len() as u64).to_be_bytes());
+        changed.extend_from_slice(&crc32fast::hash(index.as_bytes()).to_be_bytes());
+        changed.extend_from_slice(&ARCHIVE_MAGIC);
+        std::fs::write(path, changed).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_index() {
+        let password = "correct horse battery";
+        let key = EncryptionKey::from_password_with(password, KdfParams::new(64, 1, 1))
+            .await
+            .unwrap();
+        let (dir, _) = write_archive_with("transfer_engine_archive_encrypted_index", |writer| {
+            writer.set_encryption(Some(key.clone()))
+        })
+        .await;
+        let path = dir.join("src.sppa");
+        let archive = std::fs::read(&path).unwrap();
+
+        // nothing about the data is given away
+        let reader = ArchiveReader::open(&path).await.unwrap();
+        let entry = reader.find("src/docs/a.txt").unwrap();
+        assert_eq!((entry.len(), entry.crc()), (0, 0));
+
+        type Change = Box<dyn FnOnce(&mut String)>;
+        let changes: Vec<Change> = vec![
+            // the data of a file under the name of another
+            Box::new(|index| {
+                *index = index
+                    .replace("\"src/docs/a.txt\"", "\"swapped\"")
+                    .replace("\"src/b.bin\"", "\"src/docs/a.txt\"")
+                    .replace("\"swapped\"", "\"src/b.bin\"");
+            }),
+            // the permissions of a file
+            Box::new(|index| {
+                let at = index.find("\"path\":\"src/b.bin\"").unwrap();
+                let mode_at = at + index[at..].find("\"mode\":").unwrap() + "\"mode\":".len();
+                let end = mode_at
+                    + index[mode_at..]
+                        .find(|c: char| !c.is_ascii_digit())
+                        .unwrap();
+                index.replace_range(mode_at..end, &0o4777.to_string());
+            }),
+            // where a link points
+            #[cfg(unix)]
+            Box::new(|index| {
+                *index = index.replace("{\"Symlink\":\"docs/a.txt\"}", "{\"Symlink\":\"/etc\"}");
+            }),
+        ];
+        for (i, change) in changes.into_iter().enumerate() {
+            std::fs::write(&path, &archive).unwrap();
+            rewrite_index(&path, true, |index| {
+                let before = index.clone();
+                change(index);
+                assert_ne!(*index, before, "change {} changed nothing", i);
+            });
+            let mut reader = ArchiveReader::open(&path)
+                .await
+                .unwrap()
+                .set_password(Some(password.to_string()));
+            let dst = dir.join(format!("changed{}", i));
+            let res = reader.unpack(&dst).await;
+            assert!(
+                matches!(res, Err(PropErrno::InvalidPassOrCorruptVal(_))),
+                "change {}: {:?}",
+                i,
+                res
+            );
+            assert!(!dst.exists());
+        }
+
+        // the index as it was is still fine
+        std::fs::write(&path, &archive).unwrap();
+        rewrite_index(&path, true, |_| {});
+        let mut reader = ArchiveReader::open(&path)
+            .await
+            .unwrap()
+            .set_password(Some(password.to_string()));
+        reader.unpack(dir.join("unchanged")).await.unwrap();
+
+        // without the mac nothing is trusted
+        std::fs::write(&path, &archive).unwrap();
+        rewrite_index(&path, false, |_| {});
+        let mut reader = ArchiveReader::open(&path)
+            .await
+            .unwrap()
+            .set_password(Some(password.to_string()));
+        let res = reader.unpack(dir.join("stripped")).await;
+        assert!(matches!(res, Err(PropErrno::CorruptedFileVal(_))));
+
+        // the data of two files of the same size swapped in the archive
+        std::fs::write(dir.join("x"), [1u8; 100]).unwrap();
+        std::fs::write(dir.join("y"), [2u8; 100]).unwrap();
+        let path = dir.join("same.sppa");
+        let mut writer = ArchiveWriter::create(&path, CompressionPolicy::default())
+            .await
+            .unwrap()
+            .set_encryption(Some(key));
+        for name in ["x", "y"] {
+            writer
+                .add_file(Path::new(name), &dir.join(name), &Algorithm::None)
+                .await
+                .unwrap();
+        }
+        let entries = writer.finish().await.unwrap();
+        let (x, y) = (&entries[0], &entries[1]);
+        assert_eq!(x.compressed_len(), y.compressed_len());
+        let mut bytes = std::fs::read(&path).unwrap();
+        let (x_at, y_at, len) = (
+            x.offset() as usize,
+            y.offset() as usize,
+            x.compressed_len() as usize,
+        );
+        let x_data = bytes[x_at..x_at + len].to_vec();
+        bytes.copy_within(y_at..y_at + len, x_at);
+        bytes[y_at..y_at + len].copy_from_slice(&x_data);
+        std::fs::write(&path, &bytes).unwrap();
+        let mut reader = ArchiveReader::open(&path)
+            .await
+            .unwrap()
+            .set_password(Some(password.to_string()));
+        let res = reader.read_entry(x, &mut Vec::new()).await;
+        assert!(matches!(res, Err(PropErrno::CorruptedFileVal(_))));
+    }
+
+    #[tokio::test]
+    async fn test_archive_to_recipients() {
+        let identity = Identity::generate();
+        let recipients = [identity.to_public()];
+        let (dir, src) = write_archive_with("transfer_engine_archive_recipients", |writer| {
+            writer.encrypt_to(&recipients).unwrap()
+        })
+        .await;
+        let path = dir.join("src.sppa");
+
+        let reader = ArchiveReader::open(&path).await.unwrap();
+        assert!(reader.has_recipients());
+        let entry = reader.find("src/docs/a.txt").unwrap().clone();
+        assert!(entry.is_encrypted());
+
+        // a password or somebody else's key can't open it
+        let mut reader = reader
+            .set_password(Some("correct horse battery".to_string()))
+            .set_identities(vec![Identity::generate()]);
+        let res = reader.read_entry(&entry, &mut Vec::new()).await;
+        assert!(matches!(res, Err(PropErrno::DecryptVal(_))));
+
+        let mut reader = reader.set_identities(vec![identity]);
+        let all = dir.join("all");
+        reader.unpack(&all).await.unwrap();
+        for name in ["docs/a.txt", "b.bin", "empty"] {
+            assert_eq!(
+                std::fs::read(all.join("src").join(name)).unwrap(),
+                std::fs::read(src.join(name)).unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_archive_dictionaries() {
+        use crate::compression::dictionary::DictionaryTrainer;
+
+        let dir = tmp_dir("transfer_engine_archive_dictionaries");
+        let src = dir.join("src");
+        std::fs::create_dir_all(&src).unwrap();
+        for i in 0..64 {
+            let user = format!(
+                r#"{{"id": {}, "name": "user-{}", "email": "user{}@example.com", "roles": ["reader"]}}"#,
+                i,
+                i * 7,
+                i * 13
+            );
+            std::fs::write(src.join(format!("{}.json", i)), user).unwrap();
+        }
+        let dictionaries = DictionaryTrainer::from_dir(&src, true).train();
+        assert_eq!(dictionaries.len(), 1);
+
+        let password = "correct horse battery";
+        let key = EncryptionKey::from_password_with(password, KdfParams::new(64, 1, 1))
+            .await
+            .unwrap();
+        let path = dir.join("src.sppa");
+        let mut writer = ArchiveWriter::create(&path, CompressionPolicy::default())
+            .await
+            .unwrap()
+            .set_encryption(Some(key));
+        writer.set_dictionaries(dictionaries).await.unwrap();
+        for i in 0..64 {
+            let name = format!("{}.json", i);
+            writer
+                .add_file(Path::new(&name), &src.join(&name), &Algorithm::Brotli)
+                .await
+                .unwrap();
+        }
+        writer.finish().await.unwrap();
+
+        let reader = ArchiveReader::open(&path).await.unwrap();
+        let entry = reader.find("7.json").unwrap();
+        assert_eq!(entry.dictionary(), Some("json"));
+        assert_eq!(entry.algorithm(), Algorithm::Zstd);
+        let mut reader = reader.set_password(Some(password.to_string()));
+        let all = dir.join("all");
+        reader.unpack(&all).await.unwrap();
+        for i in 0..64 {
+            let name = format!("{}.json", i);
+            assert_eq!(
+                std::fs::read(all.join(&name)).unwrap(),
+                std::fs::read(src.join(&name)).unwrap()
+            );
+        }
+        // read once for all the entries
+        assert_eq!(reader.loaded.len(), 1);
+
+        // the index points at a dictionary that is not there
+        let mut entry = reader.find("7.json").unwrap().clone();
+        entry.set_dictionary(Some("txt".to_string()));
+        let res = reader.read_entry(&entry, &mut Vec::new()).await;
+        assert!(matches!(res, Err(PropErrno::CorruptedFileVal(_))));
+    }
+
+    #[tokio::test]
+    async fn test_archive_dictionary_too_big() {
+        let dir = tmp_dir("transfer_engine_archive_dictionary_too_big");
+        let src = dir.join("user.json");
+        std::fs::write(&src, br#"{"id": 7, "name": "user-7"}"#).unwrap();
+
+        let mut dictionaries = Dictionaries::default();
+        dictionaries.insert("json".to_string(), vec![b'{'; DICTIONARY_SIZE + 1]);
+        let path = dir.join("src.sppa");
+        let mut writer = ArchiveWriter::create(&path, CompressionPolicy::default())
+            .await
+            .unwrap();
+        writer.set_dictionaries(dictionaries).await.unwrap();
+        writer
+            .add_file(Path::new("user.json"), &src, &Algorithm::Brotli)
+            .await
+            .unwrap();
+        writer.finish().await.unwrap();
+
+        let mut reader = ArchiveReader::open(&path).await.unwrap();
+        let entry = reader.find("user.json").unwrap().clone();
+        assert_eq!(entry.dictionary(), Some("json"));
+        let res = reader.read_entry(&entry, &mut Vec::new()).await;
+        assert!(matches!(res, Err(PropErrno::CorruptedFileVal(_))));
+
+        // the index lies about the length, the data is still cut at the limit
+        let stored = &mut reader.dictionaries[0];
+        let (algorithm, offset) = (stored.algorithm(), stored.offset());
+        stored.set_data(algorithm, offset, stored.compressed_len(), 1, stored.crc());
+        let res = reader.read_entry(&entry, &mut Vec::new()).await;
+        assert!(matches!(res, Err(PropErrno::CorruptedFileVal(_))));
+        assert!(reader.loaded.is_empty());
+    }
+
+    /// an entry and its data
+    type RawEntry = (ArchiveEntry, &'static [u8]);
+
+    /// the entries of an archive and the links already in the destination
+    type Case<'a> = (Vec<RawEntry>, Vec<(&'a str, &'a str)>);
+
+    fn file(name: &str) -> RawEntry {
+        (
+            ArchiveEntry::new(name.to_string(), EntryKind::File, None),
+            b"pwned",
+        )
+    }
+
+    fn link(name: &str, target: &str) -> RawEntry {
+        let kind = EntryKind::Symlink(target.to_string());
+        (ArchiveEntry::new(name.to_string(), kind, None), b"")
+    }
+
+    fn dir(name: &str) -> RawEntry {
+        (
+            ArchiveEntry::new(name.to_string(), EntryKind::Dir, None),
+            b"",
+        )
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_malicious_archives() {
+        let base = tmp_dir("transfer_engine_archive_malicious");
+        let outside = base.join("outside");
+        let pwned = outside.join("pwned");
+        let pwned_str = pwned.to_string_lossy().into_owned();
+        let outside_str = outside.to_string_lossy().into_owned();
+
+        let corpus: Vec<Case> = vec![
+            (vec![file("../outside/pwned")], vec![]),
+            (vec![file("a/../../outside/pwned")], vec![]),
+            (vec![file(&pwned_str)], vec![]),
+            (vec![link("link", &outside_str)], vec![]),
+            (vec![link("a/link", "../../outside")], vec![]),
+            // the link only escapes through another link
+            (
+                vec![
+                    dir("d"),
+                    link("d/up", ".."),
+                    link("x", "d/up/.."),
+                    file("x/outside/pwned"),
+                ],
+                vec![],
+            ),
+            // the link it escapes through comes after it
+            (
+                vec![link("a", "s/b/.."), dir("s"), link("s/b", "..")],
+                vec![],
+            ),
+            // every link that escapes is removed, dangling or not
+            (
+                vec![
+                    link("a", "s/b/.."),
+                    link("a2", "s/b/.."),
+                    link("a3", "s/b/../nothere"),
+                    dir("s"),
+                    link("s/b", ".."),
+                ],
+                vec![],
+            ),
+            // links left in the destination by someone else
+            (vec![file("out/pwned")], vec![("out", &outside_str)]),
+            (vec![file("victim")], vec![("victim", &pwned_str)]),
+        ];
+
+        for (i, (entries, existing)) in corpus.into_iter().enumerate() {
+            let _ = std::fs::remove_dir_all(&outside);
+            std::fs::create_dir_all(&outside).unwrap();
+            let dst = base.join(format!("dst{}", i));
+            std::fs::create_dir_all(&dst).unwrap();
+            for (name, target) in existing {
+                std::os::unix::fs::symlink(target, dst.join(name)).unwrap();
+            }
+
+            let path = base.join(format!("{}.sppa", i));
+            write_raw(&path, entries);
+            let mut reader = ArchiveReader::open(&path).await.unwrap();
+            let res = reader.unpack(&dst).await;
+            assert!(
+                matches!(res, Err(PropErrno::UnpackOutofDirVal(_))),
+                "case {}: {:?}",
+                i,
+                res
+            );
+            assert!(!pwned.exists(), "case {} wrote outside", i);
+            // no link out of the destination is left behind either
+            for entry in walkdir::WalkDir::new(&dst).min_depth(1) {
+                let entry = entry.unwrap();
+                if entry.path_is_symlink() && !existing_link(&dst, entry.path()) {
+                    let real = entry.path().canonicalize();
+                    assert!(
+                        real.is_ok_and(|real| real.starts_with(dst.canonicalize().unwrap())),
+                        "case {}: {} points outside",
+                        i,
+                        entry.path().display()
+                    );
+                }
+            }
+        }
+    }
+
+    /// the links the test put there itself
+    fn existing_link(dst: &Path, path: &Path) -> bool {
+        path == dst.join("out") || path == dst.join("victim")
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    io::Result as IOResult,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter},
+};
+
+use super::{
+    entry::{entry_name, ArchiveEntry, EntryKind},
+    ArchiveIndex, ARCHIVE_HEADER_LEN, ARCHIVE_MAGIC, ARCHIVE_VERSION, BUFFER_SIZE, TRAILER_LEN,
+};
+use crate::{
+    compression::{
+        algorithm::{Algorithm, WriteAlgorithm},
+        dictionary::{Dictionaries, MAX_SAMPLED_FILE_SIZE},
+        policy::CompressionPolicy,
+    },
+    encryption::{EncryptWriter, EncryptionKey, Recipient},
+    errnos::{PropErrno, PropErrnoResult},
+    path::PathExt,
+    shared::{
+        progress::ProgressProcessedFn,
+        throttle::{RateLimiter, ThrottledWriter},
+    },
+    transfer::MemoryBudget,
+    utils::strings::StringUtils,
+};
+
+type ArchiveFile = BufWriter<ThrottledWriter<File>>;
+
+/// Writes the entries of an archive one after the other
+/// NOTE: the archive is written next to `path` and only replaces it once `finish` is done,
+/// it is removed if the writer is dropped before
+pub struct ArchiveWriter {
+    path: PathBuf,
+    /// where the archive is written until it is finished
+    tmp: Option<PathBuf>,
+    writer: ArchiveFile,
+    /// bytes written to the archive so far
+    offset: u64,
+    index: ArchiveIndex,
+    policy: CompressionPolicy,
+    processed_cb: Option<ProgressProcessedFn>,
+    /// the files are encrypted after they are compressed, the index is only authenticated
+    encryption: Option<EncryptionKey>,
+    /// big files are only compressed on many threads when their blocks fit in it
+    memory_budget: Option<MemoryBudget>,
+    /// the small files of their groups are compressed with them
+    dictionaries: Dictionaries,
+}
+
+impl ArchiveWriter {
+    /// starts the archive, the file at `path` is only replaced by `finish`
+    pub async fn create<P: AsRef<Path>>(
+        path: P,
+        policy: CompressionPolicy,
+    ) -> PropErrnoResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}.tmp", String::random(8)));
+        let tmp = path.with_file_name(name);
+        let file = PropErrno::from_io_result(File::create(&tmp).await, Some(&path))?;
+        let file = ThrottledWriter::new(file, RateLimiter::default());
+        let mut writer = Self {
+            path,
+            tmp: Some(tmp),
+            writer: BufWriter::with_capacity(BUFFER_SIZE, file),
+            offset: ARCHIVE_HEADER_LEN as u64,
+            index: ArchiveIndex::default(),
+            policy,
+            processed_cb: None,
+            encryption: None,
+            memory_budget: None,
+            dictionaries: Dictionaries::default(),
+        };
+
+        let mut header = [0; ARCHIVE_HEADER_LEN];
+        header[..ARCHIVE_MAGIC.len()].copy_from_slice(&ARCHIVE_MAGIC);
+        header[ARCHIVE_MAGIC.len()] = ARCHIVE_VERSION;
+        let res = writer.writer.write_all(&header).await;
+        PropErrno::from_io_result(res, Some(&writer.path))?;
+        Ok(writer)
+    }
+
+    /// called with the bytes of the sources as they are read
+    pub fn set_processed_cb(mut self, processed_cb: Option<ProgressProcessedFn>) -> Self {
+        self.processed_cb = processed_cb;
+        self
+    }
+
+    /// the archive is written no faster than the limiter allows
+    pub fn set_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.writer.get_mut().set_limiter(limiter);
+        self
+    }
+
+    /// without it the files are compressed on as many threads as the policy says
+    pub fn set_memory_budget(mut self, memory_budget: Option<MemoryBudget>) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    /// encrypts the data of the files with the key, the names and the metadata stay readable
+    /// but the key authenticates them
+    pub fn set_encryption(mut self, key: Option<EncryptionKey>) -> Self {
+        self.encryption = key;
+        self.index.wrapped_key = None;
+        self
+    }
+
+    /// encrypts the data of the files with a random key that only the identities
+    /// of the recipients can open, the names and the metadata stay readable
+    /// but the key authenticates them
+    pub fn encrypt_to(mut self, recipients: &[Recipient]) -> PropErrnoResult<Self> {
+        let (key, wrapped) = EncryptionKey::for_recipients(recipients)?;
+        self.encryption = Some(key);
+        self.index.wrapped_key = Some(wrapped);
+        Ok(self)
+    }
+
+    /// writes the dictionaries into the archive, the small files added after this
+    /// are compressed with the dictionary of their group
+    /// NOTE: the dictionaries are made from the files, they are encrypted the same way
+    /// so the encryption has to be set first
+    pub async fn set_dictionaries(&mut self, dictionaries: Dictionaries) -> PropErrnoResult<()> {
+        let path = self.path.clone();
+        for (group, dictionary) in dictionaries.iter() {
+            let mut entry = ArchiveEntry::new(group.to_string(), EntryKind::File, None);
+            let reader = &mut &dictionary[..];
+            self.write_data(&mut entry, reader, &path, EntryCodec::Stored, None)
+                .await?;
+            self.index.dictionaries.push(entry);
+        }
+
+        self.dictionaries = dictionaries;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.index.entries
+    }
+
+    fn name<P: AsRef<Path>>(&self, name: P) -> PropErrnoResult<String> {
+        entry_name(name.as_ref()).ok_or_else(|| {
+            log::error!("{}: not a relative path", name.as_ref().to_string_lossy());
+            PropErrno::CompressVal(name.as_ref().parent_and_current())
+        })
+    }
+
+    /// # Arguments
+    /// * `name` - the path of the directory in the archive
+    /// * `src` - the directory its metadata is taken from
+    pub async fn add_dir<P: AsRef<Path>>(&mut self, name: P, src: P) -> PropErrnoResult<()> {
+        let name = self.name(name)?;
+        let meta = tokio::fs::metadata(src.as_ref()).await.ok();
+        self.index
+            .entries
+            .push(ArchiveEntry::new(name, EntryKind::Dir, meta.as_ref()));
+        Ok(())
+    }
+
+    /// the link itself is stored, not what it points to
+    pub async fn add_symlink<P: AsRef<Path>>(&mut self, name: P, src: P) -> PropErrnoResult<()> {
+        let name = self.name(name)?;
+        let target = PropErrno::from_io_result(
+            tokio::fs::read_link(src.as_ref()).await,
+            Some(src.as_ref()),
+        )?;
+        let meta = tokio::fs::symlink_metadata(src.as_ref()).await.ok();
+        let kind = EntryKind::Symlink(target.to_string_lossy().into_owned());
+        self.index
+            .entries
+            .push(ArchiveEntry::new(name, kind, meta.as_ref()));
+        Ok(())
+    }
+
+    /// compresses the file into the archive
+    /// # Arguments
+    /// * `name` - the path of the file in the archive
+    /// * `src` - the file to read
+    /// * `algorithm` - what to compress it with
+    pub async fn add_file<P: AsRef<Path>>(
+        &mut self,
+        name: P,
+        src: P,
+        algorithm: &Algorithm,
+    ) -> PropErrnoResult<()> {
+        let name = self.name(name)?;
+        let src = src.as_ref();
+        let mut reader = PropErrno::from_io_result(File::open(src).await, Some(src))?;
+        let meta = reader.metadata().await.ok();
+        let size = meta.as_ref().map_or(0, |meta| meta.len());
+        let dictionary = self
+            .dictionaries
+            .find(&name)
+            .filter(|_| algorithm.is_enabled() && size <= MAX_SAMPLED_FILE_SIZE)
+            .map(|(group, dictionary)| (group.to_string(), dictionary.to_vec()));
+        let mut entry = ArchiveEntry::new(name, EntryKind::File, meta.as_ref());
+
+        let (policy, _permit) = match &self.memory_budget {
+            Some(budget) => budget.reserve_compression(&self.policy, algorithm, size),
+            None => (self.policy.clone(), None),
+        };
+        let codec = match &dictionary {
+            Some((_, dictionary)) => EntryCodec::Dictionary(dictionary, &policy),
+            None => EntryCodec::Policy(*algorithm, &policy, size),
+        };
+
+        let processed_cb = self.processed_cb.clone();
+        self.write_data(&mut entry, &mut reader, src, codec, processed_cb)
+            .await?;
+        entry.set_dictionary(dictionary.map(|(group, _)| group));
+        self.index.entries.push(entry);
+        Ok(())
+    }
+
+    /// compresses and encrypts what is read from `src` into the archive
+    /// and sets where the data of `entry` is
+    async fn write_data<R: AsyncRead + Unpin>(
+        &mut self,
+        entry: &mut ArchiveEntry,
+        reader: &mut R,
+        src: &Path,
+        codec: EntryCodec<'_>,
+        processed_cb: Option<ProgressProcessedFn>,
+    ) -> PropErrnoResult<()> {
+        let offset = self.offset;
+        let counter = CountingWriter {
+            writer: &mut self.writer,
+            written: &mut self.offset,
+        };
+        let compress_err = |err: std::io::Error| {
+            log::error!("{}: {}", src.to_string_lossy(), err);
+            PropErrno::CompressVal(src.parent_and_current())
+        };
+        let copy = EntryCopy {
+            src,
+            dst: &self.path,
+            processed_cb: &processed_cb,
+        };
+        let (len, crc, nonce) = match &self.encryption {
+            Some(key) => {
+                let writer = EncryptWriter::new(counter, key);
+                let nonce = writer.nonce();
+                let encoder = codec.encoder(writer).map_err(compress_err)?;
+                copy.compress(reader, encoder).await?;
+                (0, 0, Some(nonce))
+            }
+            None => {
+                let encoder = codec.encoder(counter).map_err(compress_err)?;
+                let (len, crc) = copy.compress(reader, encoder).await?;
+                (len, crc, None)
+            }
+        };
+
+        entry.set_data(codec.algorithm(), offset, self.offset - offset, len, crc);
+        entry.set_nonce(nonce);
+        Ok(())
+    }
+
+    /// writes the index and the trailer and puts the archive in place of the file at `path`,
+    /// the archive can be read after this
+    pub async fn finish(mut self) -> PropErrnoResult<Vec<ArchiveEntry>> {
+        let index = serde_json::to_vec(&self.index).map_err(|err| {
+            log::error!("{}: {}", self.path.to_string_lossy(), err);
+            PropErrno::CompressVal(self.path.parent_and_current())
+        })?;
+
+        let mac = match &self.encryption {
+            Some(key) => key.mac(&index)?.to_vec(),
+            None => Vec::new(),
+        };
+
+        let mut trailer = Vec::with_capacity(index.len() + mac.len() + TRAILER_LEN);
+        trailer.extend_from_slice(&index);
+        trailer.extend_from_slice(&mac);
+        trailer.extend_from_slice(&self.offset.to_be_bytes());
+        trailer.extend_from_slice(&(index.len() as u64).to_be_bytes());
+        trailer.extend_from_slice(&crc32fast::hash(&index).to_be_bytes());
+        trailer.extend_from_slice(&ARCHIVE_MAGIC);
+
+        let tmp = self.tmp.clone().unwrap_or_default();
+        let res = async {
+            self.writer.write_all(&trailer).await?;
+            self.writer.flush().await?;
+            self.writer.get_ref().get_ref().sync_all().await?;
+            tokio::fs::rename(&tmp, &self.path).await
+        };
+        PropErrno::from_io_result(res.await, Some(&self.path))?;
+        self.tmp = None;
+        Ok(std::mem::take(&mut self.index.entries))
+    }
+}
+
+impl Drop for ArchiveWriter {
+    /// an archive that was not finished never replaces anything
+    fn drop(&mut self) {
+        if let Some(tmp) = self.tmp.take() {
+            let _ = std::fs::remove_file(tmp);
+        }
+    }
+}
+
+/// What the data of an entry is compressed with
+enum EntryCodec<'a> {
+    /// as is
+    Stored,
+    /// with the levels of the policy, the size of the file decides on the threads
+    Policy(Algorithm, &'a CompressionPolicy, u64),
+    /// zstd with the dictionary of the group of the file
+    Dictionary(&'a [u8], &'a CompressionPolicy),
+}
+
+impl EntryCodec<'_> {
+    fn algorithm(&self) -> Algorithm {
+        match self {
+            Self::Stored => Algorithm::None,
+            Self::Policy(algorithm, _, _) => *algorithm,
+            // the dictionaries are made for zstd
+            Self::Dictionary(_, _) => Algorithm::Zstd,
+        }
+    }
+
+    fn encoder<W: AsyncWrite + Unpin>(&self, writer: W) -> IOResult<WriteAlgorithm<W>> {
+        match *self {
+            Self::Stored => Ok(WriteAlgorithm::None(writer)),
+            Self::Policy(algorithm, policy, size) => {
+                WriteAlgorithm::from_policy(&algorithm, writer, policy, size)
+            }
+            Self::Dictionary(dictionary, policy) => {
+                WriteAlgorithm::from_dictionary(writer, policy, dictionary)
+            }
+        }
+    }
+}
+
+/// Where the data of a file entry comes from and goes to
+struct EntryCopy<'a> {
+    src: &'a Path,
+    dst: &'a Path,
+    processed_cb: &'a Option<ProgressProcessedFn>,
+}
+
+impl EntryCopy<'_> {
+    /// returns the length and the crc32 of what was read
+    async fn compress<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+        &self,
+        reader: &mut R,
+        mut encoder: WriteAlgorithm<W>,
+    ) -> PropErrnoResult<(u64, u32)> {
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buf = vec![0; BUFFER_SIZE];
+        let mut len = 0;
+        loop {
+            let n = PropErrno::from_io_result(reader.read(&mut buf).await, Some(self.src))?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            PropErrno::from_io_result(encoder.write_all(&buf[..n]).await, Some(self.dst))?;
+            len += n as u64;
+            if let Some(processed_cb) = self.processed_cb {
+                processed_cb(n as u64);
+            }
+        }
+        PropErrno::from_io_result(encoder.shutdown().await, Some(self.dst))?;
+        Ok((len, hasher.finalize()))
+    }
+}
+
+/// Counts the compressed bytes of an entry
+/// shutting it down only flushes, the archive goes on after the entry
+struct CountingWriter<'a> {
+    writer: &'a mut ArchiveFile,
+    written: &'a mut u64,
+}
+
+impl AsyncWrite for CountingWriter<'_> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IOResult<usize>> {
+        let this = self.get_mut();
+        let n = ready!(Pin::new(&mut *this.writer).poll_write(cx, buf))?;
+        *this.written += n as u64;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        Pin::new(&mut *self.get_mut().writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        self.poll_flush(cx)
+    }
+}
@@ -1,46 +1,54 @@
-use crate::shared::performance::Performance;
+use crate::{
+    compression::{
+        parallel::{FrameCodec, ParallelEncoder},
+        policy::CompressionPolicy,
+        xz::XzWindowEncoder,
+    },
+    shared::performance::Performance,
+};
 
 use async_compression::{
     tokio::{
-        bufread::{BrotliDecoder, BzDecoder, XzDecoder, ZstdDecoder},
-        write::{BrotliEncoder, BzEncoder, XzEncoder, ZstdEncoder},
+        bufread::{BrotliDecoder, BzDecoder, GzipDecoder, Lz4Decoder, XzDecoder, ZstdDecoder},
+        write::{BrotliEncoder, BzEncoder, GzipEncoder, Lz4Encoder, XzEncoder, ZstdEncoder},
     },
+    zstd::CParameter,
     Level,
 };
 use mime::Mime;
 use mime_guess::from_path;
+use serde::{Deserialize, Serialize};
 use std::{
     ffi::OsStr,
     io::Result as IOResult,
     path::Path,
     pin::Pin,
-    task::{ready, Context, Poll},
+    task::{Context, Poll},
 };
-use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, BufReader, ReadBuf};
+use tokio::io::{AsyncRead, AsyncWrite, BufReader, ReadBuf};
 
+// a fast transfer spends as little time as possible compressing
 impl From<Performance> for Level {
     fn from(perf: Performance) -> Self {
         match perf {
-            Performance::Fast => Level::Best,
+            Performance::Fast => Level::Fastest,
             Performance::Average => Level::Default,
-            Performance::Slow => Level::Fastest,
+            Performance::Slow => Level::Best,
         }
     }
 }
 
 impl From<&Performance> for Level {
     fn from(perf: &Performance) -> Self {
-        match perf {
-            Performance::Fast => Level::Best,
-            Performance::Average => Level::Default,
-            Performance::Slow => Level::Fastest,
-        }
+        Level::from(*perf)
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Default)]
 pub enum Algorithm {
     /// no compression
+    #[default]
     None,
     /// good for large files;
     /// high speed but moderate compression, use this for anything over 256MB to 1.5GB
@@ -54,6 +62,11 @@ pub enum Algorithm {
     /// good general purpose compression; high speed but moderate compression
     /// this is the default compression
     Zstd,
+    /// very high speed but low compression, fast enough to keep up with an SSD
+    /// used for big files when the performance is set to Slow
+    Lz4,
+    /// moderate speed and compression, readable by pretty much any tool
+    Gzip,
 }
 
 // Following file types are not compressed we will use Brotli for them
@@ -61,11 +74,10 @@ pub enum Algorithm {
 // all microsoft office files - application/vnd.*
 // all pdf files - application/pdf
 // .tar, .iso, .svg, .wasm, .js, .json, .xml
-pub const BROTLI_FORMATS: [&'static str; 9] = [
+pub const BROTLI_FORMATS: [&str; 8] = [
     "text/",
     "application/vnd.",
     "application/pdf",
-    "application/x-tar",
     "application/x-iso9660-image",
     "image/svg+xml",
     "application/javascript",
@@ -73,24 +85,34 @@ pub const BROTLI_FORMATS: [&'static str; 9] = [
     "application/xml",
 ];
 
-pub const BZ_EXT: &'static str = "bz";
-pub const BZ_PARTED_EXT: &'static str = "bz0";
-pub const XZ_EXT: &'static str = "xz";
-pub const XZ_PARTED_EXT: &'static str = "xz0";
-pub const ZST_EXT: &'static str = "zst";
-pub const ZST_PARTED_EXT: &'static str = "zst0";
-pub const BR_EXT: &'static str = "br";
-pub const BR_PARTED_EXT: &'static str = "br0";
-pub const NONE_PARTED_EXT: &'static str = "0";
-
-/// These are all the possible extentions that can be used for the following compression algorithms
-/// if the extension is any of them it means that the file was split into multiple parts
-/// bz0, xz0, 0, zst0, br0
-// pub static ref SPLIT_EXT: [&'static OsStr; 5] = [
+// Following file types are compressed with gzip so the copies open with the usual tools
+// .tar -> .tar.gz
+pub const GZIP_FORMATS: [&str; 1] = ["application/x-tar"];
+
+pub const BZ_EXT: &str = "bz";
+pub const BZ_PARTED_EXT: &str = "bz0";
+pub const XZ_EXT: &str = "xz";
+pub const XZ_PARTED_EXT: &str = "xz0";
+pub const ZST_EXT: &str = "zst";
+pub const ZST_PARTED_EXT: &str = "zst0";
+pub const BR_EXT: &str = "br";
+pub const BR_PARTED_EXT: &str = "br0";
+pub const LZ4_EXT: &str = "lz4";
+pub const LZ4_PARTED_EXT: &str = "lz40";
+pub const GZ_EXT: &str = "gz";
+pub const GZ_PARTED_EXT: &str = "gz0";
+pub const NONE_PARTED_EXT: &str = "0";
+
+// These are all the possible extentions that can be used for the following compression algorithms
+// if the extension is any of them it means that the file was split into multiple parts
+// bz0, xz0, 0, zst0, br0, lz40, gz0
+// pub static ref SPLIT_EXT: [&'static OsStr; 7] = [
 //     OsStr::new(BZ_PARTED_EXT),
 //     OsStr::new(XZ_PARTED_EXT),
 //     OsStr::new(ZST_PARTED_EXT),
 //     OsStr::new(BR_PARTED_EXT),
+//     OsStr::new(LZ4_PARTED_EXT),
+//     OsStr::new(GZ_PARTED_EXT),
 //     OsStr::new(NONE_PARTED_EXT),
 // ];
 
@@ -125,6 +147,14 @@ impl Algorithm {
             return Some(Self::Brotli);
         }
 
+        if ext == OsStr::new(LZ4_EXT) || ext == OsStr::new(LZ4_PARTED_EXT) {
+            return Some(Self::Lz4);
+        }
+
+        if ext == OsStr::new(GZ_EXT) || ext == OsStr::new(GZ_PARTED_EXT) {
+            return Some(Self::Gzip);
+        }
+
         if ext == OsStr::new(NONE_PARTED_EXT) {
             return Some(Self::None);
         }
@@ -135,6 +165,13 @@ impl Algorithm {
     /// This function returnst the compression algorithm to use based on the file size, mime type and performance
     /// Note: the logic behind this function might change in the future
     pub fn from_info(size: &u64, mime: &Mime, ext: Option<&OsStr>, perf: &Performance) -> Self {
+        if GZIP_FORMATS
+            .iter()
+            .any(|&t| mime.essence_str().starts_with(t))
+        {
+            return Self::Gzip;
+        }
+
         // if mime type is in BROTLI_FORMATS, then use Brotli regardless of size and performance
         // because if considerablly faster than Zstd
         if BROTLI_FORMATS
@@ -155,12 +192,14 @@ impl Algorithm {
             return Self::Zstd;
         }
 
-        // if size is greater than 1.5GB, then use Xz if performance is Fast
-        if *size > XZ_SIZE_MIN_THRESHOLD && perf == &Performance::Fast {
-            return Self::Xz;
+        // same as the levels, Fast spends as little time as possible compressing
+        // and Slow gets the smallest files
+        match perf {
+            Performance::Fast => Self::Lz4,
+            // if size is greater than 1.5GB, then use Xz if performance is Slow
+            Performance::Slow if *size > XZ_SIZE_MIN_THRESHOLD => Self::Xz,
+            _ => Self::Bzip2,
         }
-
-        return Self::Bzip2;
     }
 
     pub fn from_size(size: &u64) -> Self {
@@ -176,6 +215,13 @@ impl Algorithm {
     }
 
     pub fn from_mime(mime: &Mime) -> Option<Self> {
+        if GZIP_FORMATS
+            .iter()
+            .any(|&t| mime.essence_str().starts_with(t))
+        {
+            return Some(Self::Gzip);
+        }
+
         if BROTLI_FORMATS
             .iter()
             .any(|&t| mime.essence_str().starts_with(t))
@@ -208,10 +254,7 @@ impl Algorithm {
 
     /// returns if the compression is enabled
     pub fn is_enabled(&self) -> bool {
-        match self {
-            Self::None => false,
-            _ => true,
-        }
+        !matches!(self, Self::None)
     }
 
     pub fn get_ext(&self) -> Option<&str> {
@@ -221,16 +264,13 @@ impl Algorithm {
             Self::Xz => Some(XZ_EXT),
             Self::Brotli => Some(BR_EXT),
             Self::Zstd => Some(ZST_EXT),
+            Self::Lz4 => Some(LZ4_EXT),
+            Self::Gzip => Some(GZ_EXT),
         }
     }
 }
 
-impl Default for Algorithm {
-    fn default() -> Self {
-        Self::None
-    }
-}
-
+#[allow(clippy::large_enum_variant)]
 pub enum WriteAlgorithm<W: AsyncWrite> {
     /// no compression
     None(W),
@@ -241,11 +281,19 @@ pub enum WriteAlgorithm<W: AsyncWrite> {
     /// good for large files;
     /// slow speed but good compression, use this for anything over 1.5GB
     Xz(XzEncoder<W>),
+    /// same as Xz with the window size set by the policy
+    XzWindow(XzWindowEncoder<W>),
+    /// Zstd or Xz compressed in blocks on many threads
+    Parallel(ParallelEncoder<W>),
     /// good for text files;
     Brotli(BrotliEncoder<W>),
     /// good general purpose compression; high speed but moderate compression
     /// this is the default compression
     Zstd(ZstdEncoder<W>),
+    /// very high speed but low compression
+    Lz4(Lz4Encoder<W>),
+    /// moderate speed and compression, readable by pretty much any tool
+    Gzip(GzipEncoder<W>),
 }
 
 impl<W: AsyncWrite> From<WriteAlgorithm<W>> for Algorithm {
@@ -253,9 +301,12 @@ impl<W: AsyncWrite> From<WriteAlgorithm<W>> for Algorithm {
         match algo {
             WriteAlgorithm::None(_) => Self::None,
             WriteAlgorithm::Bzip2(_) => Self::Bzip2,
-            WriteAlgorithm::Xz(_) => Self::Xz,
+            WriteAlgorithm::Xz(_) | WriteAlgorithm::XzWindow(_) => Self::Xz,
+            WriteAlgorithm::Parallel(w) => w.algorithm(),
             WriteAlgorithm::Brotli(_) => Self::Brotli,
             WriteAlgorithm::Zstd(_) => Self::Zstd,
+            WriteAlgorithm::Lz4(_) => Self::Lz4,
+            WriteAlgorithm::Gzip(_) => Self::Gzip,
         }
     }
 }
@@ -265,9 +316,12 @@ impl<W: AsyncWrite> From<&WriteAlgorithm<W>> for Algorithm {
         match algo {
             WriteAlgorithm::None(_) => Self::None,
             WriteAlgorithm::Bzip2(_) => Self::Bzip2,
-            WriteAlgorithm::Xz(_) => Self::Xz,
+            WriteAlgorithm::Xz(_) | WriteAlgorithm::XzWindow(_) => Self::Xz,
+            WriteAlgorithm::Parallel(w) => w.algorithm(),
             WriteAlgorithm::Brotli(_) => Self::Brotli,
             WriteAlgorithm::Zstd(_) => Self::Zstd,
+            WriteAlgorithm::Lz4(_) => Self::Lz4,
+            WriteAlgorithm::Gzip(_) => Self::Gzip,
         }
     }
 }
@@ -280,8 +334,67 @@ impl<W: AsyncWrite + Unpin> WriteAlgorithm<W> {
             Algorithm::Xz => Self::Xz(XzEncoder::with_quality(writer, perf.into())),
             Algorithm::Brotli => Self::Brotli(BrotliEncoder::with_quality(writer, perf.into())),
             Algorithm::Zstd => Self::Zstd(ZstdEncoder::with_quality(writer, perf.into())),
+            Algorithm::Lz4 => Self::Lz4(Lz4Encoder::with_quality(writer, perf.into())),
+            Algorithm::Gzip => Self::Gzip(GzipEncoder::with_quality(writer, perf.into())),
         }
     }
+
+    /// same as `from_algorithm` with the levels and options of the policy
+    /// # Arguments
+    /// * `size` - bytes about to be compressed, only big inputs are compressed on many threads
+    pub fn from_policy(
+        algo: &Algorithm,
+        writer: W,
+        policy: &CompressionPolicy,
+        size: u64,
+    ) -> IOResult<Self> {
+        if let (Some(threads), Some(codec)) = (
+            policy.parallel_threads(algo, size),
+            FrameCodec::from_policy(algo, policy),
+        ) {
+            return Ok(Self::Parallel(ParallelEncoder::new(writer, codec, threads)));
+        }
+
+        let level = policy.level(algo);
+        let encoder = match algo {
+            Algorithm::Zstd => {
+                let mut params = vec![CParameter::enable_long_distance_matching(
+                    policy.zstd_long_distance(),
+                )];
+                if let Some(window_log) = policy.zstd_window_log() {
+                    params.push(CParameter::window_log(window_log));
+                }
+                Self::Zstd(ZstdEncoder::with_quality_and_params(writer, level, &params))
+            }
+            Algorithm::Xz => match policy.xz_window_log() {
+                Some(window_log) => Self::XzWindow(XzWindowEncoder::new(
+                    writer,
+                    policy.xz_preset(),
+                    window_log,
+                )?),
+                None => Self::Xz(XzEncoder::with_quality(writer, level)),
+            },
+            Algorithm::None => Self::None(writer),
+            Algorithm::Bzip2 => Self::Bzip2(BzEncoder::with_quality(writer, level)),
+            Algorithm::Brotli => Self::Brotli(BrotliEncoder::with_quality(writer, level)),
+            Algorithm::Lz4 => Self::Lz4(Lz4Encoder::with_quality(writer, level)),
+            Algorithm::Gzip => Self::Gzip(GzipEncoder::with_quality(writer, level)),
+        };
+
+        Ok(encoder)
+    }
+
+    /// zstd with a dictionary trained on files like this one, at the zstd level of the policy
+    pub fn from_dictionary(
+        writer: W,
+        policy: &CompressionPolicy,
+        dictionary: &[u8],
+    ) -> IOResult<Self> {
+        let level = policy.level(&Algorithm::Zstd);
+        Ok(Self::Zstd(ZstdEncoder::with_dict(
+            writer, level, dictionary,
+        )?))
+    }
 }
 
 impl<W: AsyncWrite + Unpin> AsyncWrite for WriteAlgorithm<W> {
@@ -290,8 +403,12 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for WriteAlgorithm<W> {
             Self::None(w) => Pin::new(w).poll_write(cx, buf),
             Self::Bzip2(w) => Pin::new(w).poll_write(cx, buf),
             Self::Xz(w) => Pin::new(w).poll_write(cx, buf),
+            Self::XzWindow(w) => Pin::new(w).poll_write(cx, buf),
+            Self::Parallel(w) => Pin::new(w).poll_write(cx, buf),
             Self::Brotli(w) => Pin::new(w).poll_write(cx, buf),
             Self::Zstd(w) => Pin::new(w).poll_write(cx, buf),
+            Self::Lz4(w) => Pin::new(w).poll_write(cx, buf),
+            Self::Gzip(w) => Pin::new(w).poll_write(cx, buf),
         }
     }
 
@@ -300,8 +417,12 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for WriteAlgorithm<W> {
             Self::None(w) => Pin::new(w).poll_flush(cx),
             Self::Bzip2(w) => Pin::new(w).poll_flush(cx),
             Self::Xz(w) => Pin::new(w).poll_flush(cx),
+            Self::XzWindow(w) => Pin::new(w).poll_flush(cx),
+            Self::Parallel(w) => Pin::new(w).poll_flush(cx),
             Self::Brotli(w) => Pin::new(w).poll_flush(cx),
             Self::Zstd(w) => Pin::new(w).poll_flush(cx),
+            Self::Lz4(w) => Pin::new(w).poll_flush(cx),
+            Self::Gzip(w) => Pin::new(w).poll_flush(cx),
         }
     }
 
@@ -310,8 +431,12 @@ impl<W: AsyncWrite + Unpin> AsyncWrite for WriteAlgorithm<W> {
             Self::None(w) => Pin::new(w).poll_shutdown(cx),
             Self::Bzip2(w) => Pin::new(w).poll_shutdown(cx),
             Self::Xz(w) => Pin::new(w).poll_shutdown(cx),
+            Self::XzWindow(w) => Pin::new(w).poll_shutdown(cx),
+            Self::Parallel(w) => Pin::new(w).poll_shutdown(cx),
             Self::Brotli(w) => Pin::new(w).poll_shutdown(cx),
             Self::Zstd(w) => Pin::new(w).poll_shutdown(cx),
+            Self::Lz4(w) => Pin::new(w).poll_shutdown(cx),
+            Self::Gzip(w) => Pin::new(w).poll_shutdown(cx),
         }
     }
 }
@@ -331,6 +456,10 @@ pub enum ReadAlgorithm<R: AsyncRead> {
     /// good general purpose compression; high speed but moderate compression
     /// this is the default compression
     Zstd(ZstdDecoder<BufReader<R>>),
+    /// very high speed but low compression
+    Lz4(Lz4Decoder<BufReader<R>>),
+    /// moderate speed and compression, readable by pretty much any tool
+    Gzip(GzipDecoder<BufReader<R>>),
 }
 
 impl<R: AsyncRead> From<ReadAlgorithm<R>> for Algorithm {
@@ -341,6 +470,8 @@ impl<R: AsyncRead> From<ReadAlgorithm<R>> for Algorithm {
             ReadAlgorithm::Xz(_) => Self::Xz,
             ReadAlgorithm::Brotli(_) => Self::Brotli,
             ReadAlgorithm::Zstd(_) => Self::Zstd,
+            ReadAlgorithm::Lz4(_) => Self::Lz4,
+            ReadAlgorithm::Gzip(_) => Self::Gzip,
         }
     }
 }
@@ -353,6 +484,8 @@ impl<R: AsyncRead> From<&ReadAlgorithm<R>> for Algorithm {
             ReadAlgorithm::Xz(_) => Self::Xz,
             ReadAlgorithm::Brotli(_) => Self::Brotli,
             ReadAlgorithm::Zstd(_) => Self::Zstd,
+            ReadAlgorithm::Lz4(_) => Self::Lz4,
+            ReadAlgorithm::Gzip(_) => Self::Gzip,
         }
     }
 }
@@ -362,11 +495,29 @@ impl<R: AsyncRead + Unpin> ReadAlgorithm<R> {
         match algo {
             Algorithm::None => Self::None(reader),
             Algorithm::Bzip2 => Self::Bzip2(BzDecoder::new(BufReader::new(reader))),
-            Algorithm::Xz => Self::Xz(XzDecoder::new(BufReader::new(reader))),
+            Algorithm::Xz => {
+                // the parallel encoder writes one stream per block
+                let mut decoder = XzDecoder::new(BufReader::new(reader));
+                decoder.multiple_members(true);
+                Self::Xz(decoder)
+            }
             Algorithm::Brotli => Self::Brotli(BrotliDecoder::new(BufReader::new(reader))),
-            Algorithm::Zstd => Self::Zstd(ZstdDecoder::new(BufReader::new(reader))),
+            Algorithm::Zstd => {
+                let mut decoder = ZstdDecoder::new(BufReader::new(reader));
+                decoder.multiple_members(true);
+                Self::Zstd(decoder)
+            }
+            Algorithm::Lz4 => Self::Lz4(Lz4Decoder::new(BufReader::new(reader))),
+            Algorithm::Gzip => Self::Gzip(GzipDecoder::new(BufReader::new(reader))),
         }
     }
+
+    /// the zstd stream has to be read with the dictionary it was written with
+    pub fn from_dictionary(reader: R, dictionary: &[u8]) -> IOResult<Self> {
+        let mut decoder = ZstdDecoder::with_dict(BufReader::new(reader), dictionary)?;
+        decoder.multiple_members(true);
+        Ok(Self::Zstd(decoder))
+    }
 }
 
 impl<R: AsyncRead + Unpin> AsyncRead for ReadAlgorithm<R> {
@@ -381,6 +532,8 @@ impl<R: AsyncRead + Unpin> AsyncRead for ReadAlgorithm<R> {
             Self::Xz(r) => Pin::new(r).poll_read(cx, buf),
             Self::Brotli(r) => Pin::new(r).poll_read(cx, buf),
             Self::Zstd(r) => Pin::new(r).poll_read(cx, buf),
+            Self::Lz4(r) => Pin::new(r).poll_read(cx, buf),
+            Self::Gzip(r) => Pin::new(r).poll_read(cx, buf),
         }
     }
 }
@@ -421,4 +574,97 @@ mod test {
                 .starts_with("text/plain")
         );
     }
+
+    #[tokio::test]
+    async fn test_lz4_and_gzip_roundtrip() {
+        use super::*;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let data: Vec<u8> = (0..64 * 1024).map(|i| (i % 13) as u8).collect();
+        for algo in [Algorithm::Lz4, Algorithm::Gzip] {
+            let mut writer =
+                WriteAlgorithm::from_algorithm(&algo, Vec::new(), &Performance::Average);
+            writer.write_all(&data).await.unwrap();
+            writer.shutdown().await.unwrap();
+            let compressed = match writer {
+                WriteAlgorithm::Lz4(w) => w.into_inner(),
+                WriteAlgorithm::Gzip(w) => w.into_inner(),
+                _ => unreachable!(),
+            };
+            assert!(compressed.len() < data.len());
+
+            let mut reader = ReadAlgorithm::from_algorithm(&algo, compressed.as_slice());
+            let mut decompressed = Vec::new();
+            reader.read_to_end(&mut decompressed).await.unwrap();
+            assert_eq!(decompressed, data);
+            assert_eq!(
+                Algorithm::from_ext(OsStr::new(algo.get_ext().unwrap())),
+                Some(algo)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_policy_roundtrip() {
+        use super::*;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let data: Vec<u8> = (0..256 * 1024).map(|i| (i % 251) as u8).collect();
+        let policy = CompressionPolicy::default()
+            .set_speed_vs_size(Some(30))
+            .set_zstd_long_distance(true)
+            .set_zstd_window_log(Some(20))
+            .set_xz_window_log(Some(16))
+            // too small to be split over them
+            .set_threads(Some(4));
+        for algo in [Algorithm::Zstd, Algorithm::Xz] {
+            let mut writer =
+                WriteAlgorithm::from_policy(&algo, Vec::new(), &policy, data.len() as u64).unwrap();
+            writer.write_all(&data).await.unwrap();
+            writer.shutdown().await.unwrap();
+            let compressed = match writer {
+                WriteAlgorithm::Zstd(w) => w.into_inner(),
+                WriteAlgorithm::XzWindow(w) => w.into_inner(),
+                _ => unreachable!(),
+            };
+
+            let mut reader = ReadAlgorithm::from_algorithm(&algo, compressed.as_slice());
+            let mut decompressed = Vec::new();
+            reader.read_to_end(&mut decompressed).await.unwrap();
+            assert_eq!(decompressed, data);
+        }
+    }
+
+    #[test]
+    fn test_from_info_picks_lz4_and_gzip() {
+        use super::*;
+
+        let tar: Mime = "application/x-tar".parse().unwrap();
+        let video: Mime = "video/mp4".parse().unwrap();
+        let size = 2 * XZ_SIZE_MIN_THRESHOLD;
+        assert_eq!(
+            Algorithm::from_info(&0, &tar, None, &Performance::Fast),
+            Algorithm::Gzip
+        );
+        assert_eq!(
+            Algorithm::from_info(&size, &video, None, &Performance::Fast),
+            Algorithm::Lz4
+        );
+        assert_eq!(
+            Algorithm::from_info(&size, &video, None, &Performance::Slow),
+            Algorithm::Xz
+        );
+        assert_eq!(
+            Algorithm::from_info(&ZSTD_SIZE_MIN_THRESHOLD, &video, None, &Performance::Slow),
+            Algorithm::Bzip2
+        );
+        assert_eq!(
+            Algorithm::from_info(&size, &video, None, &Performance::Average),
+            Algorithm::Bzip2
+        );
+        assert_eq!(
+            Algorithm::from_ext(OsStr::new(GZ_PARTED_EXT)),
+            Some(Algorithm::Gzip)
+        );
+    }
 }
@@ -5,7 +5,10 @@ use std::{
     task::{Context, Poll},
 };
 
-use crate::{compression::algorithm::Algorithm, shared::performance::Performance};
+use crate::{
+    compression::{algorithm::Algorithm, policy::CompressionPolicy},
+    shared::performance::Performance,
+};
 
 use mime_guess::from_path;
 use tokio::io::{AsyncWrite, BufWriter};
@@ -36,7 +39,7 @@ impl<W: AsyncWrite + Unpin> Compression<W> {
                     if let Some(size) = path_size {
                         algo = Algorithm::from_info(&size, &mime, ext, perf);
                     } else {
-                        algo = Algorithm::from_mime(&mime).unwrap_or_else(|| Algorithm::Zstd);
+                        algo = Algorithm::from_mime(&mime).unwrap_or(Algorithm::Zstd);
                     }
                 }
                 // no mime type so check if there is size
@@ -63,6 +66,37 @@ impl<W: AsyncWrite + Unpin> Compression<W> {
         }
     }
 
+    /// compresses with the levels and options of the policy instead of the performance
+    /// # Arguments
+    /// * `size` - bytes about to be compressed, only big inputs are compressed on many threads
+    pub fn from_policy(
+        algorithm: &Algorithm,
+        writer: W,
+        perf: &Performance,
+        policy: &CompressionPolicy,
+        size: u64,
+    ) -> IOResult<Self> {
+        let policy = policy.for_perf(perf);
+        Ok(Self {
+            inner: WriteAlgorithm::from_policy(algorithm, BufWriter::new(writer), &policy, size)?,
+            perf: *perf,
+        })
+    }
+
+    /// compresses with zstd and a dictionary trained on files like this one
+    pub fn from_dictionary(
+        writer: W,
+        perf: &Performance,
+        policy: &CompressionPolicy,
+        dictionary: &[u8],
+    ) -> IOResult<Self> {
+        let policy = policy.for_perf(perf);
+        Ok(Self {
+            inner: WriteAlgorithm::from_dictionary(BufWriter::new(writer), &policy, dictionary)?,
+            perf: *perf,
+        })
+    }
+
     pub fn perf(&self) -> &Performance {
         &self.perf
     }
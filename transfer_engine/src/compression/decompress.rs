@@ -0,0 +1,109 @@
+use std::{
+    ffi::OsStr,
+    io::{Cursor, Result as IOResult},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, Chain, ReadBuf};
+
+use super::{
+    algorithm::{Algorithm, ReadAlgorithm},
+    detect::{self, SNIFF_LEN},
+};
+use crate::errnos::{PropErrno, PropErrnoResult};
+
+pub struct Decomprossor<R: AsyncRead + Unpin> {
+    inner: ReadAlgorithm<R>,
+}
+
+impl<R: AsyncRead + Unpin> Decomprossor<R> {
+    pub fn new(algorithm: Algorithm, reader: R) -> Self {
+        Self {
+            inner: ReadAlgorithm::from_algorithm(&algorithm, reader),
+        }
+    }
+
+    /// for the files compressed with a dictionary, see `Compression::from_dictionary`
+    pub fn from_dictionary(reader: R, dictionary: &[u8]) -> IOResult<Self> {
+        Ok(Self {
+            inner: ReadAlgorithm::from_dictionary(reader, dictionary)?,
+        })
+    }
+
+    /// picks the algorithm from the first bytes of `reader` instead of trusting the extension
+    /// the sniffed bytes are put back in front of the reader
+    /// # Arguments
+    /// * `ext` - the extension of the file, it has to agree with the bytes
+    pub async fn detect(
+        mut reader: R,
+        ext: Option<&OsStr>,
+    ) -> PropErrnoResult<Decomprossor<Chain<Cursor<Vec<u8>>, R>>> {
+        let mut prefix = Vec::with_capacity(SNIFF_LEN);
+        PropErrno::from_io_result(
+            (&mut reader)
+                .take(SNIFF_LEN as u64)
+                .read_to_end(&mut prefix)
+                .await,
+            None,
+        )?;
+
+        let algorithm = detect::detect(&prefix, ext)?;
+        Ok(Decomprossor::new(
+            algorithm,
+            Cursor::new(prefix).chain(reader),
+        ))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Decomprossor<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<IOResult<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::algorithm::WriteAlgorithm;
+    use crate::shared::performance::Performance;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_detect_decompress() {
+        let data = "the quick brown fox jumps over the lazy dog. ".repeat(1024);
+        for algo in [
+            Algorithm::Bzip2,
+            Algorithm::Xz,
+            Algorithm::Zstd,
+            Algorithm::Lz4,
+            Algorithm::Gzip,
+        ] {
+            let mut writer =
+                WriteAlgorithm::from_algorithm(&algo, Vec::new(), &Performance::Average);
+            writer.write_all(data.as_bytes()).await.unwrap();
+            writer.shutdown().await.unwrap();
+            let compressed = match writer {
+                WriteAlgorithm::Bzip2(w) => w.into_inner(),
+                WriteAlgorithm::Xz(w) => w.into_inner(),
+                WriteAlgorithm::Zstd(w) => w.into_inner(),
+                WriteAlgorithm::Lz4(w) => w.into_inner(),
+                WriteAlgorithm::Gzip(w) => w.into_inner(),
+                _ => unreachable!(),
+            };
+
+            let mut reader = Decomprossor::detect(compressed.as_slice(), None)
+                .await
+                .unwrap();
+            let mut decompressed = Vec::new();
+            reader.read_to_end(&mut decompressed).await.unwrap();
+            assert_eq!(decompressed, data.as_bytes());
+        }
+
+        let res = Decomprossor::detect(data.as_bytes(), Some(OsStr::new("zst"))).await;
+        assert!(matches!(res, Err(PropErrno::Decompress)));
+    }
+}
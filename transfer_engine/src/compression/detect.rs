@@ -0,0 +1,113 @@
+// Finds out how a stream was compressed from its first bytes.
+// The extension of a file is only a hint, it can be missing or lie
+use std::ffi::OsStr;
+
+use super::algorithm::Algorithm;
+use crate::errnos::{PropErrno, PropErrnoResult};
+
+/// the header of the parts starts with this, see `transfer::header`
+/// so a part that is not compressed can be told apart from compressed data
+pub const PART_MAGIC: [u8; 4] = *b"SPPT";
+/// enough bytes for the longest magic number
+pub const SNIFF_LEN: usize = 6;
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+/// the low nibble of the first byte can be anything
+const ZSTD_SKIPPABLE_MAGIC: [u8; 3] = [0x2A, 0x4D, 0x18];
+const XZ_MAGIC: [u8; 6] = [0xFD, b'7', b'z', b'X', b'Z', 0x00];
+const BZIP2_MAGIC: [u8; 3] = *b"BZh";
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const LZ4_MAGIC: [u8; 4] = [0x04, 0x22, 0x4D, 0x18];
+
+/// What the first bytes of a stream say it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sniffed {
+    Compressed(Algorithm),
+    /// a part that is not compressed, it starts with our header
+    Part,
+}
+
+/// NOTE: brotli has no magic number so it is never found here
+pub fn sniff(bytes: &[u8]) -> Option<Sniffed> {
+    let algorithm = if bytes.starts_with(&ZSTD_MAGIC)
+        || (bytes.len() >= 4 && bytes[0] & 0xF0 == 0x50 && bytes[1..4] == ZSTD_SKIPPABLE_MAGIC)
+    {
+        Algorithm::Zstd
+    } else if bytes.starts_with(&XZ_MAGIC) {
+        Algorithm::Xz
+    } else if bytes.starts_with(&BZIP2_MAGIC) && matches!(bytes.get(3), Some(b'1'..=b'9')) {
+        Algorithm::Bzip2
+    } else if bytes.starts_with(&GZIP_MAGIC) {
+        Algorithm::Gzip
+    } else if bytes.starts_with(&LZ4_MAGIC) {
+        Algorithm::Lz4
+    } else if bytes.starts_with(&PART_MAGIC) {
+        return Some(Sniffed::Part);
+    } else {
+        return None;
+    };
+
+    Some(Sniffed::Compressed(algorithm))
+}
+
+/// the algorithm to read the stream with, checking the bytes against the extension
+/// # Arguments
+/// * `bytes` - the first bytes of the stream, at most `SNIFF_LEN` are looked at
+/// * `ext` - the extension of the file if there is one
+pub fn detect(bytes: &[u8], ext: Option<&OsStr>) -> PropErrnoResult<Algorithm> {
+    let claimed = ext.and_then(Algorithm::from_ext);
+    let algorithm = match (sniff(bytes), claimed) {
+        // a part that is not compressed starts with its magic, whatever its extension says
+        (Some(Sniffed::Part), _) => Some(Algorithm::None),
+        // the bytes win over an extension that says the data is not compressed
+        (Some(Sniffed::Compressed(found)), None | Some(Algorithm::None)) => Some(found),
+        (Some(Sniffed::Compressed(found)), Some(claimed)) if found == claimed => Some(found),
+        (None, Some(Algorithm::None)) => Some(Algorithm::None),
+        (None, Some(Algorithm::Brotli)) => Some(Algorithm::Brotli),
+        // the extension lies or the data is not something we can read
+        _ => None,
+    };
+
+    algorithm.ok_or_else(|| {
+        log::error!(
+            "{}: the data does not match the extension {:?}",
+            PropErrno::Decompress,
+            ext
+        );
+        PropErrno::Decompress
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let zst = Some(OsStr::new("zst"));
+        let zstd = [0x28, 0xB5, 0x2F, 0xFD, 0x04, 0x00];
+        assert_eq!(detect(&zstd, zst).unwrap(), Algorithm::Zstd);
+        assert_eq!(detect(&zstd, None).unwrap(), Algorithm::Zstd);
+        assert_eq!(
+            detect(b"BZh91AY", Some(OsStr::new("bz0"))).unwrap(),
+            Algorithm::Bzip2
+        );
+        assert_eq!(detect(b"SPPT\x00\x01", None).unwrap(), Algorithm::None);
+        assert_eq!(detect(b"SPPT\x00\x01", zst).unwrap(), Algorithm::None);
+        // a `.0` part is not trusted over the bytes
+        let plain = Some(OsStr::new("0"));
+        assert_eq!(detect(&zstd, plain).unwrap(), Algorithm::Zstd);
+        assert_eq!(detect(b"hello", plain).unwrap(), Algorithm::None);
+        // brotli can only be trusted on the extension
+        assert_eq!(
+            detect(b"\x8b\x02\x80", Some(OsStr::new("br"))).unwrap(),
+            Algorithm::Brotli
+        );
+
+        // the extension lies
+        assert!(detect(&[0x1F, 0x8B, 0x08], zst).is_err());
+        // nothing to go on
+        assert!(detect(b"hello", None).is_err());
+        assert!(detect(b"hello", zst).is_err());
+    }
+}
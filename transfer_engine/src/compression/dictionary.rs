@@ -0,0 +1,214 @@
+// Zstd dictionaries trained on the files of a job.
+// Small files of the same kind (json, logs, sources) share most of their content,
+// but compressed one by one each of them starts from nothing. A dictionary trained on samples
+// of them hands zstd that shared content up front
+use std::{
+    fs::File,
+    io::{Read, Result as IOResult},
+    path::Path,
+    sync::Arc,
+};
+
+use hashbrown::HashMap;
+use walkdir::WalkDir;
+
+/// bigger files do fine without a dictionary
+pub const MAX_SAMPLED_FILE_SIZE: u64 = 128 * 1024; // 128KB
+/// bytes read from every sampled file
+pub const MAX_SAMPLE_SIZE: u64 = 16 * 1024; // 16KB
+/// bytes of samples over all the groups, training takes longer the more there is
+pub const MAX_SAMPLES_SIZE: usize = 8 * 1024 * 1024; // 8MB
+/// groups with fewer samples are not worth a dictionary
+pub const MIN_SAMPLES: usize = 8;
+/// the size zstd itself defaults to
+pub const DICTIONARY_SIZE: usize = 110 * 1024; // 110KB
+/// group of the files without an extension and of every file when not grouping
+const DEFAULT_GROUP: &str = "_";
+
+/// Collects samples of small files and trains a dictionary per group of them
+pub struct DictionaryTrainer {
+    /// a dictionary per extension instead of one for the whole job
+    by_ext: bool,
+    samples: HashMap<String, Vec<Vec<u8>>>,
+    /// bytes of all the samples
+    size: usize,
+}
+
+impl DictionaryTrainer {
+    pub fn new(by_ext: bool) -> Self {
+        Self {
+            by_ext,
+            samples: HashMap::new(),
+            size: 0,
+        }
+    }
+
+    /// samples the small files under `src`, the way the job walks them
+    /// NOTE: this blocks, run it on the blocking pool
+    pub fn from_dir<P: AsRef<Path>>(src: P, by_ext: bool) -> Self {
+        let mut trainer = Self::new(by_ext);
+        let walker = WalkDir::new(src).into_iter().filter_entry(|entry| {
+            entry.depth() == 0
+                || !entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| name.starts_with('.'))
+        });
+
+        for entry in walker.flatten() {
+            let len = match entry.metadata() {
+                Ok(meta) if meta.is_file() => meta.len(),
+                _ => continue,
+            };
+            // a file that cannot be read is just not sampled, the copy will report it
+            if let Ok(false) = trainer.add_file(entry.path(), len) {
+                break;
+            }
+        }
+
+        trainer
+    }
+
+    /// samples the file if it is small enough to gain from a dictionary
+    /// returns false once there is no room left for more samples
+    pub fn add_file<P: AsRef<Path>>(&mut self, path: P, len: u64) -> IOResult<bool> {
+        if self.is_full() {
+            return Ok(false);
+        }
+        if len == 0 || len > MAX_SAMPLED_FILE_SIZE {
+            return Ok(true);
+        }
+
+        let mut sample = Vec::with_capacity(len.min(MAX_SAMPLE_SIZE) as usize);
+        File::open(path.as_ref())?
+            .take(MAX_SAMPLE_SIZE)
+            .read_to_end(&mut sample)?;
+        let group = self.group(path.as_ref());
+        self.add_sample(group, sample);
+
+        Ok(!self.is_full())
+    }
+
+    pub fn add_sample(&mut self, group: String, sample: Vec<u8>) {
+        self.size += sample.len();
+        self.samples.entry(group).or_default().push(sample);
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.size >= MAX_SAMPLES_SIZE
+    }
+
+    fn group(&self, path: &Path) -> String {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if self.by_ext => ext.to_lowercase(),
+            _ => DEFAULT_GROUP.to_string(),
+        }
+    }
+
+    /// trains a dictionary for every group with enough samples
+    /// NOTE: this takes a while, run it on the blocking pool
+    pub fn train(self) -> Dictionaries {
+        let mut dictionaries = Dictionaries::default();
+        for (group, samples) in self.samples {
+            if samples.len() < MIN_SAMPLES {
+                continue;
+            }
+
+            // zstd refuses samples that have nothing in common, those files just go without
+            if let Ok(dictionary) = zstd::dict::from_samples(&samples, DICTIONARY_SIZE) {
+                dictionaries.insert(group, dictionary);
+            }
+        }
+
+        dictionaries
+    }
+}
+
+/// The dictionaries of a job by group
+/// NOTE: cloning shares the dictionaries
+#[derive(Debug, Clone, Default)]
+pub struct Dictionaries(HashMap<String, Arc<Vec<u8>>>);
+
+impl Dictionaries {
+    pub fn insert(&mut self, group: String, dictionary: Vec<u8>) {
+        self.0.insert(group, Arc::new(dictionary));
+    }
+
+    /// the dictionary of the group of the file, falling back to the one of the whole job
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&[u8]> {
+        self.find(path).map(|(_, dictionary)| dictionary)
+    }
+
+    /// same as `get` along with the group of the dictionary
+    pub fn find<P: AsRef<Path>>(&self, path: P) -> Option<(&str, &[u8])> {
+        path.as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.0.get_key_value(&ext.to_lowercase()))
+            .or_else(|| self.0.get_key_value(DEFAULT_GROUP))
+            .map(|(group, dictionary)| (group.as_str(), dictionary.as_slice()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.0
+            .iter()
+            .map(|(group, dictionary)| (group.as_str(), dictionary.as_slice()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::{
+        algorithm::{ReadAlgorithm, WriteAlgorithm},
+        policy::CompressionPolicy,
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn sample(i: usize) -> Vec<u8> {
+        format!(
+            r#"{{"id": {}, "name": "user-{}", "email": "user{}@example.com", "active": {}, "roles": ["reader", "writer"]}}"#,
+            i,
+            i * 7,
+            i * 13,
+            i.is_multiple_of(2)
+        )
+        .into_bytes()
+    }
+
+    #[tokio::test]
+    async fn test_dictionary_roundtrip() {
+        let mut trainer = DictionaryTrainer::new(true);
+        for i in 0..256 {
+            trainer.add_sample("json".to_string(), sample(i));
+        }
+        let dictionaries = trainer.train();
+        assert_eq!(dictionaries.len(), 1);
+        assert!(dictionaries.get("a.txt").is_none());
+        let (group, dictionary) = dictionaries.find("users/1000.JSON").unwrap();
+        assert_eq!(group, "json");
+
+        let data = sample(1000);
+        let policy = CompressionPolicy::default();
+        let mut writer = WriteAlgorithm::from_dictionary(Vec::new(), &policy, dictionary).unwrap();
+        writer.write_all(&data).await.unwrap();
+        writer.shutdown().await.unwrap();
+        let compressed = match writer {
+            WriteAlgorithm::Zstd(w) => w.into_inner(),
+            _ => unreachable!(),
+        };
+
+        let mut reader = ReadAlgorithm::from_dictionary(compressed.as_slice(), dictionary).unwrap();
+        let mut decompressed = Vec::new();
+        reader.read_to_end(&mut decompressed).await.unwrap();
+        assert_eq!(decompressed, data);
+    }
+}
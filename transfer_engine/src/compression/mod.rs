@@ -3,3 +3,9 @@
 pub mod algorithm;
 pub mod compress;
 pub mod decompress;
+pub mod detect;
+pub mod dictionary;
+pub mod parallel;
+pub mod policy;
+pub mod probe;
+pub mod xz;
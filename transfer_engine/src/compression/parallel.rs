@@ -0,0 +1,257 @@
+// Compresses a single big file on many threads.
+// The input is cut into blocks and every block becomes a frame of its own, compressed on the
+// blocking pool. The frames are written in order so the output is a regular multi-frame
+// zstd or xz stream that any decoder reads back
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io::{Error, ErrorKind, Result as IOResult},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use liblzma::stream::{Action, Status};
+use tokio::{io::AsyncWrite, task::JoinHandle};
+use zstd::{bulk::Compressor, zstd_safe::CParameter};
+
+use super::{algorithm::Algorithm, policy::CompressionPolicy, xz};
+
+/// size of the input of every frame, smaller blocks compress worse
+pub const PARALLEL_BLOCK_SIZE: usize = 8 * 1024 * 1024; // 8MB
+/// anything smaller is compressed on one thread, it would not even fill two blocks
+pub const MIN_PARALLEL_SIZE: u64 = 2 * PARALLEL_BLOCK_SIZE as u64;
+
+/// the most memory an encoder on `threads` threads holds: the block being filled,
+/// the blocks being compressed along with their frames and the frame being written
+pub fn parallel_memory(threads: usize) -> u64 {
+    ((2 * threads + 2) * PARALLEL_BLOCK_SIZE) as u64
+}
+
+/// the most threads an encoder can run on within `memory` bytes
+pub fn parallel_threads_within(memory: u64) -> usize {
+    (memory / PARALLEL_BLOCK_SIZE as u64).saturating_sub(2) as usize / 2
+}
+
+/// What a block is compressed with, cloned into every blocking task
+#[derive(Debug, Clone, Copy)]
+pub enum FrameCodec {
+    Zstd {
+        level: i32,
+        long_distance: bool,
+        window_log: Option<u32>,
+    },
+    Xz {
+        preset: u32,
+        window_log: Option<u32>,
+    },
+}
+
+impl FrameCodec {
+    /// None when `algorithm` cannot be compressed in frames
+    pub fn from_policy(algorithm: &Algorithm, policy: &CompressionPolicy) -> Option<Self> {
+        match algorithm {
+            Algorithm::Zstd => Some(Self::Zstd {
+                level: policy.zstd_level(),
+                long_distance: policy.zstd_long_distance(),
+                window_log: policy.zstd_window_log(),
+            }),
+            Algorithm::Xz => Some(Self::Xz {
+                preset: policy.xz_preset(),
+                window_log: policy.xz_window_log(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn compress(&self, block: &[u8]) -> IOResult<Vec<u8>> {
+        match *self {
+            Self::Zstd {
+                level,
+                long_distance,
+                window_log,
+            } => {
+                let mut compressor = Compressor::new(level)?;
+                compressor.set_parameter(CParameter::EnableLongDistanceMatching(long_distance))?;
+                if let Some(window_log) = window_log {
+                    compressor.set_parameter(CParameter::WindowLog(window_log))?;
+                }
+                compressor.compress(block)
+            }
+            Self::Xz { preset, window_log } => {
+                let mut stream = xz::encoder_stream(preset, window_log)?;
+                let mut frame = Vec::with_capacity(block.len() / 2 + 1024);
+                loop {
+                    let consumed = stream.total_in() as usize;
+                    let status = stream
+                        .process_vec(&block[consumed..], &mut frame, Action::Finish)
+                        .map_err(xz::io_err)?;
+                    if let Status::StreamEnd = status {
+                        return Ok(frame);
+                    }
+                    frame.reserve(frame.capacity());
+                }
+            }
+        }
+    }
+}
+
+pub struct ParallelEncoder<W> {
+    writer: W,
+    codec: FrameCodec,
+    threads: usize,
+    /// the block being filled
+    block: Vec<u8>,
+    /// frames being compressed, oldest first
+    frames: VecDeque<JoinHandle<IOResult<Vec<u8>>>>,
+    /// the compressed frame being written
+    out: Vec<u8>,
+    /// how much of `out` was already written
+    written: usize,
+}
+
+impl<W> ParallelEncoder<W> {
+    /// # Arguments
+    /// * `threads` - most blocks compressed at the same time
+    pub fn new(writer: W, codec: FrameCodec, threads: usize) -> Self {
+        Self {
+            writer,
+            codec,
+            threads: threads.max(1),
+            block: Vec::with_capacity(PARALLEL_BLOCK_SIZE),
+            frames: VecDeque::new(),
+            out: Vec::new(),
+            written: 0,
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match self.codec {
+            FrameCodec::Zstd { .. } => Algorithm::Zstd,
+            FrameCodec::Xz { .. } => Algorithm::Xz,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: AsyncWrite + Unpin> ParallelEncoder<W> {
+    /// hands the block to the blocking pool
+    fn spawn_block(&mut self) {
+        let block = std::mem::replace(&mut self.block, Vec::with_capacity(PARALLEL_BLOCK_SIZE));
+        let codec = self.codec;
+        self.frames
+            .push_back(tokio::task::spawn_blocking(move || codec.compress(&block)));
+    }
+
+    /// writes the frame that is done being compressed
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        while self.written < self.out.len() {
+            let n = ready!(Pin::new(&mut self.writer).poll_write(cx, &self.out[self.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(ErrorKind::WriteZero.into()));
+            }
+            self.written += n;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// waits for the oldest frame and starts writing it
+    fn poll_next_frame(&mut self, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        if let Some(frame) = self.frames.front_mut() {
+            let res = ready!(Pin::new(frame).poll(cx));
+            self.frames.pop_front();
+            self.out = res.map_err(Error::other)??;
+            self.written = 0;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// compresses whatever is in the block and writes every frame
+    fn poll_finish_frames(&mut self, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        if !self.block.is_empty() {
+            self.spawn_block();
+        }
+
+        loop {
+            ready!(self.poll_drain(cx))?;
+            if self.frames.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            ready!(self.poll_next_frame(cx))?;
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for ParallelEncoder<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IOResult<usize>> {
+        let this = self.get_mut();
+        // every thread is busy, the oldest frame has to be written before taking more
+        while this.frames.len() >= this.threads {
+            ready!(this.poll_drain(cx))?;
+            ready!(this.poll_next_frame(cx))?;
+        }
+        ready!(this.poll_drain(cx))?;
+
+        let n = buf.len().min(PARALLEL_BLOCK_SIZE - this.block.len());
+        this.block.extend_from_slice(&buf[..n]);
+        if this.block.len() == PARALLEL_BLOCK_SIZE {
+            this.spawn_block();
+        }
+
+        Poll::Ready(Ok(n))
+    }
+
+    /// NOTE: flushing ends the current frame early, so flushing often compresses worse
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_finish_frames(cx))?;
+        Pin::new(&mut this.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_finish_frames(cx))?;
+        Pin::new(&mut this.writer).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::algorithm::ReadAlgorithm;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_parallel_roundtrip() {
+        // a few whole blocks and a partial one
+        let data: Vec<u8> = (0..2 * PARALLEL_BLOCK_SIZE + 12345)
+            .map(|i| (i % 251) as u8 ^ (i / 4096) as u8)
+            .collect();
+        let policy = CompressionPolicy::default().set_speed_vs_size(Some(0));
+        for algo in [Algorithm::Zstd, Algorithm::Xz] {
+            let codec = FrameCodec::from_policy(&algo, &policy).unwrap();
+            let mut writer = ParallelEncoder::new(Vec::new(), codec, 2);
+            writer.write_all(&data).await.unwrap();
+            writer.shutdown().await.unwrap();
+            assert_eq!(writer.algorithm(), algo);
+
+            let compressed = writer.into_inner();
+            let mut reader = ReadAlgorithm::from_algorithm(&algo, compressed.as_slice());
+            let mut decompressed = Vec::new();
+            reader.read_to_end(&mut decompressed).await.unwrap();
+            assert!(decompressed == data);
+        }
+    }
+
+    #[test]
+    fn test_parallel_memory() {
+        assert_eq!(parallel_memory(4), 80 * 1024 * 1024);
+        assert_eq!(parallel_threads_within(parallel_memory(4)), 4);
+        assert_eq!(parallel_threads_within(parallel_memory(4) - 1), 3);
+        assert_eq!(parallel_threads_within(1024), 0);
+    }
+}
@@ -0,0 +1,316 @@
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+use async_compression::Level;
+use serde::{Deserialize, Serialize};
+
+use super::{algorithm::Algorithm, parallel::MIN_PARALLEL_SIZE};
+use crate::{
+    errnos::{Errno, ErrnoResult},
+    shared::performance::Performance,
+};
+
+/// the slider goes from the fastest (0) to the smallest (100) compression
+pub const MAX_SPEED_VS_SIZE: u8 = 100;
+pub const ZSTD_WINDOW_LOGS: RangeInclusive<u32> = 10..=27;
+pub const XZ_WINDOW_LOGS: RangeInclusive<u32> = 12..=30;
+pub const MIN_COMPRESSION_THREADS: usize = 1;
+pub const MAX_COMPRESSION_THREADS: usize = 64;
+/// the levels `Level::Default` stands for
+const ZSTD_DEFAULT_LEVEL: i32 = 3;
+const XZ_DEFAULT_PRESET: u32 = 6;
+
+impl Algorithm {
+    /// the levels the algorithm understands, from the fastest to the smallest
+    pub fn levels(&self) -> RangeInclusive<i32> {
+        match self {
+            Self::None => 0..=0,
+            Self::Bzip2 => 1..=9,
+            Self::Xz => 0..=9,
+            Self::Brotli => 0..=11,
+            Self::Zstd => 1..=22,
+            Self::Lz4 => 0..=12,
+            Self::Gzip => 1..=9,
+        }
+    }
+}
+
+/// Which files share a trained zstd dictionary
+/// the dictionaries are stored in the archive, the other splitters do not use them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DictionaryPolicy {
+    /// every file is compressed on its own
+    #[default]
+    None,
+    /// one dictionary for the small files of the whole job
+    Job,
+    /// one dictionary per extension, better when the job mixes kinds of files
+    Extension,
+}
+
+/// How hard the files are compressed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionPolicy {
+    /// 0 is the fastest and 100 the smallest, mapped onto the levels of every algorithm
+    /// None keeps the default level of every algorithm
+    speed_vs_size: Option<u8>,
+    /// level of an algorithm in its own range, this wins over the slider
+    levels: BTreeMap<Algorithm, i32>,
+    /// lets zstd find matches far back in big files
+    zstd_long_distance: bool,
+    /// the zstd window is 2^zstd_window_log bytes, None lets the level pick it
+    zstd_window_log: Option<u32>,
+    /// the xz window is 2^xz_window_log bytes, None lets the level pick it
+    xz_window_log: Option<u32>,
+    /// big zstd and xz files are compressed in blocks on this many threads, None uses one
+    threads: Option<usize>,
+    /// trains dictionaries on samples of the small files before compressing them with zstd
+    dictionary: DictionaryPolicy,
+}
+
+impl CompressionPolicy {
+    pub fn set_speed_vs_size(mut self, speed_vs_size: Option<u8>) -> Self {
+        self.speed_vs_size = speed_vs_size;
+        self
+    }
+
+    /// None goes back to the slider for that algorithm
+    pub fn set_level(mut self, algorithm: Algorithm, level: Option<i32>) -> Self {
+        match level {
+            Some(level) => self.levels.insert(algorithm, level),
+            None => self.levels.remove(&algorithm),
+        };
+        self
+    }
+
+    pub fn set_zstd_long_distance(mut self, zstd_long_distance: bool) -> Self {
+        self.zstd_long_distance = zstd_long_distance;
+        self
+    }
+
+    pub fn set_zstd_window_log(mut self, zstd_window_log: Option<u32>) -> Self {
+        self.zstd_window_log = zstd_window_log;
+        self
+    }
+
+    pub fn set_xz_window_log(mut self, xz_window_log: Option<u32>) -> Self {
+        self.xz_window_log = xz_window_log;
+        self
+    }
+
+    pub fn set_threads(mut self, threads: Option<usize>) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn set_dictionary(mut self, dictionary: DictionaryPolicy) -> Self {
+        self.dictionary = dictionary;
+        self
+    }
+
+    pub fn speed_vs_size(&self) -> Option<u8> {
+        self.speed_vs_size
+    }
+
+    pub fn zstd_long_distance(&self) -> bool {
+        self.zstd_long_distance
+    }
+
+    pub fn zstd_window_log(&self) -> Option<u32> {
+        self.zstd_window_log
+    }
+
+    pub fn xz_window_log(&self) -> Option<u32> {
+        self.xz_window_log
+    }
+
+    pub fn threads(&self) -> Option<usize> {
+        self.threads
+    }
+
+    pub fn dictionary(&self) -> DictionaryPolicy {
+        self.dictionary
+    }
+
+    /// the policy with the slider of `perf` when it has none, so the levels agree with
+    /// the ones `perf` stands for without a policy
+    pub fn for_perf(&self, perf: &Performance) -> Self {
+        let mut policy = self.clone();
+        if policy.speed_vs_size.is_none() {
+            policy.speed_vs_size = match perf {
+                Performance::Fast => Some(0),
+                Performance::Average => None,
+                Performance::Slow => Some(MAX_SPEED_VS_SIZE),
+            };
+        }
+        policy
+    }
+
+    /// the threads `size` bytes compressed with `algorithm` are spread over,
+    /// None when they are compressed on one thread
+    pub fn parallel_threads(&self, algorithm: &Algorithm, size: u64) -> Option<usize> {
+        let threads = self.threads.filter(|threads| *threads > 1)?;
+        // only zstd and xz can be compressed in frames
+        let framed = matches!(algorithm, Algorithm::Zstd | Algorithm::Xz);
+        (framed && size >= MIN_PARALLEL_SIZE).then_some(threads)
+    }
+
+    /// the zstd level as zstd itself counts it
+    pub fn zstd_level(&self) -> i32 {
+        let levels = Algorithm::Zstd.levels();
+        match self.level(&Algorithm::Zstd) {
+            Level::Fastest => *levels.start(),
+            Level::Best => *levels.end(),
+            Level::Precise(level) => level.clamp(*levels.start(), *levels.end()),
+            _ => ZSTD_DEFAULT_LEVEL,
+        }
+    }
+
+    /// the xz preset as xz itself counts it
+    pub fn xz_preset(&self) -> u32 {
+        let levels = Algorithm::Xz.levels();
+        match self.level(&Algorithm::Xz) {
+            Level::Fastest => *levels.start() as u32,
+            Level::Best => *levels.end() as u32,
+            Level::Precise(level) => level.clamp(*levels.start(), *levels.end()) as u32,
+            _ => XZ_DEFAULT_PRESET,
+        }
+    }
+
+    /// the level `algorithm` runs at
+    pub fn level(&self, algorithm: &Algorithm) -> Level {
+        if let Some(level) = self.levels.get(algorithm) {
+            return Level::Precise(*level);
+        }
+
+        match self.speed_vs_size {
+            Some(slider) => {
+                let levels = algorithm.levels();
+                let span = (levels.end() - levels.start()) as f64;
+                let level = span * slider.min(MAX_SPEED_VS_SIZE) as f64 / MAX_SPEED_VS_SIZE as f64;
+                Level::Precise(levels.start() + level.round() as i32)
+            }
+            None => Level::Default,
+        }
+    }
+
+    /// same as `Settings::validate`
+    pub fn validate(&self) -> ErrnoResult<()> {
+        if let Some(slider) = self.speed_vs_size {
+            if slider > MAX_SPEED_VS_SIZE {
+                return Err(Errno::setting_range(
+                    "speed_vs_size".to_string(),
+                    0,
+                    MAX_SPEED_VS_SIZE as u64,
+                ));
+            }
+        }
+
+        for (algorithm, level) in &self.levels {
+            let levels = algorithm.levels();
+            if !levels.contains(level) {
+                return Err(Errno::setting_range(
+                    format!("{:?} level", algorithm),
+                    *levels.start() as u64,
+                    *levels.end() as u64,
+                ));
+            }
+        }
+
+        if let Some(threads) = self.threads {
+            if !(MIN_COMPRESSION_THREADS..=MAX_COMPRESSION_THREADS).contains(&threads) {
+                return Err(Errno::setting_range(
+                    "compression threads".to_string(),
+                    MIN_COMPRESSION_THREADS as u64,
+                    MAX_COMPRESSION_THREADS as u64,
+                ));
+            }
+        }
+
+        for (name, window_log, range) in [
+            ("zstd_window_log", self.zstd_window_log, ZSTD_WINDOW_LOGS),
+            ("xz_window_log", self.xz_window_log, XZ_WINDOW_LOGS),
+        ] {
+            if let Some(window_log) = window_log {
+                if !range.contains(&window_log) {
+                    return Err(Errno::setting_range(
+                        name.to_string(),
+                        *range.start() as u64,
+                        *range.end() as u64,
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slider_maps_onto_levels() {
+        let policy = CompressionPolicy::default().set_speed_vs_size(Some(0));
+        assert!(matches!(policy.level(&Algorithm::Zstd), Level::Precise(1)));
+        assert!(matches!(policy.level(&Algorithm::Xz), Level::Precise(0)));
+
+        let policy = policy.set_speed_vs_size(Some(100));
+        assert!(matches!(policy.level(&Algorithm::Zstd), Level::Precise(22)));
+        assert!(matches!(
+            policy.level(&Algorithm::Brotli),
+            Level::Precise(11)
+        ));
+
+        // an explicit level wins over the slider
+        let policy = policy.set_level(Algorithm::Zstd, Some(5));
+        assert!(matches!(policy.level(&Algorithm::Zstd), Level::Precise(5)));
+        assert!(policy.validate().is_ok());
+
+        let policy = policy.set_level(Algorithm::Gzip, Some(12));
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn test_for_perf() {
+        let policy = CompressionPolicy::default();
+        let fast = policy.for_perf(&Performance::Fast);
+        assert!(matches!(fast.level(&Algorithm::Zstd), Level::Precise(1)));
+        assert_eq!(fast.xz_preset(), 0);
+        let slow = policy.for_perf(&Performance::Slow);
+        assert!(matches!(slow.level(&Algorithm::Zstd), Level::Precise(22)));
+        let average = policy.for_perf(&Performance::Average);
+        assert!(matches!(average.level(&Algorithm::Zstd), Level::Default));
+
+        // the slider of the policy wins over the performance
+        let policy = policy.set_speed_vs_size(Some(50));
+        let fast = policy.for_perf(&Performance::Fast);
+        assert_eq!(fast.speed_vs_size(), Some(50));
+    }
+
+    #[test]
+    fn test_parallel_threads() {
+        let policy = CompressionPolicy::default();
+        assert_eq!(policy.parallel_threads(&Algorithm::Zstd, u64::MAX), None);
+
+        let policy = policy.set_threads(Some(4));
+        assert_eq!(
+            policy.parallel_threads(&Algorithm::Zstd, MIN_PARALLEL_SIZE),
+            Some(4)
+        );
+        assert_eq!(policy.parallel_threads(&Algorithm::Xz, u64::MAX), Some(4));
+        // small files and algorithms without frames stay on one thread
+        assert_eq!(
+            policy.parallel_threads(&Algorithm::Zstd, MIN_PARALLEL_SIZE - 1),
+            None
+        );
+        assert_eq!(policy.parallel_threads(&Algorithm::Gzip, u64::MAX), None);
+        assert_eq!(
+            policy
+                .set_threads(Some(1))
+                .parallel_threads(&Algorithm::Zstd, u64::MAX),
+            None
+        );
+    }
+}
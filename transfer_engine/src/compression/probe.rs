@@ -0,0 +1,207 @@
+// Guesses how well a file compresses from a few samples of it.
+// The mime type says nothing about files with a missing or wrong extension
+// and compressing data that is already compressed only burns CPU
+use std::{
+    collections::BTreeMap,
+    io::{Result as IOResult, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use async_compression::tokio::write::Lz4Encoder;
+use serde::Serialize;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+
+use super::algorithm::Algorithm;
+
+/// number of blocks read, spread evenly over the file
+pub const SAMPLE_BLOCKS: u64 = 4;
+pub const SAMPLE_BLOCK_SIZE: u64 = 64 * 1024; // 64KB
+/// compressing has to shrink the samples at least this much to be worth it
+pub const MIN_COMPRESSION_RATIO: f64 = 1.1;
+/// bits per byte above which the samples are taken as already compressed without trying
+pub const MAX_ENTROPY: f64 = 7.9;
+
+/// What the samples of a file say about how well it compresses
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CompressionProbe {
+    /// shannon entropy of the samples in bits per byte, 8 is random data
+    entropy: f64,
+    /// size of the samples over their size compressed with a fast algorithm
+    ratio: f64,
+}
+
+impl CompressionProbe {
+    /// reads the samples of the file and probes them
+    /// # Arguments
+    /// * `size` - size of the file, small files are read whole
+    pub async fn from_path<P: AsRef<Path>>(path: P, size: u64) -> IOResult<Self> {
+        let mut file = File::open(path.as_ref()).await?;
+        let mut samples = Vec::new();
+        if size <= SAMPLE_BLOCKS * SAMPLE_BLOCK_SIZE {
+            file.read_to_end(&mut samples).await?;
+        } else {
+            let step = (size - SAMPLE_BLOCK_SIZE) / (SAMPLE_BLOCKS - 1);
+            for block in 0..SAMPLE_BLOCKS {
+                file.seek(SeekFrom::Start(block * step)).await?;
+                (&mut file)
+                    .take(SAMPLE_BLOCK_SIZE)
+                    .read_to_end(&mut samples)
+                    .await?;
+            }
+        }
+
+        Self::from_samples(&samples).await
+    }
+
+    pub async fn from_samples(samples: &[u8]) -> IOResult<Self> {
+        let entropy = entropy(samples);
+        // there is nothing left to gain, the ratio can only be about 1
+        if entropy > MAX_ENTROPY || samples.is_empty() {
+            return Ok(Self {
+                entropy,
+                ratio: 1.0,
+            });
+        }
+
+        let mut encoder = Lz4Encoder::new(Vec::with_capacity(samples.len()));
+        encoder.write_all(samples).await?;
+        encoder.shutdown().await?;
+        let compressed = encoder.into_inner().len().max(1);
+
+        Ok(Self {
+            entropy,
+            ratio: samples.len() as f64 / compressed as f64,
+        })
+    }
+
+    pub fn entropy(&self) -> f64 {
+        self.entropy
+    }
+
+    pub fn ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    /// whether compressing the file is expected to pay off
+    pub fn is_compressible(&self) -> bool {
+        self.ratio >= MIN_COMPRESSION_RATIO
+    }
+}
+
+/// shannon entropy in bits per byte
+fn entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+
+    let mut counts = [0u64; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// The compression picked for a file and why
+#[derive(Debug, Clone, Serialize)]
+pub struct CompressionReport {
+    pub src: PathBuf,
+    pub size: u64,
+    pub algorithm: Algorithm,
+    /// None when the file was not probed
+    pub probe: Option<CompressionProbe>,
+}
+
+/// The reports of all the files of a transfer added up, so the observer is not told about every file
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CompressionSummary {
+    pub files: u64,
+    pub bytes: u64,
+    /// number of files every algorithm was picked for
+    pub algorithms: BTreeMap<Algorithm, u64>,
+    /// files left uncompressed because their samples did not compress well enough
+    pub skipped: u64,
+    /// bytes of the probed files
+    pub probed_bytes: u64,
+    /// ratio the samples compressed at, weighted by the size of their files
+    pub ratio: f64,
+}
+
+impl CompressionSummary {
+    pub fn add(&mut self, report: &CompressionReport) {
+        self.files += 1;
+        self.bytes += report.size;
+        *self.algorithms.entry(report.algorithm).or_default() += 1;
+
+        let probe = match &report.probe {
+            Some(probe) => probe,
+            None => return,
+        };
+        if !probe.is_compressible() {
+            self.skipped += 1;
+        }
+        let probed_bytes = self.probed_bytes + report.size;
+        if probed_bytes > 0 {
+            self.ratio = (self.ratio * self.probed_bytes as f64
+                + probe.ratio() * report.size as f64)
+                / probed_bytes as f64;
+        }
+        self.probed_bytes = probed_bytes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+
+    #[tokio::test]
+    async fn test_probe_samples() {
+        let text = "the quick brown fox jumps over the lazy dog. ".repeat(4096);
+        let probe = CompressionProbe::from_samples(text.as_bytes())
+            .await
+            .unwrap();
+        assert!(probe.is_compressible());
+        assert!(probe.entropy() < 5.0);
+
+        let mut random = vec![0; 256 * 1024];
+        rand::thread_rng().fill_bytes(&mut random);
+        let probe = CompressionProbe::from_samples(&random).await.unwrap();
+        assert!(!probe.is_compressible());
+        assert!(probe.entropy() > MAX_ENTROPY);
+    }
+
+    #[test]
+    fn test_summary() {
+        let report = |size, algorithm, ratio: Option<f64>| CompressionReport {
+            src: PathBuf::from("file"),
+            size,
+            algorithm,
+            probe: ratio.map(|ratio| CompressionProbe {
+                entropy: 4.0,
+                ratio,
+            }),
+        };
+
+        let mut summary = CompressionSummary::default();
+        summary.add(&report(100, Algorithm::Zstd, Some(3.0)));
+        summary.add(&report(300, Algorithm::None, Some(1.0)));
+        summary.add(&report(50, Algorithm::None, None));
+        assert_eq!((summary.files, summary.bytes), (3, 450));
+        assert_eq!(summary.algorithms[&Algorithm::None], 2);
+        assert_eq!(summary.algorithms[&Algorithm::Zstd], 1);
+        assert_eq!((summary.skipped, summary.probed_bytes), (1, 400));
+        assert_eq!(summary.ratio, 1.5);
+    }
+}
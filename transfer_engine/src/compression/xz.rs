@@ -0,0 +1,109 @@
+// An xz encoder with a custom window (dictionary) size.
+// async-compression only takes a preset for xz and the preset ties the window to the level
+use std::{
+    io::{Error, ErrorKind, Result as IOResult},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use liblzma::stream::{Action, Check, Filters, LzmaOptions, Status, Stream};
+use tokio::io::AsyncWrite;
+
+/// the compressed output is handed to the writer in pieces of this size
+const OUT_CAPACITY: usize = 64 * 1024; // 64KB
+
+pub struct XzWindowEncoder<W> {
+    writer: W,
+    stream: Stream,
+    /// compressed bytes not written yet
+    out: Vec<u8>,
+    /// how much of `out` was already written
+    written: usize,
+    finished: bool,
+}
+
+impl<W: AsyncWrite + Unpin> XzWindowEncoder<W> {
+    /// # Arguments
+    /// * `preset` - the xz level, 0 to 9
+    /// * `window_log` - the window is 2^window_log bytes
+    pub fn new(writer: W, preset: u32, window_log: u32) -> IOResult<Self> {
+        Ok(Self {
+            writer,
+            stream: encoder_stream(preset, Some(window_log))?,
+            out: Vec::with_capacity(OUT_CAPACITY),
+            written: 0,
+            finished: false,
+        })
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// writes everything compressed so far
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        while self.written < self.out.len() {
+            let n = ready!(Pin::new(&mut self.writer).poll_write(cx, &self.out[self.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(ErrorKind::WriteZero.into()));
+            }
+            self.written += n;
+        }
+
+        self.out.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// a whole xz stream, `window_log` overrides the window of the preset
+pub fn encoder_stream(preset: u32, window_log: Option<u32>) -> IOResult<Stream> {
+    let mut options = LzmaOptions::new_preset(preset).map_err(io_err)?;
+    if let Some(window_log) = window_log {
+        options.dict_size(1 << window_log);
+    }
+    let mut filters = Filters::new();
+    filters.lzma2(&options);
+    Stream::new_stream_encoder(&filters, Check::Crc64).map_err(io_err)
+}
+
+pub fn io_err(err: liblzma::stream::Error) -> Error {
+    Error::new(ErrorKind::InvalidInput, err)
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for XzWindowEncoder<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IOResult<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+
+        let before = this.stream.total_in();
+        this.stream
+            .process_vec(buf, &mut this.out, Action::Run)
+            .map_err(io_err)?;
+        Poll::Ready(Ok((this.stream.total_in() - before) as usize))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        let this = self.get_mut();
+        loop {
+            ready!(this.poll_drain(cx))?;
+            if this.finished {
+                break;
+            }
+
+            let status = this
+                .stream
+                .process_vec(&[], &mut this.out, Action::Finish)
+                .map_err(io_err)?;
+            this.finished = matches!(status, Status::StreamEnd);
+        }
+
+        Pin::new(&mut this.writer).poll_shutdown(cx)
+    }
+}
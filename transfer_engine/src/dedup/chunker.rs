@@ -0,0 +1,162 @@
+use std::io::Result as IOResult;
+
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{AVG_CHUNK_SIZE, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE};
+use crate::transfer::Chunk;
+
+/// a random number for every byte, the rolling hash adds them up
+const GEAR: [u64; 256] = gear_table();
+/// a cut needs more zero bits before the average size and fewer after it,
+/// which keeps most of the chunks close to the average
+const MASK_SMALL: u64 = !0 << (64 - (AVG_CHUNK_SIZE.trailing_zeros() + 2));
+const MASK_LARGE: u64 = !0 << (64 - (AVG_CHUNK_SIZE.trailing_zeros() - 2));
+
+/// the table has to be the same on every machine and every run, or nothing would dedup
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    // splitmix64
+    let mut seed: u64 = 0x5350_5044_4544_5550;
+    let mut i = 0;
+    while i < table.len() {
+        seed = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// the length of the chunk at the start of `data`
+/// NOTE: `data` has to hold `MAX_CHUNK_SIZE` bytes unless it is the end of the file
+pub fn cut_point(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK_SIZE {
+        return data.len();
+    }
+
+    let end = data.len().min(MAX_CHUNK_SIZE);
+    let normal = end.min(AVG_CHUNK_SIZE);
+    let mut hash: u64 = 0;
+    // the top bits of the hash depend on the last 64 bytes
+    for (i, byte) in data.iter().enumerate().take(end).skip(MIN_CHUNK_SIZE) {
+        hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+        let mask = if i < normal { MASK_SMALL } else { MASK_LARGE };
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// Cuts a file into chunks where its content says so, not at fixed offsets.
+/// The same content gives the same chunks wherever it is in the file,
+/// so an insert only changes the chunks around it
+pub struct ContentChunker<R> {
+    reader: R,
+    /// read but not chunked yet
+    buf: BytesMut,
+    /// offset of the start of `buf` in the file
+    offset: u64,
+    is_eof: bool,
+}
+
+impl<R: AsyncRead + Unpin> ContentChunker<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: BytesMut::with_capacity(2 * MAX_CHUNK_SIZE),
+            offset: 0,
+            is_eof: false,
+        }
+    }
+
+    /// the next chunk of the file, None at the end of it
+    pub async fn next(&mut self) -> IOResult<Option<Chunk>> {
+        while !self.is_eof && self.buf.len() < MAX_CHUNK_SIZE {
+            self.buf.reserve(MAX_CHUNK_SIZE);
+            self.is_eof = self.reader.read_buf(&mut self.buf).await? == 0;
+        }
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+
+        let len = cut_point(&self.buf);
+        let data = self.buf.split_to(len);
+        let start = self.offset;
+        self.offset += len as u64;
+        Ok(Some(Chunk::new(start, self.offset, data)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the same bytes on every run
+    fn data(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    async fn chunk_all(data: &[u8]) -> Vec<Chunk> {
+        let mut chunker = ContentChunker::new(data);
+        let mut chunks = Vec::new();
+        while let Some(chunk) = chunker.next().await.unwrap() {
+            chunks.push(chunk);
+        }
+        chunks
+    }
+
+    #[tokio::test]
+    async fn test_chunk_sizes() {
+        let data = data(4 * 1024 * 1024, 1);
+        let chunks = chunk_all(&data).await;
+        let mut offset = 0;
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert_eq!(*chunk.start(), offset);
+            assert!(chunk.size() as usize <= MAX_CHUNK_SIZE);
+            if i + 1 < chunks.len() {
+                assert!(chunk.size() as usize > MIN_CHUNK_SIZE);
+            }
+            offset = *chunk.end();
+        }
+        assert_eq!(offset, data.len() as u64);
+
+        let avg = data.len() / chunks.len();
+        assert!(
+            avg > AVG_CHUNK_SIZE / 2 && avg < AVG_CHUNK_SIZE * 2,
+            "{}",
+            avg
+        );
+        assert!(chunk_all(&[]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_insert_keeps_other_chunks() {
+        let data = data(2 * 1024 * 1024, 2);
+        let mut changed = data.clone();
+        changed.splice(1024 * 1024..1024 * 1024, b"a few new bytes".iter().copied());
+
+        let hashes = |chunks: Vec<Chunk>| -> Vec<blake3::Hash> {
+            chunks
+                .iter()
+                .map(|chunk| blake3::hash(chunk.data()))
+                .collect()
+        };
+        let before = hashes(chunk_all(&data).await);
+        let after = hashes(chunk_all(&changed).await);
+        let shared = after.iter().filter(|hash| before.contains(hash)).count();
+        // only the chunk with the insert (and maybe its neighbour) is new
+        assert!(after.len() - shared <= 2, "{} of {}", shared, after.len());
+    }
+}
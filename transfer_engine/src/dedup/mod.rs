@@ -0,0 +1,30 @@
+// Deduplication of the copies.
+// The files are cut where their content says so (a rolling hash of the last bytes) instead
+// of at fixed offsets, so an insert or a delete only changes the chunks around it.
+// Every unique chunk is stored once in the chunk store, named after its blake3 hash,
+// and every copy of the source is a snapshot: the recipes of its entries, the chunks every
+// file is made of. Copying a similar source again only writes the chunks that are new:
+//
+// <store>/chunks/<first 2 hex of the hash>/<hash>
+// <store>/snapshots/<milliseconds since the unix epoch>[-<count within the millisecond>].json
+pub mod chunker;
+pub mod recipe;
+pub mod store;
+pub mod writer;
+
+pub use chunker::ContentChunker;
+pub use recipe::{ChunkRef, Recipe, Snapshot};
+pub use store::ChunkStore;
+pub use writer::{DedupReport, EntryStorer, SnapshotWriter, StoredEntry};
+
+pub const STORE_EXT: &str = "sppd";
+pub const SNAPSHOT_VERSION: u8 = 1;
+/// no chunk is cut before this, except the last one of a file
+pub const MIN_CHUNK_SIZE: usize = 16 * 1024; // 16KB
+/// has to be a power of 2
+pub const AVG_CHUNK_SIZE: usize = 64 * 1024; // 64KB
+pub const MAX_CHUNK_SIZE: usize = 256 * 1024; // 256KB
+/// the most a file being stored holds: what its chunker has read ahead
+pub const FILE_MEMORY: u64 = 3 * MAX_CHUNK_SIZE as u64;
+const CHUNKS_DIR: &str = "chunks";
+const SNAPSHOTS_DIR: &str = "snapshots";
@@ -0,0 +1,109 @@
+use std::{
+    fs::Metadata,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{EntryKind, EntryMeta};
+
+/// A chunk of a file as it is found in the chunk store
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkRef {
+    /// blake3 of the data, in hex
+    pub hash: String,
+    pub len: u64,
+}
+
+/// How to put an entry of the source back together
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recipe {
+    #[serde(flatten)]
+    meta: EntryMeta,
+    len: u64,
+    /// in the order they make up the file, empty for anything but a file
+    chunks: Vec<ChunkRef>,
+}
+
+impl Recipe {
+    /// a recipe with no chunks yet
+    pub fn new(path: String, kind: EntryKind, meta: Option<&Metadata>) -> Self {
+        Self {
+            meta: EntryMeta::new(path, kind, meta),
+            len: 0,
+            chunks: Vec::new(),
+        }
+    }
+
+    pub fn push_chunk(&mut self, hash: String, len: u64) {
+        self.len += len;
+        self.chunks.push(ChunkRef { hash, len });
+    }
+
+    pub fn meta(&self) -> &EntryMeta {
+        &self.meta
+    }
+
+    pub fn path(&self) -> &str {
+        self.meta.path()
+    }
+
+    pub fn kind(&self) -> &EntryKind {
+        self.meta.kind()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn chunks(&self) -> &[ChunkRef] {
+        &self.chunks
+    }
+}
+
+impl AsRef<EntryMeta> for Recipe {
+    fn as_ref(&self) -> &EntryMeta {
+        &self.meta
+    }
+}
+
+/// A copy of the source at one point in time
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    version: u8,
+    /// milliseconds since the unix epoch
+    created: u64,
+    /// parents before their children
+    entries: Vec<Recipe>,
+}
+
+impl Snapshot {
+    pub fn new(version: u8, entries: Vec<Recipe>) -> Self {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis() as u64)
+            .unwrap_or_default();
+
+        Self {
+            version,
+            created,
+            entries,
+        }
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn created(&self) -> u64 {
+        self.created
+    }
+
+    pub fn entries(&self) -> &[Recipe] {
+        &self.entries
+    }
+}
@@ -0,0 +1,382 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use hashbrown::{HashMap, HashSet};
+use parking_lot::Mutex;
+use tokio::{fs::File, io::AsyncWriteExt, sync::OnceCell};
+
+use super::{Recipe, Snapshot, CHUNKS_DIR, SNAPSHOTS_DIR, SNAPSHOT_VERSION};
+use crate::{
+    errnos::{PropErrno, PropErrnoResult},
+    path::{
+        unpack::{UnpackRoot, UnpackSource},
+        PathExt,
+    },
+    utils::strings::StringUtils,
+};
+
+/// The chunks of every snapshot of a source, each stored once under its hash
+/// NOTE: many files can put their chunks at once, a chunk is still only written once
+pub struct ChunkStore {
+    root: PathBuf,
+    /// chunks known to be in the store or being written to it, saves looking them up again.
+    /// whoever puts a chunk that is being written waits for the write
+    known: Mutex<HashMap<String, Arc<OnceCell<()>>>>,
+    /// chunks written since the last snapshot, they reach the disk before it does
+    unsynced: Mutex<HashSet<PathBuf>>,
+}
+
+impl ChunkStore {
+    /// opens the store at `root`, it is created if there is none
+    pub async fn open<P: AsRef<Path>>(root: P) -> PropErrnoResult<Self> {
+        let root = root.as_ref().to_path_buf();
+        for dir in [CHUNKS_DIR, SNAPSHOTS_DIR] {
+            let dir = root.join(dir);
+            PropErrno::from_io_result(tokio::fs::create_dir_all(&dir).await, Some(&dir))?;
+        }
+
+        Ok(Self {
+            root,
+            known: Mutex::new(HashMap::new()),
+            unsynced: Mutex::new(HashSet::new()),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// where the chunk with `hash` is stored
+    /// the hash comes from a snapshot, anything but a hash could point anywhere
+    fn chunk_path(&self, hash: &str) -> PropErrnoResult<PathBuf> {
+        let is_hash = hash.len() == 2 * blake3::OUT_LEN
+            && hash
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+        if !is_hash {
+            log::error!("{}: not the hash of a chunk", hash);
+            return Err(PropErrno::CorruptedFileVal(hash.to_string()));
+        }
+
+        Ok(self.root.join(CHUNKS_DIR).join(&hash[..2]).join(hash))
+    }
+
+    /// stores `data` unless the store already has it
+    /// returns the hash of the data and whether it was new
+    /// NOTE: the hash is only returned once the chunk is stored, a failed write fails
+    /// everyone waiting for it and the next put tries again
+    pub async fn put(&self, data: &[u8]) -> PropErrnoResult<(String, bool)> {
+        let hash = blake3::hash(data).to_hex().to_string();
+        let stored = self.known.lock().entry(hash.clone()).or_default().clone();
+
+        let mut is_new = false;
+        let res = stored
+            .get_or_try_init(|| async {
+                is_new = self.store(&hash, data).await?;
+                Ok(())
+            })
+            .await;
+        res.map(|_| (hash, is_new))
+    }
+
+    /// returns whether the chunk had to be written
+    /// NOTE: a chunk is only checked against its hash when it is read, a chunk with
+    /// the wrong length (a write cut short) is written again
+    async fn store(&self, hash: &str, data: &[u8]) -> PropErrnoResult<bool> {
+        let path = self.chunk_path(hash)?;
+        let is_stored = tokio::fs::metadata(&path)
+            .await
+            .is_ok_and(|meta| meta.len() == data.len() as u64);
+        if !is_stored {
+            write_new(&path, data).await?;
+            self.unsynced.lock().insert(path);
+        }
+        Ok(!is_stored)
+    }
+
+    /// flushes the chunks written since the last snapshot and the names they got
+    async fn sync_chunks(&self) -> PropErrnoResult<()> {
+        let chunks: Vec<PathBuf> = self.unsynced.lock().iter().cloned().collect();
+        for chunk in &chunks {
+            sync_path(chunk).await?;
+        }
+        #[cfg(unix)]
+        {
+            let dirs: HashSet<&Path> = chunks.iter().filter_map(|chunk| chunk.parent()).collect();
+            for dir in dirs {
+                sync_path(dir).await?;
+            }
+        }
+
+        let mut unsynced = self.unsynced.lock();
+        for chunk in &chunks {
+            unsynced.remove(chunk);
+        }
+        Ok(())
+    }
+
+    /// the data of the chunk, checked against its hash
+    pub async fn get(&self, hash: &str) -> PropErrnoResult<Vec<u8>> {
+        let path = self.chunk_path(hash)?;
+        let data = PropErrno::from_io_result(tokio::fs::read(&path).await, Some(&path))?;
+        if blake3::hash(&data).to_hex().as_str() != hash {
+            log::error!("{}: the chunk is corrupted", path.to_string_lossy());
+            return Err(PropErrno::CorruptedFileVal(path.parent_and_current()));
+        }
+        Ok(data)
+    }
+
+    /// saves the snapshot, the chunks of its files have to be stored already
+    /// the chunks reach the disk before the snapshot does, so a crash never leaves
+    /// a snapshot with missing chunks
+    /// NOTE: snapshots created in the same millisecond get a count after the time
+    pub async fn write_snapshot(&self, snapshot: &Snapshot) -> PropErrnoResult<PathBuf> {
+        let dir = self.root.join(SNAPSHOTS_DIR);
+        let tmp = tmp_path(&dir.join(snapshot.created().to_string()));
+        let json = serde_json::to_vec(snapshot).map_err(|err| {
+            log::error!("{}: {}", tmp.to_string_lossy(), err);
+            PropErrno::CompressVal(tmp.parent_and_current())
+        })?;
+
+        self.sync_chunks().await?;
+        let res = async {
+            let mut file = File::create(&tmp).await?;
+            file.write_all(&json).await?;
+            file.sync_all().await
+        };
+        if let Err(err) = res.await {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return Err(PropErrno::from_io_error(&err, Some(&tmp)));
+        }
+
+        // a link fails instead of replacing a snapshot with the same name
+        let mut count = 0;
+        let res = loop {
+            let path = match count {
+                0 => dir.join(format!("{}.json", snapshot.created())),
+                _ => dir.join(format!("{}-{}.json", snapshot.created(), count)),
+            };
+            match tokio::fs::hard_link(&tmp, &path).await {
+                Ok(()) => break Ok(path),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => count += 1,
+                Err(err) => break Err(PropErrno::from_io_error(&err, Some(&path))),
+            }
+        };
+
+        let _ = tokio::fs::remove_file(&tmp).await;
+        #[cfg(unix)]
+        if res.is_ok() {
+            sync_path(&dir).await?;
+        }
+        res
+    }
+
+    /// the snapshots in the store, oldest first
+    pub async fn snapshots(&self) -> PropErrnoResult<Vec<PathBuf>> {
+        let dir = self.root.join(SNAPSHOTS_DIR);
+        let mut read_dir = PropErrno::from_io_result(tokio::fs::read_dir(&dir).await, Some(&dir))?;
+        let mut snapshots = Vec::new();
+        while let Some(entry) = PropErrno::from_io_result(read_dir.next_entry().await, Some(&dir))?
+        {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                snapshots.push(path);
+            }
+        }
+
+        // named after the time they were created and a count within the same millisecond
+        snapshots.sort_by_key(|path| {
+            let stem = path.file_stem().and_then(|stem| stem.to_str())?;
+            let (created, count) = stem.split_once('-').unwrap_or((stem, "0"));
+            Some((created.parse::<u64>().ok()?, count.parse::<u64>().ok()?))
+        });
+        Ok(snapshots)
+    }
+
+    pub async fn read_snapshot<P: AsRef<Path>>(&self, path: P) -> PropErrnoResult<Snapshot> {
+        let path = path.as_ref();
+        let json = PropErrno::from_io_result(tokio::fs::read(path).await, Some(path))?;
+        let snapshot: Snapshot = serde_json::from_slice(&json).map_err(|err| {
+            log::error!("{}: {}", path.to_string_lossy(), err);
+            PropErrno::CorruptedFileVal(path.parent_and_current())
+        })?;
+
+        if snapshot.version() > SNAPSHOT_VERSION {
+            log::error!("{}: written by a newer version", path.to_string_lossy());
+            return Err(PropErrno::UnpackVal(path.parent_and_current()));
+        }
+        Ok(snapshot)
+    }
+
+    /// puts the files of the snapshot back together in `dst`
+    /// an entry that would end up outside of `dst` stops the restore
+    pub async fn restore<P: AsRef<Path>>(
+        &self,
+        snapshot: &Snapshot,
+        dst: P,
+    ) -> PropErrnoResult<()> {
+        let root = UnpackRoot::create(dst).await?;
+        let mut source = self;
+        root.unpack(&mut source, snapshot.entries()).await
+    }
+}
+
+#[async_trait]
+impl UnpackSource for &ChunkStore {
+    type Entry = Recipe;
+
+    async fn write_file(
+        &mut self,
+        recipe: &Recipe,
+        dst: &Path,
+        file: &mut File,
+    ) -> PropErrnoResult<()> {
+        for chunk in recipe.chunks() {
+            let data = self.get(&chunk.hash).await?;
+            if data.len() as u64 != chunk.len {
+                return Err(PropErrno::CorruptedFileVal(dst.parent_and_current()));
+            }
+            PropErrno::from_io_result(file.write_all(&data).await, Some(dst))?;
+        }
+        PropErrno::from_io_result(file.flush().await, Some(dst))
+    }
+}
+
+/// writes the file next to `path` and renames it, so a file at `path` is never half written
+async fn write_new(path: &Path, data: &[u8]) -> PropErrnoResult<()> {
+    if let Some(parent) = path.parent() {
+        PropErrno::from_io_result(tokio::fs::create_dir_all(parent).await, Some(parent))?;
+    }
+
+    let tmp = tmp_path(path);
+    let res = async {
+        tokio::fs::write(&tmp, data).await?;
+        tokio::fs::rename(&tmp, path).await
+    };
+    if let Err(err) = res.await {
+        let _ = tokio::fs::remove_file(&tmp).await;
+        return Err(PropErrno::from_io_error(&err, Some(path)));
+    }
+    Ok(())
+}
+
+/// flushes the file at `path` to the disk, for a directory the names in it
+async fn sync_path(path: &Path) -> PropErrnoResult<()> {
+    let res = async { File::open(path).await?.sync_all().await };
+    PropErrno::from_io_result(res.await, Some(path))
+}
+
+/// a name next to `path` that nothing else writes to
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", String::random(8)));
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{archive::EntryKind, utils::tmp::tmp_dir};
+
+    #[tokio::test]
+    async fn test_put_and_get() {
+        let dir = tmp_dir("transfer_engine_chunk_store");
+        let store = ChunkStore::open(dir.join("store.sppd")).await.unwrap();
+
+        let (hash, is_new) = store.put(b"chunk").await.unwrap();
+        assert!(is_new);
+        assert!(!store.put(b"chunk").await.unwrap().1);
+        assert_eq!(store.get(&hash).await.unwrap(), b"chunk");
+
+        // another handle only finds it on the disk
+        let other = ChunkStore::open(store.root()).await.unwrap();
+        assert!(!other.put(b"chunk").await.unwrap().1);
+
+        // the same chunk put by two files at once is only new to one of them
+        let (first, second) = tokio::join!(store.put(b"twice"), store.put(b"twice"));
+        assert!(first.unwrap().1 ^ second.unwrap().1);
+
+        for hash in ["../../etc/passwd", "abc", &hash.to_uppercase()] {
+            assert!(matches!(
+                store.get(hash).await,
+                Err(PropErrno::CorruptedFileVal(_))
+            ));
+        }
+
+        std::fs::write(store.chunk_path(&hash).unwrap(), b"chang").unwrap();
+        assert!(matches!(
+            store.get(&hash).await,
+            Err(PropErrno::CorruptedFileVal(_))
+        ));
+
+        // a write that was cut short is written again
+        std::fs::write(store.chunk_path(&hash).unwrap(), b"ch").unwrap();
+        let other = ChunkStore::open(store.root()).await.unwrap();
+        assert!(other.put(b"chunk").await.unwrap().1);
+        assert_eq!(store.get(&hash).await.unwrap(), b"chunk");
+    }
+
+    #[tokio::test]
+    async fn test_failed_put() {
+        let dir = tmp_dir("transfer_engine_chunk_store_failed");
+        let store = ChunkStore::open(dir.join("store.sppd")).await.unwrap();
+        let hash = blake3::hash(b"chunk").to_hex().to_string();
+
+        // the directory of the chunk can't be created
+        let chunks = dir.join("store.sppd").join(CHUNKS_DIR);
+        std::fs::write(chunks.join(&hash[..2]), b"").unwrap();
+        let (first, second) = tokio::join!(store.put(b"chunk"), store.put(b"chunk"));
+        assert!(first.is_err());
+        assert!(second.is_err());
+
+        std::fs::remove_file(chunks.join(&hash[..2])).unwrap();
+        assert!(store.put(b"chunk").await.unwrap().1);
+        assert_eq!(store.get(&hash).await.unwrap(), b"chunk");
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_names() {
+        let dir = tmp_dir("transfer_engine_chunk_store_snapshots");
+        let store = ChunkStore::open(dir.join("store.sppd")).await.unwrap();
+
+        // created in the same millisecond
+        let snapshot = Snapshot::new(SNAPSHOT_VERSION, Vec::new());
+        let mut paths = Vec::new();
+        for _ in 0..3 {
+            paths.push(store.write_snapshot(&snapshot).await.unwrap());
+        }
+
+        assert_eq!(store.snapshots().await.unwrap(), paths);
+        for path in &paths {
+            assert_eq!(&store.read_snapshot(path).await.unwrap(), &snapshot);
+        }
+        let names = std::fs::read_dir(dir.join("store.sppd").join(SNAPSHOTS_DIR))
+            .unwrap()
+            .count();
+        assert_eq!(names, 3);
+    }
+
+    #[tokio::test]
+    async fn test_restore_stays_in_dst() {
+        let dir = tmp_dir("transfer_engine_chunk_store_restore");
+        let store = ChunkStore::open(dir.join("store.sppd")).await.unwrap();
+        let (hash, _) = store.put(b"pwned").await.unwrap();
+
+        for name in ["../escape", "/tmp/escape", "a/../../escape"] {
+            let mut recipe = Recipe::new(name.to_string(), EntryKind::File, None);
+            recipe.push_chunk(hash.clone(), 5);
+            let snapshot = Snapshot::new(SNAPSHOT_VERSION, vec![recipe]);
+            let res = store.restore(&snapshot, dir.join("dst")).await;
+            assert!(
+                matches!(res, Err(PropErrno::UnpackOutofDirVal(_))),
+                "{}",
+                name
+            );
+        }
+        assert!(!dir.join("escape").exists());
+    }
+}
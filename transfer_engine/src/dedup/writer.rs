@@ -0,0 +1,278 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Serialize;
+use tokio::fs::File;
+
+use super::{ChunkStore, ContentChunker, Recipe, Snapshot, SNAPSHOT_VERSION};
+use crate::{
+    archive::{entry::entry_name, EntryKind},
+    errnos::{PropErrno, PropErrnoResult},
+    path::PathExt,
+    shared::{progress::ProgressProcessedFn, throttle::RateLimiter},
+};
+
+/// How much of a snapshot was already in the store
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DedupReport {
+    pub files: u64,
+    pub chunks: u64,
+    /// chunks that were not in the store yet
+    pub new_chunks: u64,
+    pub bytes: u64,
+    /// bytes written to the store, the rest was already there
+    pub new_bytes: u64,
+}
+
+impl DedupReport {
+    fn add(&mut self, other: &DedupReport) {
+        self.files += other.files;
+        self.chunks += other.chunks;
+        self.new_chunks += other.new_chunks;
+        self.bytes += other.bytes;
+        self.new_bytes += other.new_bytes;
+    }
+}
+
+/// The recipe of an entry and how much of it was new to the store
+pub type StoredEntry = (Recipe, DedupReport);
+
+/// Adds the entries of a snapshot to a chunk store
+/// NOTE: the snapshot is not in the store until `finish` writes it, the chunks are
+pub struct SnapshotWriter {
+    storer: EntryStorer,
+    entries: Vec<Recipe>,
+    report: DedupReport,
+}
+
+impl SnapshotWriter {
+    /// the store is created if there is none
+    pub async fn create<P: AsRef<Path>>(store: P) -> PropErrnoResult<Self> {
+        Ok(Self {
+            storer: EntryStorer {
+                store: Arc::new(ChunkStore::open(store).await?),
+                processed_cb: None,
+                limiter: RateLimiter::default(),
+            },
+            entries: Vec::new(),
+            report: DedupReport::default(),
+        })
+    }
+
+    /// called with the bytes of the sources as they are read
+    pub fn set_processed_cb(mut self, processed_cb: Option<ProgressProcessedFn>) -> Self {
+        self.storer.processed_cb = processed_cb;
+        self
+    }
+
+    /// the new chunks are written no faster than the limiter allows
+    pub fn set_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.storer.limiter = limiter;
+        self
+    }
+
+    pub fn store(&self) -> &ChunkStore {
+        &self.storer.store
+    }
+
+    pub fn report(&self) -> &DedupReport {
+        &self.report
+    }
+
+    /// stores the entries without the writer, so many files can be stored at once
+    /// their recipes are `push`ed in the order they should be in the snapshot
+    pub fn storer(&self) -> EntryStorer {
+        self.storer.clone()
+    }
+
+    /// adds an entry stored by the `storer`
+    pub fn push(&mut self, (recipe, report): StoredEntry) {
+        self.report.add(&report);
+        self.entries.push(recipe);
+    }
+
+    /// # Arguments
+    /// * `name` - the path of the directory in the snapshot
+    /// * `src` - the directory its metadata is taken from
+    pub async fn add_dir<P: AsRef<Path>>(&mut self, name: P, src: P) -> PropErrnoResult<()> {
+        let stored = self.storer.dir(name, src).await?;
+        self.push(stored);
+        Ok(())
+    }
+
+    /// the link itself is stored, not what it points to
+    pub async fn add_symlink<P: AsRef<Path>>(&mut self, name: P, src: P) -> PropErrnoResult<()> {
+        let stored = self.storer.symlink(name, src).await?;
+        self.push(stored);
+        Ok(())
+    }
+
+    /// cuts the file into chunks and stores the ones the store doesn't have
+    /// # Arguments
+    /// * `name` - the path of the file in the snapshot
+    /// * `src` - the file to read
+    pub async fn add_file<P: AsRef<Path>>(&mut self, name: P, src: P) -> PropErrnoResult<()> {
+        let stored = self.storer.file(name, src).await?;
+        self.push(stored);
+        Ok(())
+    }
+
+    /// writes the snapshot, returns where it was written
+    pub async fn finish(self) -> PropErrnoResult<(PathBuf, DedupReport)> {
+        let snapshot = Snapshot::new(SNAPSHOT_VERSION, self.entries);
+        let path = self.storer.store.write_snapshot(&snapshot).await?;
+        Ok((path, self.report))
+    }
+}
+
+/// Puts the chunks of the entries in the store and gives back their recipes
+/// NOTE: cloning gives another handle to the same store
+#[derive(Clone)]
+pub struct EntryStorer {
+    store: Arc<ChunkStore>,
+    processed_cb: Option<ProgressProcessedFn>,
+    limiter: RateLimiter,
+}
+
+impl EntryStorer {
+    fn name<P: AsRef<Path>>(&self, name: P) -> PropErrnoResult<String> {
+        entry_name(name.as_ref()).ok_or_else(|| {
+            log::error!("{}: not a relative path", name.as_ref().to_string_lossy());
+            PropErrno::WriteVal(name.as_ref().parent_and_current())
+        })
+    }
+
+    pub async fn dir<P: AsRef<Path>>(&self, name: P, src: P) -> PropErrnoResult<StoredEntry> {
+        let name = self.name(name)?;
+        let meta = tokio::fs::metadata(src.as_ref()).await.ok();
+        let recipe = Recipe::new(name, EntryKind::Dir, meta.as_ref());
+        Ok((recipe, DedupReport::default()))
+    }
+
+    pub async fn symlink<P: AsRef<Path>>(&self, name: P, src: P) -> PropErrnoResult<StoredEntry> {
+        let name = self.name(name)?;
+        let target = PropErrno::from_io_result(
+            tokio::fs::read_link(src.as_ref()).await,
+            Some(src.as_ref()),
+        )?;
+        let meta = tokio::fs::symlink_metadata(src.as_ref()).await.ok();
+        let kind = EntryKind::Symlink(target.to_string_lossy().into_owned());
+        Ok((
+            Recipe::new(name, kind, meta.as_ref()),
+            DedupReport::default(),
+        ))
+    }
+
+    pub async fn file<P: AsRef<Path>>(&self, name: P, src: P) -> PropErrnoResult<StoredEntry> {
+        let name = self.name(name)?;
+        let src = src.as_ref();
+        let file = PropErrno::from_io_result(File::open(src).await, Some(src))?;
+        let meta = file.metadata().await.ok();
+        let mut recipe = Recipe::new(name, EntryKind::File, meta.as_ref());
+        let mut report = DedupReport {
+            files: 1,
+            ..Default::default()
+        };
+
+        let mut chunker = ContentChunker::new(file);
+        while let Some(chunk) = PropErrno::from_io_result(chunker.next().await, Some(src))? {
+            let (hash, is_new) = self.store.put(chunk.data()).await?;
+            report.chunks += 1;
+            report.bytes += chunk.size();
+            if is_new {
+                report.new_chunks += 1;
+                report.new_bytes += chunk.size();
+                self.limiter.consume(chunk.size());
+                self.limiter.wait().await;
+            }
+            recipe.push_chunk(hash, chunk.size());
+            if let Some(processed_cb) = &self.processed_cb {
+                processed_cb(chunk.size());
+            }
+        }
+
+        Ok((recipe, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tmp::tmp_dir;
+
+    /// the same bytes on every run, they don't compress or repeat
+    fn data(len: usize) -> Vec<u8> {
+        let mut state: u64 = 7;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    async fn snapshot(src: &Path, store: &Path) -> (PathBuf, DedupReport) {
+        let mut writer = SnapshotWriter::create(store).await.unwrap();
+        writer.add_dir(Path::new("src"), src).await.unwrap();
+        for name in ["image", "copy", "empty"] {
+            writer
+                .add_file(Path::new("src").join(name), src.join(name))
+                .await
+                .unwrap();
+        }
+        writer.finish().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_share_chunks() {
+        let dir = tmp_dir("transfer_engine_snapshot_writer");
+        let src = dir.join("src");
+        let store = dir.join("src.sppd");
+        std::fs::create_dir_all(&src).unwrap();
+        let mut image = data(3 * 1024 * 1024);
+        std::fs::write(src.join("image"), &image).unwrap();
+        std::fs::write(src.join("copy"), &image).unwrap();
+        std::fs::write(src.join("empty"), []).unwrap();
+
+        // the copy is all duplicates
+        let (first, report) = snapshot(&src, &store).await;
+        assert_eq!(report.files, 3);
+        assert_eq!(report.bytes, 2 * image.len() as u64);
+        assert_eq!(report.new_bytes, image.len() as u64);
+        assert_eq!(report.new_chunks * 2, report.chunks);
+
+        // a few bytes changed in the middle of the image
+        image.splice(
+            1024 * 1024..1024 * 1024 + 10,
+            b"a new part of the image".iter().copied(),
+        );
+        std::fs::write(src.join("image"), &image).unwrap();
+        let (second, report) = snapshot(&src, &store).await;
+        assert!(report.new_chunks <= 2, "{:?}", report);
+        assert!(report.new_bytes < report.bytes / 10);
+
+        let store = ChunkStore::open(&store).await.unwrap();
+        assert_eq!(
+            store.snapshots().await.unwrap(),
+            vec![first.clone(), second]
+        );
+
+        // the first snapshot still restores the old image
+        let snapshot = store.read_snapshot(&first).await.unwrap();
+        let dst = dir.join("restored");
+        store.restore(&snapshot, &dst).await.unwrap();
+        assert_eq!(
+            std::fs::read(dst.join("src/image")).unwrap(),
+            data(3 * 1024 * 1024)
+        );
+        assert_eq!(
+            std::fs::read(dst.join("src/copy")).unwrap(),
+            data(3 * 1024 * 1024)
+        );
+        assert!(std::fs::read(dst.join("src/empty")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    io::{Error as IOError, Result as IOResult},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use chacha20poly1305::{aead::stream::DecryptorBE32, XChaCha20Poly1305};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use super::{EncryptionHeader, EncryptionKey, CHUNK_SIZE, HEADER_LEN, TAG_LEN};
+use crate::errnos::{PropErrno, PropErrnoResult};
+
+/// a chunk as it is stored
+const SEALED_CHUNK: usize = CHUNK_SIZE + TAG_LEN;
+
+/// Decrypts a stream written by `EncryptWriter`
+/// nothing is returned from a chunk until it is authenticated
+pub struct DecryptReader<R> {
+    reader: R,
+    /// authenticated with every chunk
+    header: [u8; HEADER_LEN],
+    /// taken by the last chunk
+    decryptor: Option<DecryptorBE32<XChaCha20Poly1305>>,
+    /// read but not opened yet
+    sealed: Vec<u8>,
+    /// opened but not returned yet
+    plain: Vec<u8>,
+    pos: usize,
+    /// a wrong password fails the first chunk, a later failure means the data changed
+    is_opened: bool,
+    is_eof: bool,
+    /// the stream can't go on after a chunk failed
+    failed: Option<PropErrno>,
+}
+
+impl<R: AsyncRead + Unpin> DecryptReader<R> {
+    /// reads the header and derives the key from the password
+    pub async fn open(mut reader: R, password: &str) -> PropErrnoResult<Self> {
+        let header = EncryptionHeader::read(&mut reader).await?;
+        let key = EncryptionKey::from_header(password, &header).await?;
+        Ok(Self::new(reader, &header, &key))
+    }
+
+    /// the `header` was already read from `reader`
+    /// the key is only checked by the first chunk
+    pub fn new(reader: R, header: &EncryptionHeader, key: &EncryptionKey) -> Self {
+        let decryptor =
+            DecryptorBE32::from_aead(key.cipher().clone(), header.nonce().as_slice().into());
+
+        Self {
+            reader,
+            header: header.bytes(),
+            decryptor: Some(decryptor),
+            sealed: Vec::with_capacity(SEALED_CHUNK + 1),
+            plain: Vec::new(),
+            pos: 0,
+            is_opened: false,
+            is_eof: false,
+            failed: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// reads until there is a chunk and a byte after it, which tells whether it is the last
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        while !self.is_eof && self.sealed.len() <= SEALED_CHUNK {
+            let filled = self.sealed.len();
+            self.sealed.resize(SEALED_CHUNK + 1, 0);
+            let mut buf = ReadBuf::new(&mut self.sealed[filled..]);
+            let res = Pin::new(&mut self.reader).poll_read(cx, &mut buf);
+            let n = buf.filled().len();
+            self.sealed.truncate(filled + n);
+
+            ready!(res)?;
+            self.is_eof = n == 0;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn open_chunk(&mut self) -> IOResult<()> {
+        let res = match self.decryptor.take() {
+            // the stream ends with the last chunk, one that isn't marked as such was cut off
+            Some(decryptor) if self.sealed.len() <= SEALED_CHUNK => {
+                let res = decryptor.decrypt_last_in_place(&self.header, &mut self.sealed);
+                self.plain = std::mem::take(&mut self.sealed);
+                res
+            }
+            Some(mut decryptor) => {
+                let rest = self.sealed.split_off(SEALED_CHUNK);
+                let res = decryptor.decrypt_next_in_place(&self.header, &mut self.sealed);
+                self.decryptor = Some(decryptor);
+                self.plain = std::mem::replace(&mut self.sealed, rest);
+                res
+            }
+            None => return Ok(()),
+        };
+        self.pos = 0;
+
+        if res.is_err() {
+            self.plain.clear();
+            let err = if self.is_opened {
+                log::error!("a chunk failed to authenticate");
+                PropErrno::CorruptedFile
+            } else {
+                log::error!("the first chunk failed to authenticate");
+                PropErrno::InvalidPassOrCorrupt
+            };
+            self.decryptor = None;
+            self.failed = Some(err.clone());
+            return Err(IOError::other(err));
+        }
+        self.is_opened = true;
+        Ok(())
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for DecryptReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<IOResult<()>> {
+        let this = self.get_mut();
+        if let Some(err) = &this.failed {
+            return Poll::Ready(Err(IOError::other(err.clone())));
+        }
+
+        while this.pos == this.plain.len() {
+            if this.decryptor.is_none() {
+                // the last chunk was returned
+                return Poll::Ready(Ok(()));
+            }
+            ready!(this.poll_fill(cx))?;
+            this.open_chunk()?;
+        }
+
+        let n = buf.remaining().min(this.plain.len() - this.pos);
+        buf.put_slice(&this.plain[this.pos..this.pos + n]);
+        this.pos += n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compression::algorithm::{Algorithm, ReadAlgorithm, WriteAlgorithm},
+        encryption::{EncryptWriter, KdfParams},
+        shared::performance::Performance,
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const PASSWORD: &str = "correct horse battery";
+
+    /// argon2 with the default costs is too slow for the tests
+    async fn test_key() -> EncryptionKey {
+        EncryptionKey::from_password_with(PASSWORD, KdfParams::new(64, 1, 1))
+            .await
+            .unwrap()
+    }
+
+    async fn encrypt(key: &EncryptionKey, data: &[u8]) -> Vec<u8> {
+        let mut writer = EncryptWriter::new(Vec::new(), key);
+        writer.write_all(data).await.unwrap();
+        writer.shutdown().await.unwrap();
+        writer.into_inner()
+    }
+
+    async fn decrypt(sealed: &[u8], password: &str) -> PropErrnoResult<Vec<u8>> {
+        let mut reader = DecryptReader::open(sealed, password).await?;
+        let mut data = Vec::new();
+        let res = reader.read_to_end(&mut data).await;
+        PropErrno::from_io_result(res, None)?;
+        Ok(data)
+    }
+
+    #[tokio::test]
+    async fn test_roundtrip() {
+        let key = test_key().await;
+        for len in [
+            0,
+            1,
+            CHUNK_SIZE - 1,
+            CHUNK_SIZE,
+            CHUNK_SIZE + 1,
+            3 * CHUNK_SIZE,
+        ] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let sealed = encrypt(&key, &data).await;
+            assert_eq!(
+                sealed.len(),
+                HEADER_LEN + len + len.div_ceil(CHUNK_SIZE).max(1) * TAG_LEN
+            );
+            assert_eq!(decrypt(&sealed, PASSWORD).await.unwrap(), data);
+        }
+
+        // the same key never reuses a nonce
+        assert_ne!(encrypt(&key, b"data").await, encrypt(&key, b"data").await);
+    }
+
+    #[tokio::test]
+    async fn test_compressed_roundtrip() {
+        let key = test_key().await;
+        let data = "the quick brown fox jumps over the lazy dog. ".repeat(4096);
+
+        let writer = EncryptWriter::new(Vec::new(), &key);
+        let mut encoder =
+            WriteAlgorithm::from_algorithm(&Algorithm::Zstd, writer, &Performance::Fast);
+        encoder.write_all(data.as_bytes()).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        let sealed = match encoder {
+            WriteAlgorithm::Zstd(encoder) => encoder.into_inner().into_inner(),
+            _ => unreachable!(),
+        };
+        assert!(sealed.len() < data.len());
+
+        let reader = DecryptReader::open(sealed.as_slice(), PASSWORD)
+            .await
+            .unwrap();
+        let mut decoder = ReadAlgorithm::from_algorithm(&Algorithm::Zstd, reader);
+        let mut out = String::new();
+        decoder.read_to_string(&mut out).await.unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_password_or_changed_data() {
+        let key = test_key().await;
+        let data = vec![7u8; 2 * CHUNK_SIZE + 100];
+        let sealed = encrypt(&key, &data).await;
+
+        assert_eq!(
+            decrypt(&sealed, "wrong password").await,
+            Err(PropErrno::InvalidPassOrCorrupt)
+        );
+        assert_eq!(
+            decrypt(&sealed, "short").await,
+            Err(PropErrno::PasswordLength(8, 128))
+        );
+
+        // the header is authenticated too
+        let mut changed = sealed.clone();
+        changed[HEADER_LEN - 1] ^= 1;
+        assert_eq!(
+            decrypt(&changed, PASSWORD).await,
+            Err(PropErrno::InvalidPassOrCorrupt)
+        );
+
+        let mut changed = sealed.clone();
+        changed[HEADER_LEN + SEALED_CHUNK + 10] ^= 1;
+        assert_eq!(
+            decrypt(&changed, PASSWORD).await,
+            Err(PropErrno::CorruptedFile)
+        );
+
+        // cut off at the end of a chunk, or in the middle of one
+        for len in [HEADER_LEN + 2 * SEALED_CHUNK, sealed.len() - 1] {
+            assert_eq!(
+                decrypt(&sealed[..len], PASSWORD).await,
+                Err(PropErrno::CorruptedFile)
+            );
+        }
+
+        assert_eq!(
+            decrypt(&sealed[..HEADER_LEN - 1], PASSWORD).await,
+            Err(PropErrno::Decrypt)
+        );
+        assert_eq!(decrypt(&data, PASSWORD).await, Err(PropErrno::Decrypt));
+    }
+}
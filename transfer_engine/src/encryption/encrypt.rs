@@ -0,0 +1,132 @@
+use std::{
+    io::{Error as IOError, ErrorKind, Result as IOResult},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use chacha20poly1305::{aead::stream::EncryptorBE32, XChaCha20Poly1305};
+use tokio::io::AsyncWrite;
+
+use super::{EncryptionKey, CHUNK_SIZE, HEADER_LEN, NONCE_LEN, TAG_LEN};
+use crate::errnos::PropErrno;
+
+/// Encrypts everything written to it into `writer`
+/// NOTE: the last chunk is only written by `shutdown`, without it the stream can't be decrypted
+pub struct EncryptWriter<W> {
+    writer: W,
+    /// authenticated with every chunk
+    header: [u8; HEADER_LEN],
+    /// taken by the last chunk
+    encryptor: Option<EncryptorBE32<XChaCha20Poly1305>>,
+    /// data waiting for a full chunk
+    plain: Vec<u8>,
+    /// sealed bytes not written yet, the header goes first
+    sealed: Vec<u8>,
+    pos: usize,
+}
+
+impl<W: AsyncWrite + Unpin> EncryptWriter<W> {
+    pub fn new(writer: W, key: &EncryptionKey) -> Self {
+        let header = key.new_header();
+        let encryptor =
+            EncryptorBE32::from_aead(key.cipher().clone(), header.nonce().as_slice().into());
+
+        Self {
+            writer,
+            header: header.bytes(),
+            encryptor: Some(encryptor),
+            plain: Vec::with_capacity(CHUNK_SIZE + TAG_LEN),
+            sealed: header.bytes().to_vec(),
+            pos: 0,
+        }
+    }
+
+    /// the nonce of the stream, it tells the streams of a key apart
+    pub fn nonce(&self) -> [u8; NONCE_LEN] {
+        // SAFE because the nonce is the end of the header
+        self.header[HEADER_LEN - NONCE_LEN..].try_into().unwrap()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// writes out whatever was sealed
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        while self.pos < self.sealed.len() {
+            let n = ready!(Pin::new(&mut self.writer).poll_write(cx, &self.sealed[self.pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(ErrorKind::WriteZero.into()));
+            }
+            self.pos += n;
+        }
+
+        self.sealed.clear();
+        self.pos = 0;
+        Poll::Ready(Ok(()))
+    }
+
+    /// seals the buffered data into a chunk, it has to be drained first
+    fn seal(&mut self, last: bool) -> IOResult<()> {
+        let res = match (last, self.encryptor.take()) {
+            (true, Some(encryptor)) => {
+                encryptor.encrypt_last_in_place(&self.header, &mut self.plain)
+            }
+            (false, Some(mut encryptor)) => {
+                let res = encryptor.encrypt_next_in_place(&self.header, &mut self.plain);
+                self.encryptor = Some(encryptor);
+                res
+            }
+            (_, None) => return Err(IOError::other(PropErrno::Encrypt)),
+        };
+        res.map_err(|_| {
+            log::error!("failed to seal a chunk");
+            IOError::other(PropErrno::Encrypt)
+        })?;
+
+        std::mem::swap(&mut self.plain, &mut self.sealed);
+        Ok(())
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for EncryptWriter<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IOResult<usize>> {
+        let this = self.get_mut();
+        loop {
+            ready!(this.poll_drain(cx))?;
+            if this.encryptor.is_none() {
+                // already shut down
+                return Poll::Ready(Err(IOError::other(PropErrno::Encrypt)));
+            }
+
+            // a full chunk is only sealed once more comes, the last chunk is marked as such
+            if this.plain.len() < CHUNK_SIZE {
+                let n = buf.len().min(CHUNK_SIZE - this.plain.len());
+                this.plain.extend_from_slice(&buf[..n]);
+                return Poll::Ready(Ok(n));
+            }
+            this.seal(false)?;
+        }
+    }
+
+    /// NOTE: data that doesn't fill a chunk yet stays buffered
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        if this.encryptor.is_some() {
+            this.seal(true)?;
+            ready!(this.poll_drain(cx))?;
+        }
+        Pin::new(&mut this.writer).poll_shutdown(cx)
+    }
+}
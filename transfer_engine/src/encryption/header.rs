@@ -0,0 +1,90 @@
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{KdfParams, ENCRYPTION_MAGIC, ENCRYPTION_VERSION, HEADER_LEN, NONCE_LEN, SALT_LEN};
+use crate::errnos::{PropErrno, PropErrnoResult};
+
+/// The start of an encrypted stream, what is needed to derive its key again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptionHeader {
+    params: KdfParams,
+    salt: [u8; SALT_LEN],
+    nonce: [u8; NONCE_LEN],
+}
+
+impl EncryptionHeader {
+    pub fn new(params: KdfParams, salt: [u8; SALT_LEN], nonce: [u8; NONCE_LEN]) -> Self {
+        Self {
+            params,
+            salt,
+            nonce,
+        }
+    }
+
+    /// reads the header at the start of `reader`
+    pub async fn read<R: AsyncRead + Unpin>(reader: &mut R) -> PropErrnoResult<Self> {
+        let mut bytes = [0; HEADER_LEN];
+        // too short to be encrypted by us
+        reader
+            .read_exact(&mut bytes)
+            .await
+            .map_err(|_| PropErrno::Decrypt)?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8; HEADER_LEN]) -> PropErrnoResult<Self> {
+        let magic_len = ENCRYPTION_MAGIC.len();
+        if bytes[..magic_len] != ENCRYPTION_MAGIC {
+            log::error!("the stream is not encrypted");
+            return Err(PropErrno::Decrypt);
+        }
+        if bytes[magic_len] != ENCRYPTION_VERSION {
+            log::error!("the stream was encrypted by a newer version");
+            return Err(PropErrno::Decrypt);
+        }
+
+        let u32_at = |at: usize| u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap());
+        let params = KdfParams::new(
+            u32_at(magic_len + 1),
+            u32_at(magic_len + 5),
+            u32_at(magic_len + 9),
+        );
+        if !params.is_sane() {
+            log::error!("the key derivation costs are too high: {:?}", params);
+            return Err(PropErrno::Decrypt);
+        }
+
+        let salt_at = magic_len + 13;
+        let nonce_at = salt_at + SALT_LEN;
+        Ok(Self {
+            params,
+            salt: bytes[salt_at..nonce_at].try_into().unwrap(),
+            nonce: bytes[nonce_at..].try_into().unwrap(),
+        })
+    }
+
+    pub fn bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        let magic_len = ENCRYPTION_MAGIC.len();
+        bytes[..magic_len].copy_from_slice(&ENCRYPTION_MAGIC);
+        bytes[magic_len] = ENCRYPTION_VERSION;
+        bytes[magic_len + 1..magic_len + 5].copy_from_slice(&self.params.m_cost().to_be_bytes());
+        bytes[magic_len + 5..magic_len + 9].copy_from_slice(&self.params.t_cost().to_be_bytes());
+        bytes[magic_len + 9..magic_len + 13].copy_from_slice(&self.params.p_cost().to_be_bytes());
+        let salt_at = magic_len + 13;
+        bytes[salt_at..salt_at + SALT_LEN].copy_from_slice(&self.salt);
+        bytes[salt_at + SALT_LEN..].copy_from_slice(&self.nonce);
+        bytes
+    }
+
+    pub fn params(&self) -> &KdfParams {
+        &self.params
+    }
+
+    pub fn salt(&self) -> &[u8; SALT_LEN] {
+        &self.salt
+    }
+
+    pub fn nonce(&self) -> &[u8; NONCE_LEN] {
+        &self.nonce
+    }
+}
@@ -0,0 +1,299 @@
+use argon2::{Algorithm as Argon2Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{
+        stream::{DecryptorBE32, EncryptorBE32},
+        Payload,
+    },
+    KeyInit, XChaCha20Poly1305,
+};
+use rand::{rngs::OsRng, RngCore};
+
+use super::{
+    EncryptionHeader, HEADER_LEN, MAC_LEN, MAX_PASSWORD_LEN, MIN_PASSWORD_LEN, NONCE_LEN, SALT_LEN,
+};
+use crate::errnos::{PropErrno, PropErrnoResult};
+
+/// The costs of Argon2id, they are stored in the header so they can change
+/// without breaking what was already encrypted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// memory in KiB
+    m_cost: u32,
+    /// iterations
+    t_cost: u32,
+    /// lanes
+    p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KdfParams {
+    /// a header asking for more than this build writes was not written by us,
+    /// deriving its key would only burn memory and time
+    const MAX_M_COST: u32 = Params::DEFAULT_M_COST;
+    const MAX_T_COST: u32 = Params::DEFAULT_T_COST;
+    const MAX_P_COST: u32 = Params::DEFAULT_P_COST;
+
+    /// the key is not derived from a password but wrapped to public keys
+    pub fn none() -> Self {
+        Self::new(0, 0, 0)
+    }
+
+    pub fn is_none(&self) -> bool {
+        *self == Self::none()
+    }
+
+    pub fn new(m_cost: u32, t_cost: u32, p_cost: u32) -> Self {
+        Self {
+            m_cost,
+            t_cost,
+            p_cost,
+        }
+    }
+
+    pub fn m_cost(&self) -> u32 {
+        self.m_cost
+    }
+
+    pub fn t_cost(&self) -> u32 {
+        self.t_cost
+    }
+
+    pub fn p_cost(&self) -> u32 {
+        self.p_cost
+    }
+
+    /// whether deriving a key with these costs is reasonable
+    pub fn is_sane(&self) -> bool {
+        self.m_cost <= Self::MAX_M_COST
+            && self.t_cost <= Self::MAX_T_COST
+            && self.p_cost <= Self::MAX_P_COST
+    }
+}
+
+/// A key derived from a password
+/// derive it once and use it for every stream, each stream gets its own nonce
+#[derive(Clone)]
+pub struct EncryptionKey {
+    cipher: XChaCha20Poly1305,
+    params: KdfParams,
+    salt: [u8; SALT_LEN],
+}
+
+impl EncryptionKey {
+    /// a key with a new random salt
+    pub async fn from_password(password: &str) -> PropErrnoResult<Self> {
+        Self::from_password_with(password, KdfParams::default()).await
+    }
+
+    pub async fn from_password_with(password: &str, params: KdfParams) -> PropErrnoResult<Self> {
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive(password, params, salt).await
+    }
+
+    /// the key a stream with `header` was encrypted with, if the password is right
+    pub async fn from_header(password: &str, header: &EncryptionHeader) -> PropErrnoResult<Self> {
+        if header.params().is_none() {
+            log::error!("the stream is encrypted to public keys, not with a password");
+            return Err(PropErrno::Decrypt);
+        }
+        Self::derive(password, *header.params(), *header.salt()).await
+    }
+
+    /// a key that is not derived, the salt of its streams is the `id` of the key
+    pub(super) fn from_data_key(key: &[u8; 32], id: [u8; SALT_LEN]) -> Self {
+        Self {
+            cipher: XChaCha20Poly1305::new(key.into()),
+            params: KdfParams::none(),
+            salt: id,
+        }
+    }
+
+    async fn derive(
+        password: &str,
+        params: KdfParams,
+        salt: [u8; SALT_LEN],
+    ) -> PropErrnoResult<Self> {
+        check_password(password)?;
+        let password = password.as_bytes().to_vec();
+
+        // argon2 takes a while on purpose, it can't block the runtime
+        let cipher = tokio::task::spawn_blocking(move || {
+            let argon2_params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(32))?;
+            let argon2 = Argon2::new(Argon2Algorithm::Argon2id, Version::V0x13, argon2_params);
+            let mut key = [0; 32];
+            argon2.hash_password_into(&password, &salt, &mut key)?;
+            Ok::<_, argon2::Error>(XChaCha20Poly1305::new(&key.into()))
+        })
+        .await
+        .map_err(|_| PropErrno::PasswordInterpolation)?
+        .map_err(|err| {
+            log::error!("failed to derive the key: {}", err);
+            PropErrno::PasswordInterpolation
+        })?;
+
+        Ok(Self {
+            cipher,
+            params,
+            salt,
+        })
+    }
+
+    /// whether this key was derived for the stream with `header`
+    pub fn matches(&self, header: &EncryptionHeader) -> bool {
+        self.salt == *header.salt() && self.params == *header.params()
+    }
+
+    /// the header of a new stream, with a new random nonce
+    pub fn new_header(&self) -> EncryptionHeader {
+        let mut nonce = [0; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        EncryptionHeader::new(self.params, self.salt, nonce)
+    }
+
+    /// authenticates `data` without encrypting it, only this key can make the same mac
+    pub fn mac(&self, data: &[u8]) -> PropErrnoResult<[u8; MAC_LEN]> {
+        let header = self.new_header();
+        let encryptor =
+            EncryptorBE32::from_aead(self.cipher.clone(), header.nonce().as_slice().into());
+        let aad = [header.bytes().as_slice(), data].concat();
+        let tag = encryptor
+            .encrypt_last(Payload {
+                msg: &[],
+                aad: &aad,
+            })
+            .map_err(|_| {
+                log::error!("failed to seal the mac");
+                PropErrno::Encrypt
+            })?;
+
+        let mut mac = [0; MAC_LEN];
+        mac[..HEADER_LEN].copy_from_slice(&header.bytes());
+        mac[HEADER_LEN..].copy_from_slice(&tag);
+        Ok(mac)
+    }
+
+    /// whether `mac` was made by this key for `data`
+    pub fn verify_mac(&self, data: &[u8], mac: &[u8; MAC_LEN]) -> bool {
+        let header = match EncryptionHeader::from_bytes(mac[..HEADER_LEN].try_into().unwrap()) {
+            Ok(header) if self.matches(&header) => header,
+            _ => return false,
+        };
+        let decryptor =
+            DecryptorBE32::from_aead(self.cipher.clone(), header.nonce().as_slice().into());
+        let aad = [&mac[..HEADER_LEN], data].concat();
+        let payload = Payload {
+            msg: &mac[HEADER_LEN..],
+            aad: &aad,
+        };
+        decryptor.decrypt_last(payload).is_ok()
+    }
+
+    pub(super) fn cipher(&self) -> &XChaCha20Poly1305 {
+        &self.cipher
+    }
+}
+
+/// the password has to be between `MIN_PASSWORD_LEN` and `MAX_PASSWORD_LEN` characters
+pub fn check_password(password: &str) -> PropErrnoResult<()> {
+    let len = password.chars().count();
+    if len < MIN_PASSWORD_LEN as usize || len > MAX_PASSWORD_LEN as usize {
+        return Err(PropErrno::PasswordLength(
+            MIN_PASSWORD_LEN,
+            MAX_PASSWORD_LEN,
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_password() {
+        assert!(check_password("12345678").is_ok());
+        // characters, not bytes
+        assert!(check_password("ğğğğğğğğ").is_ok());
+        assert!(check_password(&"a".repeat(MAX_PASSWORD_LEN as usize)).is_ok());
+        for password in ["", "1234567", &"a".repeat(MAX_PASSWORD_LEN as usize + 1)] {
+            assert_eq!(
+                check_password(password),
+                Err(PropErrno::PasswordLength(
+                    MIN_PASSWORD_LEN,
+                    MAX_PASSWORD_LEN
+                ))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_key_matches_header() {
+        let params = KdfParams::new(64, 1, 1);
+        let key = EncryptionKey::from_password_with("password", params)
+            .await
+            .unwrap();
+        let header = key.new_header();
+        assert!(key.matches(&header));
+        assert_ne!(header.nonce(), key.new_header().nonce());
+
+        let header = EncryptionHeader::from_bytes(&header.bytes()).unwrap();
+        assert_eq!(header.params(), &params);
+        let again = EncryptionKey::from_header("password", &header)
+            .await
+            .unwrap();
+        assert!(again.matches(&header));
+
+        let other = EncryptionKey::from_password_with("password", params)
+            .await
+            .unwrap();
+        assert!(!other.matches(&header));
+
+        let mut bytes = header.bytes();
+        bytes[5..9].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(
+            EncryptionHeader::from_bytes(&bytes),
+            Err(PropErrno::Decrypt)
+        );
+        // no more than what this build writes
+        bytes[5..9].copy_from_slice(&(Params::DEFAULT_M_COST + 1).to_be_bytes());
+        assert_eq!(
+            EncryptionHeader::from_bytes(&bytes),
+            Err(PropErrno::Decrypt)
+        );
+        bytes[5..9].copy_from_slice(&Params::DEFAULT_M_COST.to_be_bytes());
+        bytes[9..13].copy_from_slice(&(Params::DEFAULT_T_COST + 1).to_be_bytes());
+        assert_eq!(
+            EncryptionHeader::from_bytes(&bytes),
+            Err(PropErrno::Decrypt)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mac() {
+        let key = EncryptionKey::from_password_with("password", KdfParams::new(64, 1, 1))
+            .await
+            .unwrap();
+        let mac = key.mac(b"the index").unwrap();
+        assert!(key.verify_mac(b"the index", &mac));
+        assert!(!key.verify_mac(b"the index!", &mac));
+
+        let mut changed = mac;
+        changed[MAC_LEN - 1] ^= 1;
+        assert!(!key.verify_mac(b"the index", &changed));
+
+        let other = EncryptionKey::from_password_with("password", KdfParams::new(64, 1, 1))
+            .await
+            .unwrap();
+        assert!(!other.verify_mac(b"the index", &mac));
+    }
+}
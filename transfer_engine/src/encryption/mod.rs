@@ -0,0 +1,40 @@
+// Authenticated encryption of the compressed streams.
+// The key is derived from a password with Argon2id and the data is sealed with
+// XChaCha20-Poly1305 in chunks (the STREAM construction), so a chunk that was changed,
+// moved or cut off fails to open. The encryption wraps the output of the compression,
+// `WriteAlgorithm<EncryptWriter<W>>` and `ReadAlgorithm<DecryptReader<R>>`.
+// Every stream starts with a header, it is authenticated along with every chunk:
+//
+// | magic 4 | version 1 | argon2 m cost 4 | t cost 4 | p cost 4 | salt 16 | nonce 19 |
+//
+// the costs are 0 when the key is wrapped to public keys instead (see `recipient`).
+// A mac (`EncryptionKey::mac`) is a header and the tag of sealing nothing with the data as
+// the associated data, it authenticates data that stays readable:
+//
+// | header 44 | poly1305 tag 16 |
+pub mod decrypt;
+pub mod encrypt;
+pub mod header;
+pub mod key;
+pub mod recipient;
+
+pub use decrypt::DecryptReader;
+pub use encrypt::EncryptWriter;
+pub use header::EncryptionHeader;
+pub use key::{check_password, EncryptionKey, KdfParams};
+pub use recipient::{parse_identity, parse_recipient, Identity, Recipient};
+
+pub const ENCRYPTION_MAGIC: [u8; 4] = *b"SPPE";
+pub const ENCRYPTION_VERSION: u8 = 1;
+/// in characters
+pub const MIN_PASSWORD_LEN: u8 = 8;
+pub const MAX_PASSWORD_LEN: u8 = 128;
+pub const SALT_LEN: usize = 16;
+/// the nonce of XChaCha20 without the counter and the last chunk flag of the STREAM
+pub const NONCE_LEN: usize = 19;
+pub const HEADER_LEN: usize = ENCRYPTION_MAGIC.len() + 1 + 3 * 4 + SALT_LEN + NONCE_LEN;
+/// bytes of data sealed together
+const CHUNK_SIZE: usize = 64 * 1024; // 64KB
+/// the poly1305 tag after every chunk
+const TAG_LEN: usize = 16;
+pub const MAC_LEN: usize = HEADER_LEN + TAG_LEN;
@@ -0,0 +1,138 @@
+// Encryption to public keys, for the jobs with nobody around to type a password.
+// A random data key seals the streams like a derived key would, and the data key itself is
+// wrapped to the X25519 public keys of the recipients as an age file, so only their identities
+// (private keys) can open it. The keys are in the age format, `age1...` and
+// `AGE-SECRET-KEY-1...`, and the wrapped key can be opened by age itself.
+use std::io::{Read, Write};
+
+use age::{Decryptor, Encryptor};
+use rand::{rngs::OsRng, RngCore};
+
+use super::{EncryptionKey, SALT_LEN};
+use crate::errnos::{PropErrno, PropErrnoResult};
+
+pub use age::x25519::{Identity, Recipient};
+
+/// what is wrapped: the id of the key, then the key
+const WRAPPED_LEN: usize = SALT_LEN + 32;
+
+pub fn parse_recipient(recipient: &str) -> PropErrnoResult<Recipient> {
+    recipient.trim().parse().map_err(|err| {
+        log::error!("{}: not a public key: {}", recipient, err);
+        PropErrno::EncryptVal(recipient.to_string())
+    })
+}
+
+/// NOTE: the identity is never logged
+pub fn parse_identity(identity: &str) -> PropErrnoResult<Identity> {
+    identity.trim().parse().map_err(|err| {
+        log::error!("not a private key: {}", err);
+        PropErrno::Decrypt
+    })
+}
+
+impl EncryptionKey {
+    /// a new random key and the copy of it only the identities of `recipients` can open
+    pub fn for_recipients(recipients: &[Recipient]) -> PropErrnoResult<(Self, Vec<u8>)> {
+        let mut plain = [0; WRAPPED_LEN];
+        OsRng.fill_bytes(&mut plain);
+        let (id, key) = plain.split_at(SALT_LEN);
+        let key = Self::from_data_key(key.try_into().unwrap(), id.try_into().unwrap());
+
+        let encrypt_err = |err: &dyn std::fmt::Display| {
+            log::error!("failed to wrap the key: {}", err);
+            PropErrno::Encrypt
+        };
+        let encryptor =
+            Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))
+                .map_err(|err| encrypt_err(&err))?;
+        let mut wrapped = Vec::new();
+        let res = encryptor.wrap_output(&mut wrapped).and_then(|mut writer| {
+            writer.write_all(&plain)?;
+            writer.finish()
+        });
+        res.map_err(|err| encrypt_err(&err))?;
+        plain.fill(0);
+
+        Ok((key, wrapped))
+    }
+
+    /// the key in `wrapped`, if one of the identities can open it
+    pub fn from_wrapped(wrapped: &[u8], identities: &[Identity]) -> PropErrnoResult<Self> {
+        let decrypt_err = |err: &dyn std::fmt::Display| {
+            log::error!("failed to unwrap the key: {}", err);
+            PropErrno::Decrypt
+        };
+        let decryptor = Decryptor::new(wrapped).map_err(|err| decrypt_err(&err))?;
+        let mut reader = decryptor
+            .decrypt(identities.iter().map(|i| i as &dyn age::Identity))
+            .map_err(|err| decrypt_err(&err))?;
+
+        let mut plain = Vec::with_capacity(WRAPPED_LEN);
+        reader
+            .read_to_end(&mut plain)
+            .map_err(|err| decrypt_err(&err))?;
+        if plain.len() != WRAPPED_LEN {
+            return Err(decrypt_err(&"the wrapped key has the wrong length"));
+        }
+
+        let (id, key) = plain.split_at(SALT_LEN);
+        let key = Self::from_data_key(key.try_into().unwrap(), id.try_into().unwrap());
+        plain.fill(0);
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::{DecryptReader, EncryptWriter};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_recipients() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let eve = Identity::generate();
+        let recipients = [
+            parse_recipient(&alice.to_public().to_string()).unwrap(),
+            bob.to_public(),
+        ];
+        assert!(parse_recipient("age1notakey").is_err());
+
+        let (key, wrapped) = EncryptionKey::for_recipients(&recipients).unwrap();
+        let mut writer = EncryptWriter::new(Vec::new(), &key);
+        writer.write_all(b"nightly backup").await.unwrap();
+        writer.shutdown().await.unwrap();
+        let sealed = writer.into_inner();
+
+        // either recipient can open it
+        for identity in [alice, bob] {
+            let key = EncryptionKey::from_wrapped(&wrapped, &[identity]).unwrap();
+            let mut reader = sealed.as_slice();
+            let header = crate::encryption::EncryptionHeader::read(&mut reader)
+                .await
+                .unwrap();
+            assert!(header.params().is_none());
+            assert!(key.matches(&header));
+            let mut data = Vec::new();
+            DecryptReader::new(reader, &header, &key)
+                .read_to_end(&mut data)
+                .await
+                .unwrap();
+            assert_eq!(data, b"nightly backup");
+        }
+
+        assert_eq!(
+            EncryptionKey::from_wrapped(&wrapped, &[eve]).err(),
+            Some(PropErrno::Decrypt)
+        );
+        // there is no password to try
+        assert_eq!(
+            DecryptReader::open(sealed.as_slice(), "correct horse battery")
+                .await
+                .err(),
+            Some(PropErrno::Decrypt)
+        );
+    }
+}
@@ -83,7 +83,7 @@ impl Errno {
     /// Allows for translation of PropErrno to Errno
     /// # Arguments
     /// * `prop_errno` - PropErrnoParams - all the parameters needed for the error
-    /// while all of them won't be used in all cases, they are all there for completeness
+    ///   while all of them won't be used in all cases, they are all there for completeness
     /// # Returns
     /// * `Errno`
     pub fn from_prop_errno(prop_errno: PropErrno, params: &mut PropErrnoParams) -> Self {
@@ -498,4 +498,36 @@ impl Errno {
             params: json!({ "base": base }),
         }
     }
+    pub fn job_not_found(id: String) -> Self {
+        Self {
+            fixable: false,
+            code: "job_not_found_err".to_string(),
+            params: json!({ "id": id }),
+        }
+    }
+    pub fn job_not_queued(id: String) -> Self {
+        Self {
+            fixable: false,
+            code: "job_not_queued_err".to_string(),
+            params: json!({ "id": id }),
+        }
+    }
+    pub fn setting_range(name: String, min: u64, max: u64) -> Self {
+        Self {
+            fixable: true,
+            code: "setting_range_err".to_string(),
+            params: json!({
+            "name": name,
+            "min": min,
+            "max": max
+            }),
+        }
+    }
+    pub fn profile_not_found(name: String) -> Self {
+        Self {
+            fixable: true,
+            code: "profile_not_found_err".to_string(),
+            params: json!({ "name": name }),
+        }
+    }
 }
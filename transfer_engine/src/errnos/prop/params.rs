@@ -3,23 +3,13 @@ use std::path::Path;
 use crate::path::PathExt;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Default)]
 pub struct PropErrnoParams {
     src: Option<String>, // source path
     dst: Option<String>, // the destination of the task
                          // task: Option<String>,               // the type of task running
 }
 
-impl Default for PropErrnoParams {
-    fn default() -> Self {
-        PropErrnoParams {
-            // min_password_length: None,
-            // max_password_length: None,
-            src: None,
-            dst: None,
-            // task: None,
-        }
-    }
-}
 
 impl PropErrnoParams {
     pub fn new() -> Self {
@@ -85,8 +85,10 @@ use super::PropErrnoResult;
 /// The foo function here will handle the final error and return The ErrnoResult
 #[non_exhaustive]
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Default)]
 pub enum PropErrno {
     /// Universal Errors
+    #[default]
     Unknown,
     UnknownVal(String),
     NoMem,
@@ -184,6 +186,12 @@ impl PropErrno {
     }
 
     pub fn from_io_error(err: &IOError, path: Option<&Path>) -> Self {
+        // the readers and writers that can't return a PropErrno (encryption, ...) carry it
+        // inside the io error
+        if let Some(prop) = err.get_ref().and_then(|inner| inner.downcast_ref::<PropErrno>()) {
+            return prop.clone();
+        }
+
         match err.kind() {
             ErrorKind::InvalidData => {
                 if let Some(path) = path {
@@ -270,11 +278,6 @@ impl PropErrno {
     }
 }
 
-impl Default for PropErrno {
-    fn default() -> Self {
-        PropErrno::Unknown
-    }
-}
 
 impl fmt::Display for PropErrno {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -282,6 +285,8 @@ impl fmt::Display for PropErrno {
     }
 }
 
+impl std::error::Error for PropErrno {}
+
 // impl From<ErrorKind> for PropErrno {
 //     fn from(err: ErrorKind) -> Self {
 //         match err {
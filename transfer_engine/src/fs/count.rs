@@ -21,7 +21,7 @@ async fn get_metadata(entry: WalkDirEntry) -> u64 {
     }
 }
 
-async fn _get_child_count_and_size_all<P: AsRef<Path>>(path: P, skip_hidden: bool) -> DirInfo {
+async fn _get_child_count_and_size_all<P: AsRef<Path>>(path: P, _skip_hidden: bool) -> DirInfo {
     let walkdir = WalkDir::new(path.as_ref())
         .max_depth(usize::MAX)
         .into_iter()
@@ -37,7 +37,7 @@ async fn _get_child_count_and_size_all<P: AsRef<Path>>(path: P, skip_hidden: boo
     for entry in walkdir {
         // as long as the entry is not a directory we will count it
         // and measure the size
-        if let Err(_) = entry {
+        if entry.is_err() {
             continue;
         }
         // check if the cfg!(windows) is true
@@ -1,3 +1,4 @@
+use async_trait::async_trait;
 use walkdir::DirEntry as WalkDirEntry;
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Decision {
@@ -44,4 +45,34 @@ impl DecisionEntry {
             Self::Modified(entry, ..) => Self::Modified(entry, decision),
         }
     }
+
+    pub fn decision(&self) -> &Decision {
+        match self {
+            Self::Duplicate(_, decision) => decision,
+            Self::Modified(_, decision) => decision,
+        }
+    }
+
+    pub fn entry(&self) -> &WalkDirEntry {
+        match self {
+            Self::Duplicate(entry, ..) => entry,
+            Self::Modified(entry, ..) => entry,
+        }
+    }
+
+    pub fn into_entry(self) -> WalkDirEntry {
+        match self {
+            Self::Duplicate(entry, ..) => entry,
+            Self::Modified(entry, ..) => entry,
+        }
+    }
+}
+
+/// Settles what to do with an entry that already exists in the destination
+/// the tauri app asks the user, a cli might read a flag
+/// if no decider is given to the transfer, existing files are replaced
+#[async_trait]
+pub trait Decider: Send + Sync {
+    /// the entry will always have the `Decision::NeedInput` decision
+    async fn decide(&self, entry: &DecisionEntry) -> UserDecision;
 }
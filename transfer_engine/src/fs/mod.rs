@@ -7,4 +7,4 @@ pub mod size;
 pub mod status;
 pub mod traversal;
 /// this is the available sizes for the human readable size
-pub const AVAIL_SIZES: [&'static str; 6] = ["B", "KB", "MB", "GB", "TB", "PB"];
+pub const AVAIL_SIZES: [&str; 6] = ["B", "KB", "MB", "GB", "TB", "PB"];
@@ -1,5 +1,3 @@
-use futures::StreamExt;
-use std::path::Path;
 
 use crate::fs::AVAIL_SIZES;
 
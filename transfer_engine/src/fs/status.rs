@@ -57,10 +57,7 @@ pub enum DirStatus {
 
 impl DirStatus {
     pub fn is_calculating(&self) -> bool {
-        match self {
-            DirStatus::Calculating(_) => true,
-            _ => false,
-        }
+        matches!(self, DirStatus::Calculating(_))
     }
 
     pub async fn calculate(&mut self) {
@@ -1,5 +1,4 @@
 use std::{
-    iter::Peekable,
     path::Path,
     pin::Pin,
     task::{Context, Poll},
@@ -34,13 +33,7 @@ impl DirTraversal {
             root: WalkDir::new(&path)
                 .max_depth(usize::MAX)
                 .into_iter()
-                .filter_entry(|entry| {
-                    entry
-                        .file_name()
-                        .to_str()
-                        .map(|s| !s.starts_with("."))
-                        .unwrap_or(false)
-                }),
+                .filter_entry(ignore_hidden),
             status: DirStatus::Calculating(get_child_count_and_size_all(&path, true)),
             count: 0,
         }
@@ -115,7 +108,7 @@ impl DirTraversal {
 impl Stream for DirTraversal {
     type Item = PropErrnoResult<WalkDirEntry>;
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         Poll::Ready(self.get_next())
     }
 }
@@ -130,7 +123,7 @@ impl Drop for DirTraversal {
 mod tests {
     use super::*;
     use crate::fs::traversal::DirTraversal;
-    use futures::StreamExt;
+    
     use std::path::PathBuf;
 
     #[tokio::test]
@@ -0,0 +1,16 @@
+//! The file transfer engine behind the senior project app.
+//! Nothing in here knows about tauri or any window, everything the outside
+//! world needs to know is sent through a [`transfer::TransferObserver`]
+//! and every conflict is settled by a [`fs::decision::Decider`]
+
+#![allow(clippy::needless_return, clippy::module_inception)]
+pub mod archive;
+pub mod compression;
+pub mod dedup;
+pub mod encryption;
+pub mod errnos;
+pub mod fs;
+pub mod path;
+pub mod shared;
+pub mod transfer;
+mod utils;
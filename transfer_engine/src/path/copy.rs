@@ -1,7 +1,7 @@
 use std::path::PathBuf;
-use walkdir::DirEntry as WalkDirEntry;
 /// A wrapper around [`PathBuf`] that implements necessary functions for copying to a destination.
 ///
+#[allow(unused)]
 pub struct CopyPath {
     inner: PathBuf,
     normalized: bool,
@@ -43,8 +43,8 @@ pub async fn copy<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
     let file_name_without_extension = file_parts.next()?;
     let mut extension = "".to_string();
     // now all the extension are removed
-    while let Some(ext) = file_parts.next() {
-        extension.push_str(".");
+    for ext in file_parts {
+        extension.push('.');
         extension.push_str(ext);
     }
 
@@ -80,7 +80,7 @@ pub async fn random_copy<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
     let file_name_without_extension = file_parts.next()?;
     let mut extension = "".to_string();
     // now all the extension are removed
-    while let Some(ext) = file_parts.next() {
+    for ext in file_parts {
         extension.push_str(&format!(".{ext}"));
     }
 
@@ -125,8 +125,9 @@ pub fn normalize<P: AsRef<Path>>(path: P) -> PropErrnoResult<PathBuf> {
     return Ok(path);
 }
 
-pub fn copy_path_dst<P: AsRef<Path>>(path: P, dst: P, copying_name: &str) -> Option<PathBuf> {
-    let mut new_path = dst.as_ref().to_owned();
+#[allow(unused)]
+pub fn copy_path_dst<P: AsRef<Path>>(_path: P, dst: P, _copying_name: &str) -> Option<PathBuf> {
+    let _new_path = dst.as_ref().to_owned();
 
     todo!()
 }
@@ -225,32 +226,32 @@ pub fn parent_and_current<P: AsRef<Path>>(path: P) -> Option<String> {
 //     return None;
 // }
 
-/// this function will return the last n number of
-/// components of the path
-/// # Examples
-/// ```
-/// use std::path::Path;
-/// let path = Path::new("/home/user/file.txt");
-/// assert_eq!(path.last_n_components(2), "user/file.txt");
-/// ```
+// this function will return the last n number of
+// components of the path
+// # Examples
+// ```
+// use std::path::Path;
+// let path = Path::new("/home/user/file.txt");
+// assert_eq!(path.last_n_components(2), "user/file.txt");
+// ```
 // fn last(&self, n: usize) -> String;
 
-/// this function will return the list of children
-/// a path has.
-/// if the path is a file, it will return None
-/// if the path is symlink, it will return None
-/// if the path is a directory, it will return the list of children
-/// # Examples
-/// ```
-///
-/// use std::path::Path;
-/// let path = Path::new("/home/user/file.txt");
-/// assert_eq!(path.children().await, None);
-///
-///
-/// let path = Path::new("/home/user");
-/// assert_eq!(path.children().await, Some(vec!["file.txt", "file2.txt"]));
-/// ```
+// this function will return the list of children
+// a path has.
+// if the path is a file, it will return None
+// if the path is symlink, it will return None
+// if the path is a directory, it will return the list of children
+// # Examples
+// ```
+//
+// use std::path::Path;
+// let path = Path::new("/home/user/file.txt");
+// assert_eq!(path.children().await, None);
+//
+//
+// let path = Path::new("/home/user");
+// assert_eq!(path.children().await, Some(vec!["file.txt", "file2.txt"]));
+// ```
 // async fn children(&self) -> PropErrnoResult<Vec<String>>;
 
 /// implment additonal methods for Path necessary for the file system
@@ -288,7 +289,7 @@ pub trait PathExt: AsRef<Path> {
 
     /// Normalizes the path
     fn normalize(&self) -> PropErrnoResult<PathBuf> {
-        normalize(&self)
+        normalize(self)
     }
 
     /// returns the absolute path with the path normalized
@@ -300,7 +301,7 @@ pub trait PathExt: AsRef<Path> {
     /// assert_eq!(path.normalize(), Path::new("/home/user/file.txt"));
     /// ```
     fn absolute(&self) -> PropErrnoResult<PathBuf> {
-        absolute(&self).ok_or_else(|| PropErrno::PathNormalizeVal(self.parent_and_current()))
+        absolute(self).ok_or_else(|| PropErrno::PathNormalizeVal(self.parent_and_current()))
     }
 
     /// this will return the parent path and the current path
@@ -312,11 +313,10 @@ pub trait PathExt: AsRef<Path> {
     /// assert_eq!(path.parent_and_current().to_str(), "user/file.txt");
     /// ```
     fn parent_and_current(&self) -> String {
-        parent_and_current(&self).unwrap_or_else(|| UNKNOWN_LOCATION.to_string())
+        parent_and_current(self).unwrap_or_else(|| UNKNOWN_LOCATION.to_string())
     }
 
     /// Return default path if the path is not available
-
     fn unknown_path() -> String {
         UNKNOWN_LOCATION.to_string()
     }
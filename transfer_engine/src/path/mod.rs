@@ -1,3 +1,4 @@
 pub mod copy;
 mod ext;
+pub mod unpack;
 pub use ext::PathExt;
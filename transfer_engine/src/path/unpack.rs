@@ -0,0 +1,417 @@
+// Keeps whatever is unpacked (archives, assembled parts) inside the destination.
+// The names come from files that could have been made by anyone, so a name with `..`,
+// an absolute name or a symlink already in the destination must not get a write outside of it
+use std::{
+    io::{ErrorKind, Result as IOResult},
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use tokio::fs::File;
+
+use crate::{
+    archive::{EntryKind, EntryMeta},
+    errnos::{PropErrno, PropErrnoResult},
+};
+
+use super::PathExt;
+
+/// Where the data of the unpacked files comes from, an archive or a chunk store
+#[async_trait]
+pub trait UnpackSource: Send {
+    type Entry: AsRef<EntryMeta> + Sync;
+
+    /// writes the data of the file `entry` into `file`, which was just created at `dst`
+    async fn write_file(
+        &mut self,
+        entry: &Self::Entry,
+        dst: &Path,
+        file: &mut File,
+    ) -> PropErrnoResult<()>;
+}
+
+/// The directory everything is unpacked into
+pub struct UnpackRoot {
+    /// canonical, so it can be compared with other canonical paths
+    root: PathBuf,
+}
+
+impl UnpackRoot {
+    /// the destination has to exist
+    pub fn new<P: AsRef<Path>>(dst: P) -> PropErrnoResult<Self> {
+        let root = PropErrno::from_io_result(dst.as_ref().canonicalize(), Some(dst.as_ref()))?;
+        Ok(Self { root })
+    }
+
+    /// creates the destination if it does not exist yet
+    pub async fn create<P: AsRef<Path>>(dst: P) -> PropErrnoResult<Self> {
+        let res = tokio::fs::create_dir_all(dst.as_ref()).await;
+        PropErrno::from_io_result(res, Some(dst.as_ref()))?;
+        Self::new(dst)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// unpacks `entries` from `source`, parents before their children
+    /// an entry that would end up outside of the root stops the unpack
+    pub async fn unpack<S: UnpackSource>(
+        &self,
+        source: &mut S,
+        entries: &[S::Entry],
+    ) -> PropErrnoResult<()> {
+        let mut links = Vec::new();
+        for entry in entries {
+            let meta = entry.as_ref();
+            if let EntryKind::Symlink(target) = meta.kind() {
+                self.check_link(meta.path(), target)?;
+                links.push(self.resolve(meta.path())?);
+            }
+            // the links this goes through might point out because of the ones after them
+            let name = self.root.join(meta.path());
+            let through: Vec<PathBuf> = links
+                .iter()
+                .filter(|link| name != **link && name.starts_with(link))
+                .cloned()
+                .collect();
+            self.check_links(&through).await?;
+            let dst = self.resolve(meta.path())?;
+            unpack_entry(source, entry, &dst).await?;
+        }
+
+        self.check_links(&links).await?;
+
+        // the times of the directories changed while their children were unpacked
+        // and a read only directory has to be filled first
+        for entry in entries.iter().rev() {
+            let meta = entry.as_ref();
+            if let EntryKind::Dir = meta.kind() {
+                let dst = self.resolve(meta.path())?;
+                set_meta(&dst, meta.mtime(), meta.mode())
+                    .await
+                    .map_err(|_| PropErrno::SetMetaVal(dst.parent_and_current()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// the path `name` is unpacked to
+    /// NOTE: this has to be called right before writing, what is on the disk decides the result
+    /// # Arguments
+    /// * `name` - relative to the root, what the archive or the part calls the entry
+    pub fn resolve<P: AsRef<Path>>(&self, name: P) -> PropErrnoResult<PathBuf> {
+        let name = name.as_ref();
+        let mut path = self.root.clone();
+        for component in name.components() {
+            match component {
+                Component::Normal(part) => path.push(part),
+                Component::CurDir => {}
+                // `..`, `/` or `C:` can point anywhere
+                _ => return Err(out_of_dir(name)),
+            }
+        }
+        if path == self.root {
+            return Err(out_of_dir(name));
+        }
+
+        self.check_on_disk(&path, name)?;
+        Ok(path)
+    }
+
+    /// checks the target of a symlink unpacked to `name` stays inside the root
+    pub fn check_link<P: AsRef<Path>>(&self, name: P, target: P) -> PropErrnoResult<()> {
+        let name = name.as_ref();
+        // the link is resolved from its directory
+        let mut depth = name
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .count()
+            .saturating_sub(1);
+
+        for component in target.as_ref().components() {
+            match component {
+                Component::Normal(_) => depth += 1,
+                Component::CurDir => {}
+                Component::ParentDir if depth > 0 => depth -= 1,
+                _ => return Err(out_of_dir(name)),
+            }
+        }
+
+        // the lexical check is fooled by links in the target, whatever exists is resolved
+        let link_dir = self.resolve(name)?.parent().map(Path::to_path_buf);
+        if let Some(link_dir) = link_dir {
+            self.check_on_disk(&link_dir.join(target.as_ref()), name)?;
+        }
+
+        Ok(())
+    }
+
+    /// a link that was fine on its own can point out through links unpacked after it,
+    /// so the links are checked again before anything is written under them and once
+    /// everything is unpacked. every one that points outside is removed, dangling or not
+    /// # Arguments
+    /// * `links` - the unpacked links, from `resolve`
+    pub async fn check_links(&self, links: &[PathBuf]) -> PropErrnoResult<()> {
+        // removing a link changes where the others point, they are all checked first
+        let mut escaping = Vec::new();
+        for link in links {
+            let target = match tokio::fs::read_link(link).await {
+                Ok(target) => target,
+                // replaced by an entry after it, that entry was checked on its own
+                Err(_) => continue,
+            };
+            let link_dir = link.parent().unwrap_or(&self.root);
+            if !self.is_inside(&link_dir.join(target)) {
+                escaping.push(link);
+            }
+        }
+
+        for link in &escaping {
+            let _ = tokio::fs::remove_file(link).await;
+            log::error!(
+                "{}: points outside of the destination",
+                link.to_string_lossy()
+            );
+        }
+
+        match escaping.first() {
+            Some(link) => Err(PropErrno::UnpackOutofDirVal(link.parent_and_current())),
+            None => Ok(()),
+        }
+    }
+
+    /// whether `path` stays inside the root once the links in it are followed,
+    /// the part of it that does not exist is taken as it is written
+    fn is_inside(&self, path: &Path) -> bool {
+        for ancestor in path.ancestors() {
+            match ancestor.symlink_metadata() {
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(_) => return false,
+            }
+
+            // a dangling link can't be followed, so it can't be trusted either
+            let mut real = match ancestor.canonicalize() {
+                Ok(real) => real,
+                Err(_) => return false,
+            };
+            // SAFE because `ancestor` is one of the ancestors of `path`
+            let rest = path.strip_prefix(ancestor).unwrap();
+            for component in rest.components() {
+                match component {
+                    Component::Normal(part) => real.push(part),
+                    Component::ParentDir => {
+                        real.pop();
+                    }
+                    _ => {}
+                }
+            }
+            return real.starts_with(&self.root);
+        }
+
+        false
+    }
+
+    /// the longest part of `path` that exists has to resolve inside the root
+    fn check_on_disk(&self, path: &Path, name: &Path) -> PropErrnoResult<()> {
+        for ancestor in path.ancestors() {
+            match ancestor.symlink_metadata() {
+                Ok(_) => {
+                    // a dangling link would be followed by the write and create its target
+                    return match ancestor.canonicalize() {
+                        Ok(real) if real.starts_with(&self.root) => Ok(()),
+                        _ => Err(out_of_dir(name)),
+                    };
+                }
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(PropErrno::from_io_error(&err, Some(ancestor))),
+            }
+        }
+
+        Err(out_of_dir(name))
+    }
+}
+
+/// NOTE: `dst` has to come from `UnpackRoot::resolve`
+async fn unpack_entry<S: UnpackSource>(
+    source: &mut S,
+    entry: &S::Entry,
+    dst: &Path,
+) -> PropErrnoResult<()> {
+    if let Some(parent) = dst.parent() {
+        let res = tokio::fs::create_dir_all(parent).await;
+        PropErrno::from_io_result(res, Some(parent))?;
+    }
+
+    let meta = entry.as_ref();
+    match meta.kind() {
+        EntryKind::Dir => {
+            let res = tokio::fs::create_dir_all(dst).await;
+            PropErrno::from_io_result(res, Some(dst))
+        }
+        EntryKind::Symlink(target) => symlink(target, dst).await,
+        EntryKind::File => {
+            let mut file = PropErrno::from_io_result(File::create(dst).await, Some(dst))?;
+            source.write_file(entry, dst, &mut file).await?;
+            drop(file);
+            set_meta(dst, meta.mtime(), meta.mode())
+                .await
+                .map_err(|_| PropErrno::SetMetaVal(dst.parent_and_current()))
+        }
+    }
+}
+
+/// NOTE: `dst` has to come from `UnpackRoot::resolve`
+#[cfg(unix)]
+pub async fn symlink(target: &str, dst: &Path) -> PropErrnoResult<()> {
+    // a link from a previous unpack
+    if tokio::fs::symlink_metadata(dst).await.is_ok() {
+        let res = tokio::fs::remove_file(dst).await;
+        PropErrno::from_io_result(res, Some(dst))?;
+    }
+
+    let res = tokio::fs::symlink(target, dst).await;
+    PropErrno::from_io_result(res, Some(dst))
+}
+
+#[cfg(not(unix))]
+pub async fn symlink(_target: &str, dst: &Path) -> PropErrnoResult<()> {
+    log::error!("{}: symlinks are not supported", dst.to_string_lossy());
+    Err(PropErrno::UnpackVal(dst.parent_and_current()))
+}
+
+/// sets the modified time and the permissions of what was unpacked
+/// NOTE: setuid and setgid are never restored, only the permissions and the sticky bit
+pub async fn set_meta(dst: &Path, mtime: Option<SystemTime>, mode: Option<u32>) -> IOResult<()> {
+    // the file might not be readable anymore once the permissions are set
+    if let Some(mtime) = mtime {
+        let dst = dst.to_path_buf();
+        tokio::task::spawn_blocking(move || std::fs::File::open(dst)?.set_modified(mtime))
+            .await??;
+    }
+
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = mode & 0o1777;
+        tokio::fs::set_permissions(dst, std::fs::Permissions::from_mode(mode)).await?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    Ok(())
+}
+
+fn out_of_dir(name: &Path) -> PropErrno {
+    log::error!(
+        "{}: points outside of the destination",
+        name.to_string_lossy()
+    );
+    PropErrno::UnpackOutofDirVal(name.parent_and_current())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{archive::ArchiveEntry, utils::tmp::tmp_dir};
+
+    #[test]
+    fn test_resolve() {
+        let dst = tmp_dir("transfer_engine_unpack_root");
+        let root = UnpackRoot::new(&dst).unwrap();
+        assert_eq!(root.resolve("a/./b").unwrap(), root.root().join("a/b"));
+
+        for name in ["../a", "a/../../b", "/etc/passwd", "", "."] {
+            assert!(matches!(
+                root.resolve(name),
+                Err(PropErrno::UnpackOutofDirVal(_))
+            ));
+        }
+
+        assert!(root.check_link("a/link", "../b").is_ok());
+        assert!(root.check_link("a/link", "../../b").is_err());
+        assert!(root.check_link("link", "/etc").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_through_symlinks() {
+        let dst = tmp_dir("transfer_engine_unpack_root_links");
+        let outside = tmp_dir("transfer_engine_unpack_root_outside");
+        let root = UnpackRoot::new(&dst).unwrap();
+
+        std::os::unix::fs::symlink(&outside, dst.join("out")).unwrap();
+        std::os::unix::fs::symlink(outside.join("missing"), dst.join("dangling")).unwrap();
+        std::fs::create_dir(dst.join("dir")).unwrap();
+        std::os::unix::fs::symlink("..", dst.join("dir/up")).unwrap();
+
+        assert!(root.resolve("out/file").is_err());
+        assert!(root.resolve("dangling").is_err());
+        assert!(root.resolve("dir/up/file").is_ok());
+        // fine on paper, but `dir/up` is the root so `dir/up/..` is above it
+        assert!(root.check_link("link", "dir/up/..").is_err());
+    }
+
+    /// writes the same bytes into every file
+    struct Pwned;
+
+    #[async_trait]
+    impl UnpackSource for Pwned {
+        type Entry = ArchiveEntry;
+
+        async fn write_file(
+            &mut self,
+            _entry: &ArchiveEntry,
+            dst: &Path,
+            file: &mut File,
+        ) -> PropErrnoResult<()> {
+            use tokio::io::AsyncWriteExt;
+            PropErrno::from_io_result(file.write_all(b"pwned").await, Some(dst))
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unpack_through_a_later_link() {
+        let dir = tmp_dir("transfer_engine_unpack_later_link");
+        let dst = dir.join("dst");
+        let root = UnpackRoot::create(&dst).await.unwrap();
+
+        let entry = |name: &str, kind| ArchiveEntry::new(name.to_string(), kind, None);
+        let link = |name: &str, target: &str| entry(name, EntryKind::Symlink(target.to_string()));
+        let entries = [
+            // `dir/up` does not exist yet, `link` stays inside
+            link("link", "dir/up/.."),
+            entry("dir", EntryKind::Dir),
+            // the root, so `link` is now the directory above it
+            link("dir/up", ".."),
+            entry("link/pwned", EntryKind::File),
+            entry("last", EntryKind::File),
+        ];
+
+        let res = root.unpack(&mut Pwned, &entries).await;
+        assert!(matches!(res, Err(PropErrno::UnpackOutofDirVal(_))));
+        assert!(!dir.join("pwned").exists());
+        // removed before the write, nothing after it was unpacked
+        assert!(dst.join("link").symlink_metadata().is_err());
+        assert!(!dst.join("last").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_set_meta_drops_setuid() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dst = tmp_dir("transfer_engine_unpack_set_meta").join("file");
+        std::fs::write(&dst, b"file").unwrap();
+        set_meta(&dst, None, Some(0o106755)).await.unwrap();
+        let mode = dst.metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o755);
+        set_meta(&dst, None, Some(0o101644)).await.unwrap();
+        let mode = dst.metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o1644);
+    }
+}
@@ -4,11 +4,10 @@
 /// # Attention
 /// Avoid using this if you can has it is not very efficient
 /// and resource intensive.
-use futures::{AsyncRead, AsyncWrite, Stream};
+use futures::Stream;
 use parking_lot::RwLock;
 use smallvec::SmallVec;
 use std::{
-    io::Result,
     pin::Pin,
     sync::Arc,
     task::{Context, Poll, Waker},
@@ -17,6 +16,7 @@ use std::{
 pub struct MarcoPolo<T>(std::marker::PhantomData<T>);
 
 impl<T> MarcoPolo<T> {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new() -> (Marco<T>, Polo<T>) {
         let marco = Marco::new();
         let polo = Polo::from(&marco);
@@ -69,7 +69,7 @@ impl<T> Stream for Polo<T> {
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut lock = self.0.write();
         // if empty set waker1
-        if let None = lock.waker2 {
+        if lock.waker2.is_none() {
             lock.waker2 = Some(cx.waker().clone());
         }
 
@@ -87,6 +87,12 @@ impl<T> Stream for Polo<T> {
 
 pub struct Marco<T>(Arc<RwLock<TwoWayStream<T>>>);
 
+impl<T> Default for Marco<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Marco<T> {
     pub fn new() -> Self {
         Self(Arc::new(RwLock::new(TwoWayStream::new())))
@@ -130,7 +136,7 @@ impl<T> Stream for Marco<T> {
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut lock = self.0.write();
         // if empty set waker1
-        if let None = lock.waker1 {
+        if lock.waker1.is_none() {
             lock.waker1 = Some(cx.waker().clone());
         }
 
@@ -177,11 +183,13 @@ impl<T> TwoWayStream<T> {
         }
     }
 
+    #[allow(unused)]
     pub fn capacity(&self) -> usize {
         self.data.capacity()
     }
 
     // returns the number of bytes remaining in the buffer
+    #[allow(unused)]
     pub fn remaining(&self) -> usize {
         (self.capacity() - self.data.len()).clamp(0, self.capacity())
     }
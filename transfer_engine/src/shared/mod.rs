@@ -1,4 +1,6 @@
 // pub mod buf_writer;
 pub mod marco_polo;
 pub mod performance;
+pub mod priority;
 pub mod progress;
+pub mod throttle;
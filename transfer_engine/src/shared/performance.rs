@@ -1,7 +1,9 @@
+use serde::{Deserialize, Serialize};
+
 /// This will be used to determine how to use available resources.
 /// generally the faster the performance the more resources will be used
 /// leading to more memory usage and power consumption
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Performance {
     Fast,
     Average,
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+/// How much the copy should get out of the way of the other programs
+/// this is applied to the threads of the runtime, see [`set_thread_priority`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IoPriority {
+    /// leave the threads as they are
+    Normal,
+    /// lowest best-effort disk priority and a nice of 10
+    Low,
+    /// only touch the disk when nobody else does, nice of 19
+    Idle,
+}
+
+/// sets the disk and cpu priority of the calling thread
+/// meant for `tokio::runtime::Builder::on_thread_start` so every worker thread gets it.
+/// a priority can only be lowered, going back to `Normal` needs a restart
+/// NOTE: this does nothing outside of linux
+pub fn set_thread_priority(priority: IoPriority) -> std::io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::set_thread_priority(priority)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = priority;
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::IoPriority;
+    use std::io;
+
+    // from linux/ioprio.h
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+
+    pub fn set_thread_priority(priority: IoPriority) -> io::Result<()> {
+        let (ioprio, nice) = match priority {
+            IoPriority::Normal => return Ok(()),
+            IoPriority::Low => ((IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | 7, 10),
+            IoPriority::Idle => (IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT, 19),
+        };
+
+        // SAFE because both calls only take integers, 0 is the calling thread for both
+        unsafe {
+            if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            if libc::setpriority(libc::PRIO_PROCESS, 0, nice) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_thread_priority() {
+        // on a thread of its own so the other tests keep their priority
+        let res = std::thread::spawn(|| set_thread_priority(IoPriority::Idle))
+            .join()
+            .unwrap();
+        assert!(res.is_ok());
+    }
+}
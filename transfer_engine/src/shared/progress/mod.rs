@@ -4,5 +4,5 @@ mod updater;
 mod writer;
 pub use progress::Progress;
 pub use reader::ProgressReader;
-pub use updater::ProgressUpdater;
+pub use updater::{ProgressProcessedFn, ProgressUpdater, ProgressUpdaterFn};
 pub use writer::{ProgressWriter, ProgressWriterElseWhere};
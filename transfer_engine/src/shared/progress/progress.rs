@@ -1,19 +1,15 @@
-use crate::fs::size::readable_size;
 
 use super::updater::{ProgressUpdater, ProgressUpdaterFn};
 
+#[derive(Default)]
 pub enum ProgressKind {
     /// This is to represent a progress total that is not known
     /// but it can still be updated keeping track of the current progress
+    #[default]
     Indeterministic,
     Deterministic,
 }
 
-impl Default for ProgressKind {
-    fn default() -> Self {
-        Self::Indeterministic
-    }
-}
 /// this is the most basic progress tracker
 /// it will keep track of the progress and provide
 /// a human readable string in percentage
@@ -1,6 +1,8 @@
-pub type ProgressUpdaterFn = fn(u8);
+use std::sync::Arc;
 
-pub type ProgressProcessedFn = fn(u64);
+pub type ProgressUpdaterFn = Box<dyn FnMut(u8) + Send>;
+
+pub type ProgressProcessedFn = Arc<dyn Fn(u64) + Send + Sync>;
 
 pub trait ProgressUpdater {
     /// updates the progress tracker
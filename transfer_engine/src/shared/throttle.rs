@@ -0,0 +1,219 @@
+use std::{
+    future::Future,
+    io::Result,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use tokio::{io::AsyncWrite, time::Sleep};
+
+/// How many bytes can be written at once after being idle, as time at the full rate
+const BURST: Duration = Duration::from_millis(250);
+/// A writer never sleeps longer than this before checking the rate again
+/// so a new limit is picked up quickly
+const MAX_SLEEP: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+struct Bucket {
+    /// bytes per second, 0 means unlimited
+    rate: u64,
+    /// goes below zero when a write takes more than what is left
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn capacity(&self) -> f64 {
+        self.rate as f64 * BURST.as_secs_f64()
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.capacity());
+        self.last = now;
+    }
+}
+
+/// A token bucket shared by every writer that should stay under the same bandwidth
+/// the rate can be changed at any time, even while writers are waiting on it
+/// NOTE: cloning gives another handle to the same bucket
+#[derive(Debug, Clone)]
+pub struct RateLimiter(Arc<Mutex<Bucket>>);
+
+impl RateLimiter {
+    /// # Arguments
+    /// * `rate` - bytes per second, None for unlimited
+    pub fn new(rate: Option<u64>) -> Self {
+        Self(Arc::new(Mutex::new(Bucket {
+            rate: rate.unwrap_or(0),
+            tokens: 0.0,
+            last: Instant::now(),
+        })))
+    }
+
+    pub fn set_rate(&self, rate: Option<u64>) {
+        let mut bucket = self.0.lock();
+        bucket.refill();
+        bucket.rate = rate.unwrap_or(0);
+        // whatever was owed at the old rate does not carry over
+        bucket.tokens = bucket.tokens.clamp(0.0, bucket.capacity());
+    }
+
+    pub fn rate(&self) -> Option<u64> {
+        match self.0.lock().rate {
+            0 => None,
+            rate => Some(rate),
+        }
+    }
+
+    /// takes `bytes` out of the bucket, the bucket can go into debt
+    pub fn consume(&self, bytes: u64) {
+        let mut bucket = self.0.lock();
+        if bucket.rate == 0 {
+            return;
+        }
+
+        bucket.refill();
+        bucket.tokens -= bytes as f64;
+    }
+
+    /// how long to wait before the next write
+    pub fn delay(&self) -> Duration {
+        let mut bucket = self.0.lock();
+        if bucket.rate == 0 {
+            return Duration::ZERO;
+        }
+
+        bucket.refill();
+        if bucket.tokens >= 0.0 {
+            return Duration::ZERO;
+        }
+
+        Duration::from_secs_f64(-bucket.tokens / bucket.rate as f64)
+    }
+
+    /// blocks the thread for as long as the limit asks, for the copies on the blocking pool
+    pub fn wait_blocking(&self) {
+        loop {
+            let delay = self.delay();
+            if delay.is_zero() {
+                return;
+            }
+            std::thread::sleep(delay.min(MAX_SLEEP));
+        }
+    }
+
+    /// waits for as long as the limit asks, for the writes that don't go through a [`ThrottledWriter`]
+    pub async fn wait(&self) {
+        loop {
+            let delay = self.delay();
+            if delay.is_zero() {
+                return;
+            }
+            tokio::time::sleep(delay.min(MAX_SLEEP)).await;
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+/// Wraps a writer and holds every write back until the [`RateLimiter`] allows it
+pub struct ThrottledWriter<W: AsyncWrite + Unpin> {
+    limiter: RateLimiter,
+    sleep: Option<Pin<Box<Sleep>>>,
+    writer: W,
+}
+
+impl<W: AsyncWrite + Unpin> ThrottledWriter<W> {
+    pub fn new(writer: W, limiter: RateLimiter) -> Self {
+        Self {
+            limiter,
+            sleep: None,
+            writer,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// the writes after this wait on `limiter` instead
+    pub fn set_limiter(&mut self, limiter: RateLimiter) {
+        self.limiter = limiter;
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for ThrottledWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize>> {
+        loop {
+            if let Some(sleep) = self.sleep.as_mut() {
+                if sleep.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                self.sleep = None;
+            }
+
+            let delay = self.limiter.delay();
+            if delay.is_zero() {
+                break;
+            }
+            self.sleep = Some(Box::pin(tokio::time::sleep(delay.min(MAX_SLEEP))));
+        }
+
+        match Pin::new(&mut self.writer).poll_write(cx, buf) {
+            Poll::Ready(Ok(n)) => {
+                self.limiter.consume(n as u64);
+                Poll::Ready(Ok(n))
+            }
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_throttled_writer() {
+        // 256 bytes at 1KB/s takes at least a quarter of a second
+        let limiter = RateLimiter::new(Some(1024));
+        let mut writer = ThrottledWriter::new(Vec::new(), limiter.clone());
+        let started = Instant::now();
+        for _ in 0..4 {
+            writer.write_all(&[0; 64]).await.unwrap();
+        }
+        writer.write_all(&[0; 1]).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(writer.writer.len(), 257);
+
+        // lifting the limit lets the writer through right away
+        limiter.consume(1024 * 1024);
+        limiter.set_rate(None);
+        let started = Instant::now();
+        writer.write_all(&[0; 64]).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,113 @@
+use super::{chunk::ChunkPool, file_copier::FileCopier, observer::Reporter};
+
+/// Small files that will be copied one after the other by a single worker
+pub struct Batch {
+    files: Vec<FileCopier>,
+    bytes: u64,
+    largest: u64,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            bytes: 0,
+            largest: 0,
+        }
+    }
+
+    /// # Arguments
+    /// * `copier` - the copier of the file
+    /// * `len` - the size of the file
+    pub fn push(&mut self, copier: FileCopier, len: u64) {
+        self.files.push(copier);
+        self.bytes += len;
+        self.largest = self.largest.max(len);
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// total size of the files in the batch
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// size of the largest file, the whole batch is copied through a buffer this big
+    pub fn largest(&self) -> u64 {
+        self.largest
+    }
+
+    /// copies all the files, a file that fails does not stop the rest of the batch.
+    /// every file is reported once it is copied
+    /// # Arguments
+    /// * `pool` - the buffer the files go through comes from it,
+    ///   its idle buffers are already counted by the memory budget of the job
+    pub async fn copy(mut self, pool: ChunkPool, reporter: Reporter) {
+        // files of the same directory are written together
+        self.files.sort_by(|a, b| a.dst().cmp(b.dst()));
+
+        let capacity = self.largest as usize;
+        let mut buf = pool.take(capacity);
+        for mut copier in self.files {
+            if let Err(err) = copier.copy_small(&mut buf).await {
+                reporter.error(err);
+            }
+        }
+        pool.give(buf, capacity);
+    }
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transfer::TransferEvent;
+    use crate::utils::tmp::tmp_dir;
+    use std::{path::PathBuf, sync::Arc};
+
+    #[tokio::test]
+    async fn test_batch_copy() {
+        let dst = tmp_dir("transfer_engine_batch");
+
+        let (sender, receiver) = async_channel::unbounded();
+        let reporter = Reporter::new(Some(Arc::new(sender)));
+        let mut batch = Batch::new();
+        for (src, name) in [
+            ("../testing/dir5/item5.txt", "item5.txt"),
+            ("../testing/does_not_exist", "missing"),
+            ("../testing/dir4/item4.txt", "item4.txt"),
+        ] {
+            let copier =
+                FileCopier::new(PathBuf::from(src), dst.join(name), reporter.processed_fn());
+            batch.push(copier, 0);
+        }
+        assert_eq!(batch.len(), 3);
+
+        let pool = ChunkPool::new();
+        batch.copy(pool.clone(), reporter).await;
+
+        assert_eq!(std::fs::read(dst.join("item5.txt")).unwrap().len(), 20);
+        assert_eq!(std::fs::read(dst.join("item4.txt")).unwrap().len(), 9);
+        // the buffer went back to the pool
+        assert_eq!(pool.stats().misses, 1);
+        assert!(pool.stats().idle > 0);
+
+        // in the order of the destinations, every file on its own
+        let events: Vec<TransferEvent> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        assert!(matches!(events[0], TransferEvent::Processed(9)));
+        assert!(matches!(events[1], TransferEvent::Processed(20)));
+        assert!(matches!(events[2], TransferEvent::Error(_)));
+        assert_eq!(events.len(), 3);
+    }
+}
@@ -0,0 +1,193 @@
+use std::{path::Path, sync::Arc, time::Duration};
+
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+
+/// The tuner never goes outside of these sizes
+/// as suggested in the README, anything from 4KB to 4MB is tried
+pub const MIN_TUNED_SIZE: usize = 4 * 1024; // 4KB
+pub const MAX_TUNED_SIZE: usize = 4 * 1024 * 1024; // 4MB
+/// Number of reads with the same size before its throughput is measured
+const READS_PER_STEP: usize = 4;
+/// A bigger buffer has to be at least this much faster to be kept
+const MIN_GAIN: f64 = 0.05;
+
+/// Grows the buffer of a single file while the throughput keeps improving
+/// and falls back to the best size as soon as it stops improving
+#[derive(Debug)]
+pub struct BufferTuner {
+    size: usize,
+    best_size: usize,
+    /// bytes per second of the best size
+    best_throughput: f64,
+    step_bytes: usize,
+    step_time: Duration,
+    step_reads: usize,
+    settled: bool,
+}
+
+impl BufferTuner {
+    /// # Arguments
+    /// * `start` - the size to start from, this is the remembered size of the device if any
+    /// * `file_size` - no point in having a buffer bigger than the file itself
+    pub fn new(start: usize, file_size: u64) -> Self {
+        let size = Self::initial_size(start, file_size);
+        Self {
+            size,
+            best_size: size,
+            best_throughput: 0.0,
+            step_bytes: 0,
+            step_time: Duration::ZERO,
+            step_reads: 0,
+            // a buffer that already holds the whole file cannot do any better
+            settled: size as u64 >= file_size,
+        }
+    }
+
+    /// the size is rounded up to a power of two so it lines up with the pages
+    pub fn initial_size(start: usize, file_size: u64) -> usize {
+        let file_size = usize::try_from(file_size).unwrap_or(usize::MAX);
+        start
+            .min(file_size)
+            .clamp(MIN_TUNED_SIZE, MAX_TUNED_SIZE)
+            .next_power_of_two()
+            .min(MAX_TUNED_SIZE)
+    }
+
+    /// size of the next read
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// the fastest size seen so far
+    pub fn best_size(&self) -> usize {
+        self.best_size
+    }
+
+    /// true once growing the buffer stopped helping
+    /// only then is the best size worth remembering,
+    /// files that fit in the first buffer never settle
+    pub fn is_settled(&self) -> bool {
+        self.settled && self.best_throughput > 0.0
+    }
+
+    /// records a read and write of `bytes` that took `elapsed`
+    pub fn record(&mut self, bytes: usize, elapsed: Duration) {
+        if self.settled {
+            return;
+        }
+
+        self.step_bytes += bytes;
+        self.step_time += elapsed;
+        self.step_reads += 1;
+        if self.step_reads < READS_PER_STEP {
+            return;
+        }
+
+        let throughput = self.step_bytes as f64 / self.step_time.as_secs_f64().max(f64::EPSILON);
+        if throughput > self.best_throughput * (1.0 + MIN_GAIN) {
+            self.best_throughput = throughput;
+            self.best_size = self.size;
+            if self.size >= MAX_TUNED_SIZE {
+                self.settled = true;
+            } else {
+                self.size *= 2;
+            }
+        } else {
+            self.size = self.best_size;
+            self.settled = true;
+        }
+
+        self.step_bytes = 0;
+        self.step_time = Duration::ZERO;
+        self.step_reads = 0;
+    }
+}
+
+/// Best buffer size of every destination device seen so far
+/// shared between the jobs so the next job starts where the last one settled
+/// NOTE: cloning gives another handle to the same sizes
+#[derive(Debug, Clone, Default)]
+pub struct DeviceBufferSizes(Arc<Mutex<HashMap<u64, usize>>>);
+
+impl DeviceBufferSizes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<usize> {
+        let device = device_id(path)?;
+        self.0.lock().get(&device).copied()
+    }
+
+    pub fn remember<P: AsRef<Path>>(&self, path: P, size: usize) {
+        if let Some(device) = device_id(path) {
+            self.0.lock().insert(device, size);
+        }
+    }
+}
+
+/// the device the path is stored on
+#[cfg(unix)]
+fn device_id<P: AsRef<Path>>(path: P) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    path.as_ref().metadata().ok().map(|meta| meta.dev())
+}
+
+/// there is no device id outside of unix so the drive (or root) of the path is used instead
+#[cfg(not(unix))]
+fn device_id<P: AsRef<Path>>(path: P) -> Option<u64> {
+    use std::hash::{Hash, Hasher};
+    let root = path.as_ref().components().next()?;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    root.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initial_size() {
+        assert_eq!(BufferTuner::initial_size(64 * 1024, 100), MIN_TUNED_SIZE);
+        assert_eq!(BufferTuner::initial_size(64 * 1024, 1 << 30), 64 * 1024);
+        assert_eq!(
+            BufferTuner::initial_size(5000 * 1024, 1 << 30),
+            MAX_TUNED_SIZE
+        );
+        assert_eq!(BufferTuner::initial_size(100 * 1000, 1 << 30), 128 * 1024);
+    }
+
+    #[test]
+    fn test_ramps_up_then_settles() {
+        let mut tuner = BufferTuner::new(MIN_TUNED_SIZE, 1 << 30);
+
+        // every size is twice as fast as the last one until 16KB
+        for _ in 0..READS_PER_STEP {
+            tuner.record(MIN_TUNED_SIZE, Duration::from_millis(4));
+        }
+        assert_eq!(tuner.size(), 8 * 1024);
+        for _ in 0..READS_PER_STEP {
+            tuner.record(8 * 1024, Duration::from_millis(4));
+        }
+        assert_eq!(tuner.size(), 16 * 1024);
+        // no gain, go back to the best one
+        for _ in 0..READS_PER_STEP {
+            tuner.record(16 * 1024, Duration::from_millis(8));
+        }
+
+        assert!(tuner.is_settled());
+        assert_eq!(tuner.size(), 8 * 1024);
+        assert_eq!(tuner.best_size(), 8 * 1024);
+    }
+
+    #[test]
+    fn test_remember_device() {
+        let sizes = DeviceBufferSizes::new();
+        assert_eq!(sizes.get("../testing"), None);
+        sizes.remember("../testing/dir3", 1024 * 1024);
+        // same device
+        assert_eq!(sizes.get("../testing/dir4"), Some(1024 * 1024));
+    }
+}
@@ -0,0 +1,380 @@
+// use std::{
+//     io,
+//     pin::Pin,
+//     task::{Context, Poll},
+// };
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use bytes::{Buf, BytesMut};
+use crossbeam_queue::ArrayQueue;
+use serde::Serialize;
+
+use super::memory::{MemoryBudget, MemoryPermit};
+
+/// this is the minimum chunk size to used before an IO write is performed
+pub const MIN_CHUNK_SIZE: usize = 8 * 1024; // 8KB
+/// the chunks of a part double in size up to this one
+pub const MAX_CHUNK_SIZE: usize = 2 * 1024 * 1024; // 2MB
+/// Sizes of the buffers kept by a [`ChunkPool`], one class for every chunk size the parts grow to
+/// a request is served from the smallest class it fits in
+pub const SIZE_CLASSES: [usize; 5] = [
+    MIN_CHUNK_SIZE,
+    32 * 1024,  // 32KB
+    128 * 1024, // 128KB
+    512 * 1024, // 512KB
+    MAX_CHUNK_SIZE,
+];
+/// most idle buffers kept for every size class
+const BUFFERS_PER_CLASS: usize = 32;
+/// most bytes of idle buffers a pool keeps unless it is given a limit
+pub const DEFAULT_IDLE_LIMIT: u64 = 8 * 1024 * 1024; // 8MB
+
+/// How well a [`ChunkPool`] is doing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PoolStats {
+    /// buffers handed out from the pool
+    pub hits: u64,
+    /// buffers that had to be allocated
+    pub misses: u64,
+    /// buffers freed because their class or the pool was full or they were too big to keep
+    pub dropped: u64,
+    /// bytes of the buffers waiting in the pool
+    pub idle: u64,
+}
+
+impl PoolStats {
+    /// share of the buffers that did not need an allocation, between 0 and 1
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+
+        self.hits as f64 / total as f64
+    }
+}
+
+struct PoolInner {
+    classes: Vec<ArrayQueue<BytesMut>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    dropped: AtomicU64,
+    /// bytes of the buffers in the classes
+    idle: AtomicU64,
+    idle_limit: u64,
+}
+
+/// Recycles the buffers of the chunks across parts and files without any lock
+/// NOTE: cloning gives another handle to the same buffers
+#[derive(Clone)]
+pub struct ChunkPool(Arc<PoolInner>);
+
+impl ChunkPool {
+    pub fn new() -> Self {
+        Self::with_idle_limit(DEFAULT_IDLE_LIMIT)
+    }
+
+    /// # Arguments
+    /// * `idle_limit` - most bytes of idle buffers kept, the buffers given back past it are freed
+    pub fn with_idle_limit(idle_limit: u64) -> Self {
+        Self(Arc::new(PoolInner {
+            classes: SIZE_CLASSES
+                .iter()
+                .map(|_| ArrayQueue::new(BUFFERS_PER_CLASS))
+                .collect(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            idle: AtomicU64::new(0),
+            idle_limit,
+        }))
+    }
+
+    /// most bytes of idle buffers kept
+    pub fn idle_limit(&self) -> u64 {
+        self.0.idle_limit
+    }
+
+    /// the index of the smallest class `capacity` fits in, None if it is bigger than all of them
+    fn class_of(capacity: usize) -> Option<usize> {
+        SIZE_CLASSES.iter().position(|size| capacity <= *size)
+    }
+
+    /// an empty buffer that can hold at least `capacity` bytes
+    pub fn take(&self, capacity: usize) -> BytesMut {
+        let class = match Self::class_of(capacity) {
+            Some(class) => class,
+            None => {
+                self.0.misses.fetch_add(1, Ordering::Relaxed);
+                return BytesMut::with_capacity(capacity);
+            }
+        };
+
+        match self.0.classes[class].pop() {
+            Some(buf) => {
+                self.0.hits.fetch_add(1, Ordering::Relaxed);
+                self.0
+                    .idle
+                    .fetch_sub(SIZE_CLASSES[class] as u64, Ordering::Relaxed);
+                buf
+            }
+            None => {
+                self.0.misses.fetch_add(1, Ordering::Relaxed);
+                BytesMut::with_capacity(SIZE_CLASSES[class])
+            }
+        }
+    }
+
+    /// keeps the buffer for the next `take` of the same class
+    /// # Arguments
+    /// * `capacity` - the capacity the buffer was taken with
+    pub fn give(&self, mut buf: BytesMut, capacity: usize) {
+        let class = match Self::class_of(capacity) {
+            Some(class) => class,
+            None => {
+                self.0.dropped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+
+        // the room is taken before the push so two buffers given back at once can not both fit
+        let size = SIZE_CLASSES[class] as u64;
+        if self.0.idle.fetch_add(size, Ordering::Relaxed) + size > self.0.idle_limit {
+            self.0.idle.fetch_sub(size, Ordering::Relaxed);
+            self.0.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        // the writes advance the buffer, this gets the whole allocation back
+        // without copying anything since the buffer is empty
+        buf.clear();
+        buf.reserve(SIZE_CLASSES[class]);
+        if self.0.classes[class].push(buf).is_err() {
+            self.0.idle.fetch_sub(size, Ordering::Relaxed);
+            self.0.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            hits: self.0.hits.load(Ordering::Relaxed),
+            misses: self.0.misses.load(Ordering::Relaxed),
+            dropped: self.0.dropped.load(Ordering::Relaxed),
+            idle: self.0.idle.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for ChunkPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Hands out the buffers of the chunks from a [`ChunkPool`] within a [`MemoryBudget`]
+/// the buffer of a dropped chunk goes back to the pool instead of being freed
+/// NOTE: cloning gives another handle to the same pool and budget
+#[derive(Clone)]
+pub struct ChunkAllocator {
+    budget: MemoryBudget,
+    pool: ChunkPool,
+}
+
+impl ChunkAllocator {
+    pub fn new(budget: MemoryBudget, pool: ChunkPool) -> Self {
+        Self { budget, pool }
+    }
+
+    pub fn budget(&self) -> &MemoryBudget {
+        &self.budget
+    }
+
+    pub fn pool(&self) -> &ChunkPool {
+        &self.pool
+    }
+
+    /// an empty chunk starting at `start` that can hold at least `capacity` bytes
+    /// this waits while the budget is used up, so nothing new is read until older chunks are written
+    pub async fn alloc(&self, start: u64, capacity: usize) -> Chunk {
+        let permit = self.budget.reserve(capacity as u64).await;
+        self.chunk(start, capacity, permit)
+    }
+
+    /// same as `alloc` but None instead of waiting when the budget is used up
+    /// NOTE: whoever holds a chunk while asking for the next one must use this,
+    /// otherwise everyone can end up holding memory and waiting on each other
+    pub fn try_alloc(&self, start: u64, capacity: usize) -> Option<Chunk> {
+        let permit = self.budget.try_reserve(capacity as u64)?;
+        Some(self.chunk(start, capacity, permit))
+    }
+
+    fn chunk(&self, start: u64, capacity: usize, permit: MemoryPermit) -> Chunk {
+        Chunk {
+            start,
+            end: start,
+            data: self.pool.take(capacity),
+            recycle: Some(Recycle {
+                pool: self.pool.clone(),
+                capacity,
+                _permit: permit,
+            }),
+        }
+    }
+}
+
+/// Where the buffer of a chunk goes back to once it is dropped
+struct Recycle {
+    pool: ChunkPool,
+    capacity: usize,
+    _permit: MemoryPermit,
+}
+
+//#[derive(Debug, Serialize, Deserialize)]
+/// A chunk of data
+/// this is should be contiguous slice of data in memory
+/// # Properties
+/// * start - the start offset of the chunk - inclusive
+/// * end - the end offset of the chunk - exclusive
+/// * data - contiguous slice of data in memory
+pub struct Chunk {
+    start: u64,
+    end: u64,
+    data: BytesMut,
+    /// only set for chunks from a `ChunkAllocator`
+    recycle: Option<Recycle>,
+}
+
+impl Chunk {
+    pub fn new(start: u64, end: u64, data: BytesMut) -> Self {
+        Self {
+            start,
+            end,
+            data,
+            recycle: None,
+        }
+    }
+
+    pub fn set_end(&mut self, end: u64) {
+        self.end = end;
+    }
+
+    pub fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    pub fn start(&self) -> &u64 {
+        &self.start
+    }
+
+    pub fn end(&self) -> &u64 {
+        &self.end
+    }
+
+    pub fn data(&self) -> &BytesMut {
+        &self.data
+    }
+
+    pub fn mut_data(&mut self) -> &mut BytesMut {
+        &mut self.data
+    }
+}
+
+impl Buf for Chunk {
+    fn remaining(&self) -> usize {
+        self.data.remaining()
+    }
+
+    fn chunk(&self) -> &[u8] {
+        self.data.chunk()
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        self.data.advance(cnt)
+    }
+}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        // the permit is dropped with the recycle so the memory goes back to the budget
+        if let Some(recycle) = self.recycle.take() {
+            recycle
+                .pool
+                .give(std::mem::take(&mut self.data), recycle.capacity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_allocator_reuses_buffers() {
+        let allocator = ChunkAllocator::new(MemoryBudget::new(64 * 1024), ChunkPool::new());
+        let mut chunk = allocator.alloc(0, MIN_CHUNK_SIZE).await;
+        chunk.mut_data().extend_from_slice(&[1; 100]);
+        chunk.advance(50);
+        chunk.set_end(100);
+        assert_eq!(allocator.budget().used(), MIN_CHUNK_SIZE as u64);
+
+        drop(chunk);
+        assert_eq!(allocator.budget().used(), 0);
+
+        let chunk = allocator.alloc(100, MIN_CHUNK_SIZE).await;
+        assert!(chunk.data().is_empty());
+        assert!(chunk.data().capacity() >= MIN_CHUNK_SIZE);
+        assert_eq!(
+            allocator.pool().stats(),
+            PoolStats {
+                hits: 1,
+                misses: 1,
+                dropped: 0,
+                idle: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_size_classes() {
+        let pool = ChunkPool::new();
+        let small = pool.take(100);
+        assert_eq!(small.capacity(), MIN_CHUNK_SIZE);
+        let big = pool.take(100 * 1024);
+        assert_eq!(big.capacity(), 128 * 1024);
+        let huge = pool.take(4 * 1024 * 1024);
+
+        pool.give(small, 100);
+        pool.give(big, 100 * 1024);
+        pool.give(huge, 4 * 1024 * 1024);
+        // a different size of the same class is served from the pool
+        assert_eq!(pool.take(MIN_CHUNK_SIZE).capacity(), MIN_CHUNK_SIZE);
+        assert_eq!(pool.take(64 * 1024).capacity(), 128 * 1024);
+
+        let stats = pool.stats();
+        assert_eq!((stats.hits, stats.misses, stats.dropped), (2, 3, 1));
+        assert_eq!(stats.hit_rate(), 0.4);
+        assert_eq!(stats.idle, 0);
+    }
+
+    #[test]
+    fn test_idle_limit() {
+        let pool = ChunkPool::with_idle_limit(2 * MIN_CHUNK_SIZE as u64);
+        let bufs: Vec<BytesMut> = (0..3).map(|_| pool.take(MIN_CHUNK_SIZE)).collect();
+        for buf in bufs {
+            pool.give(buf, MIN_CHUNK_SIZE);
+        }
+        // the third buffer does not fit
+        let stats = pool.stats();
+        assert_eq!((stats.dropped, stats.idle), (1, 2 * MIN_CHUNK_SIZE as u64));
+
+        let _buf = pool.take(MIN_CHUNK_SIZE);
+        assert_eq!(pool.stats().idle, MIN_CHUNK_SIZE as u64);
+        // bigger than what is left
+        pool.give(pool.take(32 * 1024), 32 * 1024);
+        assert_eq!(pool.stats().dropped, 2);
+    }
+}
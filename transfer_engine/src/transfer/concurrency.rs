@@ -0,0 +1,152 @@
+use std::time::{Duration, Instant};
+
+/// Number of workers a tuned job starts with
+pub const INITIAL_WORKERS: usize = 2;
+/// How long the throughput is measured before the number of workers is changed
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+/// More workers are only added while they make the copy at least this much faster
+const MIN_GAIN: f64 = 0.05;
+/// The number of workers is halved when the throughput drops by this much
+const MAX_LOSS: f64 = 0.2;
+/// or when a file takes this many times longer than it used to
+const MAX_LATENCY_GROWTH: f64 = 2.0;
+
+/// Decides how many workers a job should run at a time.
+/// It starts small and adds one worker at a time as long as the throughput improves,
+/// the number is halved as soon as the disks start to struggle (AIMD).
+/// A spinning disk ends up with a few workers while an NVMe drive keeps getting more.
+#[derive(Debug)]
+pub struct ConcurrencyController {
+    limit: usize,
+    min: usize,
+    max: usize,
+    window_start: Instant,
+    window_bytes: u64,
+    window_files: u32,
+    window_latency: Duration,
+    /// throughput (bytes per second) and average latency of the last sample
+    last: Option<(f64, Duration)>,
+}
+
+impl ConcurrencyController {
+    /// # Arguments
+    /// * `min` - the number of workers never goes below this
+    /// * `max` - the number of workers never goes above this
+    pub fn new(min: usize, max: usize) -> Self {
+        let max = max.max(min);
+        Self {
+            limit: INITIAL_WORKERS.clamp(min, max),
+            min,
+            max,
+            window_start: Instant::now(),
+            window_bytes: 0,
+            window_files: 0,
+            window_latency: Duration::ZERO,
+            last: None,
+        }
+    }
+
+    /// the number of workers stays at `workers`
+    pub fn fixed(workers: usize) -> Self {
+        Self::new(workers, workers)
+    }
+
+    /// number of workers that should be running right now
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// records a finished file
+    /// # Arguments
+    /// * `bytes` - the bytes written by all the workers since the last call
+    /// * `latency` - how long the file took from start to finish
+    pub fn record(&mut self, bytes: u64, latency: Duration) {
+        self.record_at(bytes, latency, Instant::now());
+    }
+
+    fn record_at(&mut self, bytes: u64, latency: Duration, now: Instant) {
+        if self.min == self.max {
+            return;
+        }
+
+        self.window_bytes += bytes;
+        self.window_files += 1;
+        self.window_latency += latency;
+
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed < SAMPLE_INTERVAL {
+            return;
+        }
+
+        let throughput = self.window_bytes as f64 / elapsed.as_secs_f64();
+        let latency = self.window_latency / self.window_files;
+        self.adjust(throughput, latency);
+
+        self.window_start = now;
+        self.window_bytes = 0;
+        self.window_files = 0;
+        self.window_latency = Duration::ZERO;
+    }
+
+    fn adjust(&mut self, throughput: f64, latency: Duration) {
+        match self.last {
+            Some((last_throughput, last_latency))
+                if throughput < last_throughput * (1.0 - MAX_LOSS)
+                    || latency.as_secs_f64() > last_latency.as_secs_f64() * MAX_LATENCY_GROWTH =>
+            {
+                self.limit = (self.limit / 2).max(self.min);
+            }
+            Some((last_throughput, _)) if throughput < last_throughput * (1.0 + MIN_GAIN) => {
+                // the sweet spot, keep it
+            }
+            _ => self.limit = (self.limit + 1).min(self.max),
+        }
+
+        self.last = Some((throughput, latency));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// feeds a whole sample with the given throughput in bytes per second
+    fn sample(controller: &mut ConcurrencyController, start: Instant, throughput: u64) -> Instant {
+        let end = start + SAMPLE_INTERVAL;
+        controller.record_at(throughput / 4, Duration::from_millis(10), end);
+        end
+    }
+
+    #[test]
+    fn test_increases_while_faster() {
+        let mut controller = ConcurrencyController::new(1, 8);
+        let mut now = controller.window_start;
+        assert_eq!(controller.limit(), INITIAL_WORKERS);
+
+        now = sample(&mut controller, now, 100);
+        assert_eq!(controller.limit(), 3);
+        now = sample(&mut controller, now, 200);
+        assert_eq!(controller.limit(), 4);
+        // no gain, hold
+        now = sample(&mut controller, now, 201);
+        assert_eq!(controller.limit(), 4);
+        // dropped, halve
+        sample(&mut controller, now, 100);
+        assert_eq!(controller.limit(), 2);
+    }
+
+    #[test]
+    fn test_stays_within_limits() {
+        let mut controller = ConcurrencyController::new(1, 3);
+        let mut now = controller.window_start;
+        for throughput in [100, 200, 400, 800] {
+            now = sample(&mut controller, now, throughput);
+        }
+        assert_eq!(controller.limit(), 3);
+
+        let mut controller = ConcurrencyController::fixed(5);
+        let now = controller.window_start;
+        sample(&mut controller, now, 100);
+        assert_eq!(controller.limit(), 5);
+    }
+}
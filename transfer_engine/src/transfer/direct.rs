@@ -0,0 +1,158 @@
+// Copies that skip the page cache with O_DIRECT.
+// A huge file going through the cache pushes out everything the other programs had cached
+// and every byte gets copied twice in memory on the way
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    path::Path,
+};
+
+use super::chunk::ChunkPool;
+use crate::shared::throttle::RateLimiter;
+
+/// Offsets, lengths and buffers all have to be aligned to this
+/// the logical block size of pretty much every disk
+pub const DIRECT_ALIGN: usize = 4096;
+pub const DIRECT_BUFFER_SIZE: usize = 1024 * 1024; // 1MB
+
+/// the file systems that do not support O_DIRECT refuse it with EINVAL
+fn is_unsupported(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::EINVAL)
+}
+
+/// fails with EINVAL when the file system of `path` does not support O_DIRECT
+pub fn open_direct(path: &Path, write: bool) -> io::Result<File> {
+    OpenOptions::new()
+        .read(!write)
+        .write(write)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)
+}
+
+/// copies the first `size` bytes of `src` into `dst` with O_DIRECT
+/// returns false without writing anything when either file system does not support it,
+/// the copy should then go through the usual path.
+/// NOTE: this blocks, run it on the blocking pool
+/// # Arguments
+/// * `tail` - a regular handle to `dst`, the last bytes that do not fill a whole block are written with it
+/// * `pool` - the buffer is taken from and given back to this pool
+pub fn copy<F: Fn(u64)>(
+    src: &Path,
+    dst: &Path,
+    tail: &File,
+    size: u64,
+    pool: &ChunkPool,
+    limiter: &RateLimiter,
+    processed_cb: F,
+) -> io::Result<bool> {
+    let (src, dst) = match (open_direct(src, false), open_direct(dst, true)) {
+        (Ok(src), Ok(dst)) => (src, dst),
+        (Err(err), _) | (_, Err(err)) if is_unsupported(&err) => return Ok(false),
+        (Err(err), _) | (_, Err(err)) => return Err(err),
+    };
+
+    // the pool makes no promise about the alignment so take a block more than needed
+    let capacity = DIRECT_BUFFER_SIZE + DIRECT_ALIGN;
+    let mut buf = pool.take(capacity);
+    buf.resize(capacity, 0);
+    let skip = buf.as_ptr().align_offset(DIRECT_ALIGN);
+    let res = copy_aligned(
+        &src,
+        &dst,
+        tail,
+        size,
+        &mut buf[skip..skip + DIRECT_BUFFER_SIZE],
+        limiter,
+        processed_cb,
+    );
+    pool.give(buf, capacity);
+
+    res
+}
+
+fn copy_aligned<F: Fn(u64)>(
+    src: &File,
+    dst: &File,
+    tail: &File,
+    size: u64,
+    buf: &mut [u8],
+    limiter: &RateLimiter,
+    processed_cb: F,
+) -> io::Result<bool> {
+    let mut offset = 0;
+    while offset < size {
+        let n = match src.read_at(buf, offset) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if offset == 0 && is_unsupported(&err) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+
+        limiter.wait_blocking();
+        let whole = n - n % DIRECT_ALIGN;
+        if whole > 0 {
+            match dst.write_all_at(&buf[..whole], offset) {
+                Ok(()) => {}
+                Err(err) if offset == 0 && is_unsupported(&err) => return Ok(false),
+                Err(err) => return Err(err),
+            }
+        }
+        // a read that does not end on a block is the end of the file
+        if whole < n {
+            tail.write_all_at(&buf[whole..n], offset + whole as u64)?;
+        }
+
+        limiter.consume(n as u64);
+        processed_cb(n as u64);
+        offset += n as u64;
+        if whole < n {
+            break;
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tmp::tmp_dir;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[test]
+    fn test_direct_copy() {
+        let dir = tmp_dir("transfer_engine_direct");
+        // a few whole buffers and a tail that is not a whole block
+        let data: Vec<u8> = (0..2 * DIRECT_BUFFER_SIZE + 3 * DIRECT_ALIGN + 123)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        std::fs::write(dir.join("src"), &data).unwrap();
+
+        let tail = File::create(dir.join("dst")).unwrap();
+        let processed = AtomicU64::new(0);
+        let copied = copy(
+            &dir.join("src"),
+            &dir.join("dst"),
+            &tail,
+            data.len() as u64,
+            &ChunkPool::new(),
+            &RateLimiter::default(),
+            |n| {
+                processed.fetch_add(n, Ordering::Relaxed);
+            },
+        )
+        .unwrap();
+
+        // the temp directory might be on a file system without O_DIRECT, tmpfs for one
+        let supported = open_direct(&dir.join("src"), false).is_ok();
+        assert_eq!(copied, supported);
+        if !supported {
+            eprintln!("skipped: {} does not support O_DIRECT", dir.display());
+            return;
+        }
+
+        assert_eq!(processed.load(Ordering::Relaxed), data.len() as u64);
+        assert_eq!(std::fs::read(dir.join("dst")).unwrap(), data);
+    }
+}
@@ -1,12 +1,13 @@
 use std::{
     fs::create_dir,
+    io::ErrorKind,
     path::{Path, PathBuf},
 };
 use walkdir::DirEntry as WalkDirEntry;
 
 use crate::{
-    errnos::PropErrno,
-    notifications::{Notification, NOTIFICATION_MANAGER},
+    errnos::{PropErrno, PropErrnoResult},
+    path::PathExt,
 };
 pub struct DstPath {
     dst: PathBuf,
@@ -14,21 +15,21 @@ pub struct DstPath {
 }
 
 impl DstPath {
-    pub fn new(mut dst: PathBuf) -> Option<Self> {
+    pub fn new(dst: PathBuf) -> Option<Self> {
         Some(Self {
             dst,
             current_depth: 0,
         })
     }
 
-    pub fn build_dst(&mut self, entry: &WalkDirEntry) -> &Path {
+    /// builds the destination path of the entry and creates it if it is a directory
+    /// the entries must be given in the same order as the traversal returns them
+    pub fn build_dst(&mut self, entry: &WalkDirEntry) -> PropErrnoResult<&Path> {
         // if entry.depth is greater than current_depth it will push
         // depth is the same curent_depth it will pop and push
         // depth is less than current_depth it will pop entry.depth - current_depth times + 1 and push
         let depth = entry.depth();
-        if depth == 0 {
-            self.dst.push(entry.file_name());
-        } else if depth > self.current_depth {
+        if depth == 0 || depth > self.current_depth {
             self.dst.push(entry.file_name());
         } else if depth == self.current_depth {
             self.dst.pop();
@@ -42,39 +43,38 @@ impl DstPath {
         }
         self.current_depth = depth;
         if entry.file_type().is_dir() {
-            let dir = create_dir(&self.dst);
-            if let Err(e) = dir {
-                NOTIFICATION_MANAGER
-                    .write()
-                    .push(Notification::new_from_properrno(
-                        PropErrno::EntityCreation(entry.file_name().to_str().unwrap().to_string()),
-                        "",
-                        "",
-                    ))
+            // the directory might be there from a previous transfer
+            if let Err(e) = create_dir(&self.dst) {
+                if e.kind() != ErrorKind::AlreadyExists {
+                    log::error!("{}: {}", self.dst.display(), e);
+                    return Err(PropErrno::EntityCreation(self.dst.parent_and_current()));
+                }
             }
         }
 
-        self.dst.as_path()
+        Ok(self.dst.as_path())
     }
 }
 
 #[cfg(test)]
 mod test {
-    use futures::StreamExt;
-
-    #[test]
-    fn dst_path_test() {
+    #[tokio::test]
+    async fn dst_path_test() {
         use super::*;
         use crate::fs::traversal::DirTraversal;
+        use crate::utils::tmp::tmp_dir;
 
         let src = PathBuf::from("../testing/");
+        let dst = tmp_dir("transfer_engine_dst_path");
 
-        let mut dst_path = DstPath::new(PathBuf::from("../testing/dst_path")).unwrap();
+        let mut dst_path = DstPath::new(dst.clone()).unwrap();
         let mut traversal = DirTraversal::new(src);
         while let Some(entry) = traversal.get_next() {
             let entry = entry.unwrap();
-            let dst = dst_path.build_dst(&entry);
+            let dst = dst_path.build_dst(&entry).unwrap();
             println!("{} -> {}", entry.path().display(), dst.display());
         }
+
+        assert!(dst.join("testing/dir2/dir /item.txt").parent().unwrap().is_dir());
     }
 }
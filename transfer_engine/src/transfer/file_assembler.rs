@@ -0,0 +1,483 @@
+use std::{
+    ffi::{OsStr, OsString},
+    io::Cursor,
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
+};
+
+use crate::{
+    compression::{decompress::Decomprossor, detect::PART_MAGIC},
+    encryption::{DecryptReader, EncryptionHeader, EncryptionKey},
+    errnos::{PropErrno, PropErrnoResult},
+    path::{unpack::set_meta, PathExt},
+    shared::progress::ProgressProcessedFn,
+};
+
+use super::header::Header;
+
+/// bytes copied from a part to the file at a time
+const ASSEMBLE_BUFFER_SIZE: usize = 128 * 1024;
+/// the old header only came with the first part, inside of its compressed stream
+const LEGACY_HEADER_LEN: usize = 10;
+
+type PartReader = Box<dyn AsyncRead + Unpin + Send>;
+
+/// Puts a file split by the [`super::file_splitter::FileSplitter`] back together
+/// the parts written before they had a header of their own can be read too
+pub struct FileAssembler {
+    /// the first part, the others are found next to it
+    first: PathBuf,
+    dst: PathBuf,
+    processed_cb: Option<ProgressProcessedFn>,
+    /// opens the encrypted parts
+    password: Option<String>,
+}
+
+impl FileAssembler {
+    /// # Arguments
+    /// * `first` - the part ending in 0, like `file.txt.zst0`
+    /// * `dst` - the file to assemble
+    pub fn new<P: AsRef<Path>>(first: P, dst: P) -> Self {
+        Self {
+            first: first.as_ref().to_path_buf(),
+            dst: dst.as_ref().to_path_buf(),
+            processed_cb: None,
+            password: None,
+        }
+    }
+
+    /// gets the bytes of the file as they are written
+    pub fn set_processed_cb(mut self, processed_cb: Option<ProgressProcessedFn>) -> Self {
+        self.processed_cb = processed_cb;
+        self
+    }
+
+    /// the password the parts were encrypted with, if they were
+    pub fn set_password(mut self, password: Option<String>) -> Self {
+        self.password = password;
+        self
+    }
+
+    /// writes the file of the parts to `dst` and returns its size
+    /// the file is removed again when a part is missing or does not match its header
+    pub async fn assemble(&self) -> PropErrnoResult<u64> {
+        let file = PropErrno::from_io_result(File::create(&self.dst).await, Some(&self.dst))?;
+        let mut writer = BufWriter::new(file);
+        let res = self.assemble_into(&mut writer).await;
+        let res = match res {
+            Ok(header) => {
+                let flushed = writer.shutdown().await;
+                PropErrno::from_io_result(flushed, Some(&self.dst)).map(|_| header)
+            }
+            Err(err) => Err(err),
+        };
+
+        let (header, len) = match res {
+            Ok(done) => done,
+            Err(err) => {
+                let _ = tokio::fs::remove_file(&self.dst).await;
+                return Err(err);
+            }
+        };
+
+        if header.mtime() != 0 {
+            let mtime = UNIX_EPOCH + Duration::from_secs(header.mtime());
+            let res = set_meta(&self.dst, Some(mtime), None).await;
+            PropErrno::from_io_result(res, Some(&self.dst))?;
+        }
+
+        Ok(len)
+    }
+
+    /// copies the parts one after the other, returns the header of the first part
+    /// and how many bytes were written
+    async fn assemble_into<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+    ) -> PropErrnoResult<(Header, u64)> {
+        // the parts of a file share the key, it is only derived once
+        let mut key = None;
+        let (first, reader) = self.open_part(0, None, &mut key).await?;
+        let mut len = self.copy_part(&self.first, &first, reader, writer).await?;
+        for index in 1..first.part_count() {
+            let path = self.part_path(index);
+            let (header, reader) = self.open_part(index, Some(&first), &mut key).await?;
+            len += self.copy_part(&path, &header, reader, writer).await?;
+        }
+
+        // only the new headers know how big the file was
+        if first.version() != 0 && len != first.original_len() {
+            log::error!(
+                "{}: {} bytes were assembled instead of {}",
+                self.dst.to_string_lossy(),
+                len,
+                first.original_len()
+            );
+            return Err(PropErrno::CorruptedFileVal(self.first.parent_and_current()));
+        }
+
+        Ok((first, len))
+    }
+
+    /// the parts only differ in the number at the end of their name
+    fn part_path(&self, index: u16) -> PathBuf {
+        let name = self.first.file_name().unwrap_or_default().to_string_lossy();
+        let stem = name.strip_suffix('0').unwrap_or(&name);
+        self.first
+            .with_file_name(OsString::from(format!("{}{}", stem, index)))
+    }
+
+    /// opens the part and reads its header
+    /// # Arguments
+    /// * `first` - the header of the first part, None when this is the first part
+    /// * `key` - the key of the parts opened so far
+    async fn open_part(
+        &self,
+        index: u16,
+        first: Option<&Header>,
+        key: &mut Option<EncryptionKey>,
+    ) -> PropErrnoResult<(Header, PartReader)> {
+        let path = self.part_path(index);
+        let file = PropErrno::from_io_result(File::open(&path).await, Some(&path))?;
+        let mut file = BufReader::new(file);
+        let mut prefix = Vec::with_capacity(Header::len());
+        let res = (&mut file)
+            .take(Header::len() as u64)
+            .read_to_end(&mut prefix)
+            .await;
+        PropErrno::from_io_result(res, Some(&path))?;
+
+        if prefix.starts_with(&PART_MAGIC) {
+            let header = Header::from_bytes(&prefix, &path)?;
+            if let Some(first) = first {
+                check_part(first, &header, index, &path)?;
+            }
+            let rest = Cursor::new(prefix.split_off(header.bytes_len())).chain(file);
+            let rest: PartReader = match header.is_encrypted() {
+                true => Box::new(self.decrypt(rest, &header, key, &path).await?),
+                false => Box::new(rest),
+            };
+            // the compressed bytes have to agree with the header too
+            let reader: PartReader = match header.algorithm().get_ext() {
+                Some(ext) => Box::new(Decomprossor::detect(rest, Some(OsStr::new(ext))).await?),
+                None => Box::new(Decomprossor::new(header.algorithm(), rest)),
+            };
+            return Ok((header, reader));
+        }
+
+        // a part of an older build, the extension of the first part has the algorithm
+        let rest = Cursor::new(prefix).chain(file);
+        let mut reader = Decomprossor::detect(rest, self.first.extension()).await?;
+        let header = match first {
+            Some(first) => {
+                let mut header = *first;
+                header.set_part_index(&index);
+                header
+            }
+            None => {
+                let mut bytes = [0; LEGACY_HEADER_LEN];
+                let res = reader.read_exact(&mut bytes).await;
+                PropErrno::from_io_result(res, Some(&path))?;
+                Header::from_bytes(&bytes, &path)?
+            }
+        };
+
+        Ok((header, Box::new(reader)))
+    }
+
+    /// opens the encryption after the header of the part, the header sealed at its start
+    /// has to be the same as the one in front of it
+    async fn decrypt<R: AsyncRead + Unpin>(
+        &self,
+        mut reader: R,
+        header: &Header,
+        key: &mut Option<EncryptionKey>,
+        path: &Path,
+    ) -> PropErrnoResult<DecryptReader<R>> {
+        let encryption = EncryptionHeader::read(&mut reader).await?;
+        let part_key = match key.as_ref().filter(|key| key.matches(&encryption)) {
+            Some(key) => key.clone(),
+            None => {
+                let password = self.password.as_deref().ok_or_else(|| {
+                    log::error!("{}: the part is encrypted", path.to_string_lossy());
+                    PropErrno::InvalidPasswordVal(path.parent_and_current())
+                })?;
+                let part_key = EncryptionKey::from_header(password, &encryption).await?;
+                *key = Some(part_key.clone());
+                part_key
+            }
+        };
+
+        let mut reader = DecryptReader::new(reader, &encryption, &part_key);
+        let mut sealed = [0; Header::len()];
+        PropErrno::from_io_result(reader.read_exact(&mut sealed).await, Some(path))?;
+        // the crc is only known once the part is written, the header in front gets it
+        let mut expected = *header;
+        expected.set_part_crc(&0);
+        if Header::from_bytes(&sealed, path)? != expected {
+            log::error!(
+                "{}: the header does not match the encrypted one",
+                path.to_string_lossy()
+            );
+            return Err(PropErrno::CorruptedHeaderVal(path.parent_and_current()));
+        }
+
+        Ok(reader)
+    }
+
+    /// copies the decompressed part to the writer and checks it against its header
+    async fn copy_part<W: AsyncWrite + Unpin>(
+        &self,
+        path: &Path,
+        header: &Header,
+        mut reader: PartReader,
+        writer: &mut W,
+    ) -> PropErrnoResult<u64> {
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buf = vec![0; ASSEMBLE_BUFFER_SIZE];
+        let mut len = 0;
+        loop {
+            let read = PropErrno::from_io_result(reader.read(&mut buf).await, Some(path))?;
+            if read == 0 {
+                break;
+            }
+
+            hasher.update(&buf[..read]);
+            let res = writer.write_all(&buf[..read]).await;
+            PropErrno::from_io_result(res, Some(&self.dst))?;
+            if let Some(processed_cb) = &self.processed_cb {
+                processed_cb(read as u64);
+            }
+            len += read as u64;
+        }
+
+        header.verify_part(&hasher.finalize(), path)?;
+        // every part but the last one is as big as the header says
+        let is_last = header.part_index() + 1 == header.part_count();
+        if len > header.part_size() || (!is_last && len != header.part_size()) {
+            log::error!(
+                "{}: the part has {} bytes instead of {}",
+                path.to_string_lossy(),
+                len,
+                header.part_size()
+            );
+            return Err(PropErrno::CorruptedFileVal(path.parent_and_current()));
+        }
+
+        Ok(len)
+    }
+}
+
+/// the part has to belong to the same file as the first part and be where its name says
+fn check_part(first: &Header, header: &Header, index: u16, path: &Path) -> PropErrnoResult<()> {
+    if header.part_index() == index
+        && header.part_count() == first.part_count()
+        && header.part_size() == first.part_size()
+        && header.original_len() == first.original_len()
+        && header.algorithm() == first.algorithm()
+    {
+        return Ok(());
+    }
+
+    log::error!(
+        "{}: the part does not belong with the first part",
+        path.to_string_lossy()
+    );
+    Err(PropErrno::CorruptedHeaderVal(path.parent_and_current()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compression::{algorithm::Algorithm, compress::Compression, policy::CompressionPolicy},
+        encryption::KdfParams,
+        shared::performance::Performance,
+        transfer::{
+            chunk::{ChunkAllocator, ChunkPool},
+            file_splitter::FileSplitter,
+            memory::MemoryBudget,
+            observer::Reporter,
+        },
+        utils::tmp::tmp_dir,
+    };
+
+    fn content() -> Vec<u8> {
+        (0..100 * 1024u32)
+            .map(|i| b"the quick brown fox "[(i % 20) as usize] ^ (i / 997) as u8)
+            .collect()
+    }
+
+    async fn write_compressed(algorithm: &Algorithm, bytes: &[u8], path: PathBuf) {
+        let file = File::create(path).await.unwrap();
+        let mut writer = Compression::from_algorithm(algorithm, file, &Performance::Average);
+        writer.write_all(bytes).await.unwrap();
+        writer.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_split_and_assemble() {
+        let dir = tmp_dir("test_split_and_assemble");
+        let src = dir.join("file.txt");
+        let content = content();
+        std::fs::write(&src, &content).unwrap();
+
+        let splitter = FileSplitter::new(
+            src.clone(),
+            dir.join("parts").join("file.txt"),
+            Performance::Fast,
+            CompressionPolicy::default(),
+            ChunkAllocator::new(MemoryBudget::new(1024 * 1024), ChunkPool::new()),
+            Reporter::new(None),
+        );
+        std::fs::create_dir_all(dir.join("parts")).unwrap();
+        let parts = splitter.split().await.unwrap();
+
+        let dst = dir.join("file.txt.assembled");
+        let len = FileAssembler::new(&parts[0], &dst)
+            .assemble()
+            .await
+            .unwrap();
+        assert_eq!(len, content.len() as u64);
+        assert_eq!(std::fs::read(&dst).unwrap(), content);
+        let secs = |path: &Path| {
+            let mtime = std::fs::metadata(path).unwrap().modified().unwrap();
+            mtime.duration_since(UNIX_EPOCH).unwrap().as_secs()
+        };
+        assert_eq!(secs(&src), secs(&dst));
+
+        // a part that went missing
+        std::fs::remove_file(&parts[2]).unwrap();
+        assert!(FileAssembler::new(&parts[0], &dst)
+            .assemble()
+            .await
+            .is_err());
+        assert!(!dst.exists());
+    }
+
+    #[tokio::test]
+    async fn test_assemble_encrypted_parts() {
+        let dir = tmp_dir("test_assemble_encrypted_parts");
+        let src = dir.join("file.txt");
+        let content = content();
+        std::fs::write(&src, &content).unwrap();
+        let password = "correct horse battery";
+        // argon2 with the default costs is too slow for the tests
+        let key = EncryptionKey::from_password_with(password, KdfParams::new(64, 1, 1))
+            .await
+            .unwrap();
+
+        std::fs::create_dir_all(dir.join("parts")).unwrap();
+        let parts = FileSplitter::new(
+            src.clone(),
+            dir.join("parts").join("file.txt"),
+            Performance::Fast,
+            CompressionPolicy::default(),
+            ChunkAllocator::new(MemoryBudget::new(1024 * 1024), ChunkPool::new()),
+            Reporter::new(None),
+        )
+        .set_encryption(Some(key))
+        .split()
+        .await
+        .unwrap();
+        let header = |path: &Path| Header::from_bytes(&std::fs::read(path).unwrap(), path);
+        assert!(header(&parts[0]).unwrap().is_encrypted());
+
+        let dst = dir.join("file.txt.assembled");
+        let assembler = FileAssembler::new(&parts[0], &dst);
+        assert!(matches!(
+            assembler.assemble().await,
+            Err(PropErrno::InvalidPasswordVal(_))
+        ));
+        let assembler = assembler.set_password(Some(password.to_string()));
+        assert_eq!(assembler.assemble().await.unwrap(), content.len() as u64);
+        assert_eq!(std::fs::read(&dst).unwrap(), content);
+
+        // two parts swapped along with the indices of their headers
+        let swap = |from: &Path, to: &Path, index: u16| {
+            let mut bytes = std::fs::read(from).unwrap();
+            let mut header = header(from).unwrap();
+            header.set_part_index(&index);
+            bytes[..Header::len()].copy_from_slice(&header.bytes());
+            std::fs::write(to, bytes).unwrap();
+        };
+        let moved = dir.join("moved");
+        swap(&parts[0], &moved, 1);
+        swap(&parts[1], &parts[0], 0);
+        std::fs::rename(&moved, &parts[1]).unwrap();
+        assert!(matches!(
+            assembler.assemble().await,
+            Err(PropErrno::CorruptedHeaderVal(_))
+        ));
+        assert!(!dst.exists());
+    }
+
+    #[tokio::test]
+    async fn test_assemble_corrupted_part() {
+        let dir = tmp_dir("test_assemble_corrupted_part");
+        let content = content();
+        let size = content.len().div_ceil(2);
+        for (index, part) in content.chunks(size).enumerate() {
+            let mut header = Header::new();
+            header.set_original_len(&(content.len() as u64));
+            header.set_part_index(&(index as u16));
+            header.set_part_count(&2);
+            header.set_part_size(&(size as u64));
+            header.set_part_crc(&crc32fast::hash(part));
+            let mut bytes = header.bytes().to_vec();
+            bytes.extend_from_slice(part);
+            std::fs::write(dir.join(format!("file.{}", index)), bytes).unwrap();
+        }
+
+        let dst = dir.join("file");
+        let assembler = FileAssembler::new(dir.join("file.0"), dst.clone());
+        assert_eq!(assembler.assemble().await.unwrap(), content.len() as u64);
+        assert_eq!(std::fs::read(&dst).unwrap(), content);
+
+        // the header is fine but the bytes after it are not
+        let mut bytes = std::fs::read(dir.join("file.1")).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        std::fs::write(dir.join("file.1"), bytes).unwrap();
+        assert!(matches!(
+            assembler.assemble().await,
+            Err(PropErrno::CorruptedFileVal(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_assemble_legacy_parts() {
+        let dir = tmp_dir("test_assemble_legacy_parts");
+        let content = content();
+        let size = content.len().div_ceil(3);
+        // the old header was the part size and count in front of the first part only
+        let mut legacy = (size as u64).to_be_bytes().to_vec();
+        legacy.extend_from_slice(&3u16.to_be_bytes());
+
+        for (algorithm, ext) in [(Algorithm::Zstd, "zst"), (Algorithm::None, "")] {
+            for (index, part) in content.chunks(size).enumerate() {
+                let mut bytes = if index == 0 {
+                    legacy.clone()
+                } else {
+                    Vec::new()
+                };
+                bytes.extend_from_slice(part);
+                let path = dir.join(format!("file.{}{}", ext, index));
+                write_compressed(&algorithm, &bytes, path).await;
+            }
+
+            let dst = dir.join(format!("file_{}", ext));
+            let first = dir.join(format!("file.{}0", ext));
+            let len = FileAssembler::new(first, dst.clone())
+                .assemble()
+                .await
+                .unwrap();
+            assert_eq!(len, content.len() as u64);
+            assert_eq!(std::fs::read(&dst).unwrap(), content);
+        }
+    }
+}
@@ -0,0 +1,482 @@
+use std::{
+    fs::{FileTimes, Metadata},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use bytes::BytesMut;
+use tokio::{
+    fs::File,
+    io::{copy_buf, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+};
+
+use crate::{
+    errnos::{Errno, ErrnoResult, PropErrno, PropErrnoParams},
+    path::PathExt,
+    shared::{
+        progress::{ProgressProcessedFn, ProgressWriterElseWhere},
+        throttle::{RateLimiter, ThrottledWriter},
+    },
+    transfer::{
+        buffer_tuner::{BufferTuner, DeviceBufferSizes},
+        chunk::ChunkPool,
+        page_cache::{AdvisedWriter, CacheAdvisor},
+        settings::{
+            CachePolicy, CopyBackend, DurabilityPolicy, MetadataPolicy, DEFAULT_BUFFER_SIZE,
+        },
+    },
+};
+
+#[cfg(target_os = "linux")]
+use crate::transfer::direct;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use crate::transfer::uring;
+
+pub struct FileCopier {
+    src: PathBuf,
+    dst: PathBuf,
+    processed_cb: ProgressProcessedFn,
+    buffer_size: usize,
+    /// set when the buffer size should be tuned while copying
+    tuning: Option<DeviceBufferSizes>,
+    metadata: MetadataPolicy,
+    durability: DurabilityPolicy,
+    limiter: RateLimiter,
+    backend: CopyBackend,
+    /// set when the file should skip the page cache, the buffers come from this pool
+    direct: Option<ChunkPool>,
+    cache: CachePolicy,
+}
+
+impl FileCopier {
+    /// # Arguments
+    /// * `src` - the file to copy
+    /// * `dst` - where the file will be copied to, it will be replaced if it exists
+    /// * `processed_cb` - called with the number of bytes written after every write
+    pub fn new<P: AsRef<Path>>(src: P, dst: P, processed_cb: ProgressProcessedFn) -> Self {
+        let src = src.as_ref().to_path_buf();
+        let dst = dst.as_ref().to_path_buf();
+        Self {
+            src,
+            dst,
+            processed_cb,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            tuning: None,
+            metadata: MetadataPolicy::None,
+            durability: DurabilityPolicy::None,
+            limiter: RateLimiter::default(),
+            backend: CopyBackend::Tokio,
+            direct: None,
+            cache: CachePolicy::Keep,
+        }
+    }
+
+    /// size of both the read and the write buffer
+    /// when tuning, this is only where the tuner starts from
+    pub fn set_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// tunes the buffer size while copying, starting from the size remembered for the destination device
+    /// the best size found is remembered for the next files
+    pub fn set_tuning(mut self, sizes: Option<DeviceBufferSizes>) -> Self {
+        self.tuning = sizes;
+        self
+    }
+
+    pub fn set_metadata(mut self, metadata: MetadataPolicy) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn set_durability(mut self, durability: DurabilityPolicy) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// every write waits on this limiter, it is usually shared by the whole job
+    pub fn set_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// only applies to `copy`, the small files are read in one go anyway
+    pub fn set_backend(mut self, backend: CopyBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// copies with O_DIRECT through a buffer of the pool, this goes before the backend.
+    /// the file is copied the usual way if the file systems do not support it
+    pub fn set_direct_io(mut self, pool: Option<ChunkPool>) -> Self {
+        self.direct = pool;
+        self
+    }
+
+    /// the hints given to the kernel about the page cache, the O_DIRECT copies skip it anyway
+    pub fn set_cache(mut self, cache: CachePolicy) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn dst(&self) -> &Path {
+        &self.dst
+    }
+
+    pub async fn copy(&mut self) -> ErrnoResult<()> {
+        self.copy_through(None).await
+    }
+
+    /// copies the whole file at once through `buf`
+    /// this is meant for small files where setting up buffers costs more than the copy itself
+    pub async fn copy_small(&mut self, buf: &mut BytesMut) -> ErrnoResult<()> {
+        self.copy_through(Some(buf)).await
+    }
+
+    async fn copy_through(&mut self, buf: Option<&mut BytesMut>) -> ErrnoResult<()> {
+        let mut params = PropErrnoParams::new_with_src_and_dst(
+            self.src.parent_and_current(),
+            self.dst.parent_and_current(),
+        );
+
+        let mut src_reader =
+            PropErrno::from_io_result(File::open(&self.src).await, Some(&self.src))
+                .map_err(|e| Errno::from_prop_errno(e, &mut params))?;
+
+        let src_meta = PropErrno::from_io_result(src_reader.metadata().await, Some(&self.src))
+            .map_err(|e| Errno::from_prop_errno(e, &mut params))?;
+
+        let mut dst_writer =
+            PropErrno::from_io_result(File::create(&self.dst).await, Some(&self.dst))
+                .map_err(|e| Errno::from_prop_errno(e, &mut params))?;
+
+        // a small file is read in one go, reading it ahead is not worth the calls
+        let advisor = match (self.cache, &buf) {
+            (CachePolicy::Sequential, Some(_)) => None,
+            (cache, _) => CacheAdvisor::new(cache, &src_reader, &dst_writer),
+        };
+        // io_uring calls back on the blocking pool once the bytes are written,
+        // the other copies tell the advisor through the `AdvisedWriter` around the destination
+        let uring_cb = match &advisor {
+            Some(advisor) => {
+                let advisor = Arc::clone(advisor);
+                let processed_cb = self.processed_cb.clone();
+                let written = AtomicU64::new(0);
+                Arc::new(move |n| {
+                    advisor.written(written.fetch_add(n, Ordering::Relaxed) + n);
+                    processed_cb(n);
+                }) as ProgressProcessedFn
+            }
+            None => self.processed_cb.clone(),
+        };
+
+        // false when the file is not copied this way, it is then copied the usual way
+        let copied = match buf {
+            Some(_) => Ok(false),
+            None => {
+                self.unbuffered_copy(&src_reader, &dst_writer, src_meta.len(), &uring_cb)
+                    .await
+            }
+        };
+
+        let mut advised_writer = AdvisedWriter::new(&mut dst_writer, advisor.clone());
+        let res = match (copied, buf, &self.tuning) {
+            (Ok(true), _, _) => Ok(()),
+            (Err(e), _, _) => Err(e),
+            (Ok(false), Some(buf), _) => {
+                self.small_copy(&mut src_reader, &mut advised_writer, buf)
+                    .await
+            }
+            (Ok(false), None, Some(sizes)) => {
+                self.tuned_copy(&mut src_reader, &mut advised_writer, src_meta.len(), sizes)
+                    .await
+            }
+            (Ok(false), None, None) => {
+                let mut buf_reader = BufReader::with_capacity(self.buffer_size, &mut src_reader);
+                let buf_writer = BufWriter::with_capacity(self.buffer_size, &mut advised_writer);
+                let throttled_writer = ThrottledWriter::new(buf_writer, self.limiter.clone());
+                let mut progress_writer =
+                    ProgressWriterElseWhere::new(throttled_writer, self.processed_cb.clone());
+                copy_buf(&mut buf_reader, &mut progress_writer)
+                    .await
+                    .map(|_| ())
+            }
+        };
+
+        let res = PropErrno::from_io_result(res, Some(&self.src));
+
+        if let Err(e) = res {
+            return Err(Errno::from_prop_errno(e, &mut params));
+        };
+
+        if let DurabilityPolicy::Sync = self.durability {
+            dst_writer
+                .flush()
+                .await
+                .and(dst_writer.sync_all().await)
+                .map_err(|_| Errno::write(self.dst.parent_and_current()))?;
+        }
+
+        if let Some(advisor) = advisor {
+            // waits for the last regions to be written before they can be dropped
+            let _ = dst_writer.flush().await;
+            let _ = tokio::task::spawn_blocking(move || advisor.finish()).await;
+        }
+
+        self.copy_metadata(&src_meta, dst_writer).await
+    }
+
+    async fn small_copy(
+        &self,
+        src_reader: &mut File,
+        dst_writer: &mut AdvisedWriter<&mut File>,
+        buf: &mut BytesMut,
+    ) -> io::Result<()> {
+        buf.clear();
+        while src_reader.read_buf(buf).await? != 0 {}
+        let mut writer = ThrottledWriter::new(dst_writer, self.limiter.clone());
+        writer.write_all(buf).await?;
+        writer.flush().await?;
+        (self.processed_cb)(buf.len() as u64);
+        Ok(())
+    }
+
+    /// the copies that go around the tokio file system, false when none of them apply
+    async fn unbuffered_copy(
+        &self,
+        src_reader: &File,
+        dst_writer: &File,
+        len: u64,
+        processed_cb: &ProgressProcessedFn,
+    ) -> io::Result<bool> {
+        // the O_DIRECT copy does not go through the cache so it gets the plain callback
+        if let Some(pool) = &self.direct {
+            if self.direct_copy(dst_writer, len, pool.clone()).await? {
+                return Ok(true);
+            }
+        }
+
+        match self.backend {
+            CopyBackend::IoUring => {
+                self.uring_copy(src_reader, dst_writer, len, processed_cb.clone())
+                    .await
+            }
+            CopyBackend::Tokio => Ok(false),
+        }
+    }
+
+    /// copies with O_DIRECT on the blocking pool
+    /// returns false when a file system does not support it
+    #[cfg(target_os = "linux")]
+    async fn direct_copy(&self, dst_writer: &File, len: u64, pool: ChunkPool) -> io::Result<bool> {
+        let tail = dst_writer.try_clone().await?.into_std().await;
+        let src = self.src.clone();
+        let dst = self.dst.clone();
+        let limiter = self.limiter.clone();
+        let processed_cb = self.processed_cb.clone();
+        tokio::task::spawn_blocking(move || {
+            direct::copy(&src, &dst, &tail, len, &pool, &limiter, |n| processed_cb(n))
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn direct_copy(&self, _dst: &File, _len: u64, _pool: ChunkPool) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// copies through io_uring on the blocking pool
+    /// returns false when io_uring cannot be used so the file is copied the usual way
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    async fn uring_copy(
+        &self,
+        src_reader: &File,
+        dst_writer: &File,
+        len: u64,
+        processed_cb: ProgressProcessedFn,
+    ) -> io::Result<bool> {
+        if !uring::is_supported() {
+            return Ok(false);
+        }
+
+        // the blocking task gets its own handles in case this future is dropped before it is done
+        let src = src_reader.try_clone().await?.into_std().await;
+        let dst = dst_writer.try_clone().await?.into_std().await;
+        let limiter = self.limiter.clone();
+        tokio::task::spawn_blocking(move || {
+            uring::copy(&src, &dst, len, &limiter, |n| processed_cb(n))
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+    async fn uring_copy(
+        &self,
+        _src: &File,
+        _dst: &File,
+        _len: u64,
+        _processed_cb: ProgressProcessedFn,
+    ) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// copies with a buffer that grows as long as the throughput improves
+    async fn tuned_copy(
+        &self,
+        src_reader: &mut File,
+        dst_writer: &mut AdvisedWriter<&mut File>,
+        file_size: u64,
+        sizes: &DeviceBufferSizes,
+    ) -> io::Result<()> {
+        let start = sizes.get(&self.dst).unwrap_or(self.buffer_size);
+        let mut tuner = BufferTuner::new(start, file_size);
+        let mut buf = vec![0; tuner.size()];
+        let writer = ThrottledWriter::new(dst_writer, self.limiter.clone());
+        let mut writer = ProgressWriterElseWhere::new(writer, self.processed_cb.clone());
+
+        loop {
+            let size = tuner.size();
+            if buf.len() < size {
+                buf.resize(size, 0);
+            }
+
+            let started = Instant::now();
+            let n = src_reader.read(&mut buf[..size]).await?;
+            if n == 0 {
+                break;
+            }
+            writer.write_all(&buf[..n]).await?;
+            tuner.record(n, started.elapsed());
+        }
+
+        writer.flush().await?;
+        // the throughput says nothing about the device while the bandwidth is limited
+        if tuner.is_settled() && self.limiter.rate().is_none() {
+            sizes.remember(&self.dst, tuner.best_size());
+        }
+
+        Ok(())
+    }
+
+    /// carries the metadata of the source over to the copy based on the policy
+    async fn copy_metadata(&self, src_meta: &Metadata, dst_writer: File) -> ErrnoResult<()> {
+        let meta_err = |_| Errno::meta_set(self.dst.parent_and_current());
+
+        if let MetadataPolicy::All = self.metadata {
+            dst_writer
+                .set_permissions(src_meta.permissions())
+                .await
+                .map_err(meta_err)?;
+        }
+
+        if let MetadataPolicy::Timestamps | MetadataPolicy::All = self.metadata {
+            let mut times = FileTimes::new();
+            if let Ok(modified) = src_meta.modified() {
+                times = times.set_modified(modified);
+            }
+            if let Ok(accessed) = src_meta.accessed() {
+                times = times.set_accessed(accessed);
+            }
+
+            let dst_writer = dst_writer.into_std().await;
+            tokio::task::spawn_blocking(move || dst_writer.set_times(times))
+                .await
+                .map_err(|_| Errno::meta_set(self.dst.parent_and_current()))?
+                .map_err(meta_err)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tmp::tmp_dir;
+    use std::{
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+    };
+
+    #[tokio::test]
+    async fn file_copier_test() {
+        let src = PathBuf::from("../testing/dir3/item3");
+        let dst = tmp_dir("transfer_engine_copier").join("item3");
+        let processed = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&processed);
+        let mut f = FileCopier::new(
+            src.clone(),
+            dst.clone(),
+            Arc::new(move |n| {
+                counter.fetch_add(n, Ordering::Relaxed);
+            }),
+        )
+        .set_metadata(MetadataPolicy::Timestamps)
+        .set_durability(DurabilityPolicy::Sync);
+        let res = f.copy().await;
+        assert!(res.is_ok(), "{:?}", res);
+        assert_eq!(std::fs::read(&src).unwrap(), std::fs::read(&dst).unwrap());
+        assert_eq!(
+            processed.load(Ordering::Relaxed),
+            std::fs::metadata(&src).unwrap().len()
+        );
+        assert_eq!(
+            std::fs::metadata(&src).unwrap().modified().unwrap(),
+            std::fs::metadata(&dst).unwrap().modified().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_direct_io_copy() {
+        let src = PathBuf::from("../testing/dir3/item3");
+        let dst = tmp_dir("transfer_engine_direct_copier").join("item3");
+        let pool = ChunkPool::new();
+        let mut f = FileCopier::new(src.clone(), dst.clone(), Arc::new(|_| {}))
+            .set_direct_io(Some(pool.clone()));
+        f.copy().await.unwrap();
+        assert_eq!(std::fs::read(&src).unwrap(), std::fs::read(&dst).unwrap());
+
+        // tmpfs does not support O_DIRECT, the copy then goes through the usual path
+        #[cfg(target_os = "linux")]
+        let supported =
+            direct::open_direct(&src, false).is_ok() && direct::open_direct(&dst, true).is_ok();
+        #[cfg(not(target_os = "linux"))]
+        let supported = false;
+        // only the O_DIRECT copy takes a buffer from the pool
+        assert_eq!(pool.stats().misses, supported as u64);
+    }
+
+    #[tokio::test]
+    async fn test_evict_cache_copy() {
+        let src = PathBuf::from("../testing/dir3/item3");
+        let dst = tmp_dir("transfer_engine_evict_copier").join("item3");
+        let processed = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&processed);
+        let mut f = FileCopier::new(
+            src.clone(),
+            dst.clone(),
+            Arc::new(move |n| {
+                counter.fetch_add(n, Ordering::Relaxed);
+            }),
+        )
+        .set_cache(CachePolicy::Evict);
+        f.copy().await.unwrap();
+        assert_eq!(std::fs::read(&src).unwrap(), std::fs::read(&dst).unwrap());
+        // the hints do not get in the way of the progress
+        assert_eq!(
+            processed.load(Ordering::Relaxed),
+            std::fs::metadata(&src).unwrap().len()
+        );
+    }
+}
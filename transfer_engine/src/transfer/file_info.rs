@@ -7,7 +7,10 @@ use std::{
 use async_fs::{metadata, FileType};
 
 use crate::{
-    compression::algorithm::Algorithm,
+    compression::{
+        algorithm::Algorithm,
+        probe::{CompressionProbe, CompressionReport},
+    },
     errnos::{PropErrno, PropErrnoResult},
     path::PathExt,
     shared::performance::Performance,
@@ -22,6 +25,8 @@ pub struct FileInfo {
     path: PathBuf,
     file_type: FileType,
     compression: Option<Algorithm>,
+    /// set when the samples of the file were probed
+    probe: Option<CompressionProbe>,
 }
 
 impl FileInfo {
@@ -39,6 +44,7 @@ impl FileInfo {
     ) -> PropErrnoResult<Self> {
         let mut file_info = Self::from_path(path).await?;
         file_info.detect_compression(compressed, perf);
+        file_info.probe_compression().await;
         Ok(file_info)
     }
 
@@ -52,6 +58,7 @@ impl FileInfo {
             path: path.as_ref().into(),
             file_type,
             compression: None,
+            probe: None,
         })
     }
 
@@ -105,6 +112,38 @@ impl FileInfo {
         }
     }
 
+    /// checks samples of the file and turns the compression off if it would not pay off
+    /// the algorithm is left as is when the file cannot be read, the copy will report it
+    pub async fn probe_compression(&mut self) {
+        if !self
+            .compression
+            .is_some_and(|algorithm| algorithm.is_enabled())
+        {
+            return;
+        }
+
+        if let Ok(probe) = CompressionProbe::from_path(&self.path, self.size).await {
+            if !probe.is_compressible() {
+                self.compression = Some(Algorithm::None);
+            }
+            self.probe = Some(probe);
+        }
+    }
+
+    pub fn probe(&self) -> Option<&CompressionProbe> {
+        self.probe.as_ref()
+    }
+
+    /// the compression decision that goes into the `TransferEvent::Compression` of the transfer
+    pub fn compression_report(&self) -> CompressionReport {
+        CompressionReport {
+            src: self.path.clone(),
+            size: self.size,
+            algorithm: self.compression.unwrap_or_default(),
+            probe: self.probe,
+        }
+    }
+
     pub fn file_type(&self) -> &FileType {
         &self.file_type
     }
@@ -0,0 +1,251 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
+
+use async_rwlock::RwLock;
+use smallvec::SmallVec;
+use tokio::{fs::File, task::JoinHandle};
+
+use crate::{
+    compression::policy::CompressionPolicy,
+    encryption::EncryptionKey,
+    errnos::{PropErrno, PropErrnoResult},
+    path::PathExt,
+    shared::performance::Performance,
+    transfer::{
+        file_info::FileInfo,
+        part::{Part, PartSource},
+        parting_info::PartingInfo,
+    },
+};
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use super::uring;
+use super::{chunk::ChunkAllocator, header::Header, observer::Reporter, settings::CopyBackend};
+
+/// Compresses a file into parts that are written next to where the file would be copied to
+/// every part is compressed on its own task and can be put back together on its own
+pub struct FileSplitter {
+    src: PathBuf,
+    /// where the file would be copied to, the parts are named after it
+    dst: PathBuf,
+    perf: Performance,
+    policy: CompressionPolicy,
+    allocator: ChunkAllocator,
+    reporter: Reporter,
+    /// the parts read the source through io_uring when it is asked for and supported
+    backend: CopyBackend,
+    /// encrypts the parts, see `Part`
+    key: Option<EncryptionKey>,
+}
+
+impl FileSplitter {
+    pub fn new<P: AsRef<Path>>(
+        src: P,
+        dst: P,
+        perf: Performance,
+        policy: CompressionPolicy,
+        allocator: ChunkAllocator,
+        reporter: Reporter,
+    ) -> Self {
+        Self {
+            src: src.as_ref().to_path_buf(),
+            dst: dst.as_ref().to_path_buf(),
+            perf,
+            policy,
+            allocator,
+            reporter,
+            backend: CopyBackend::Tokio,
+            key: None,
+        }
+    }
+
+    pub fn set_backend(mut self, backend: CopyBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// the key is derived once for all the files of a job, deriving it is slow on purpose
+    pub fn set_encryption(mut self, key: Option<EncryptionKey>) -> Self {
+        self.key = key;
+        self
+    }
+
+    pub fn src(&self) -> &Path {
+        &self.src
+    }
+
+    pub fn dst(&self) -> &Path {
+        &self.dst
+    }
+
+    /// splits the file and returns the paths of its parts in order
+    /// the parts written so far are removed when one of them fails
+    pub async fn split(&self) -> PropErrnoResult<Vec<PathBuf>> {
+        let info = FileInfo::from_path_and_detect(&self.src, true, &self.perf).await?;
+        self.reporter.compression(&info.compression_report());
+        let src = PropErrno::from_io_result(File::open(&self.src).await, Some(&self.src))?;
+        // the assembled file gets it back
+        let mtime = src
+            .metadata()
+            .await
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |mtime| mtime.as_secs());
+        let reader = self.source(src).await;
+        let parting_info = PartingInfo::calculate(info.size(), &self.perf);
+        let algorithm = info.compression().copied().unwrap_or_default();
+
+        let mut parts: Vec<PathBuf> = Vec::with_capacity(*parting_info.count() as usize);
+        let mut handles: SmallVec<
+            [JoinHandle<PropErrnoResult<()>>; PartingInfo::worker_threads()],
+        > = SmallVec::new();
+        let mut next_offset = 0;
+        let mut res = Ok(());
+        for index in 0..*parting_info.count() {
+            let dst = self.dst.with_file_name(info.append_part_num(&index));
+            let end_offset = (next_offset + parting_info.size()).min(*info.size());
+            let mut header = Header::new();
+            header.set_algorithm(algorithm);
+            header.set_original_len(info.size());
+            header.set_part_index(&index);
+            header.set_part_count(parting_info.count());
+            header.set_part_size(parting_info.size());
+            header.set_mtime(&mtime);
+
+            let part = Part::new_from_compression(
+                &dst,
+                &algorithm,
+                &self.perf,
+                &self.policy,
+                self.key.as_ref(),
+                header,
+                next_offset,
+                end_offset,
+                reader.clone(),
+                self.allocator.clone(),
+                self.reporter.processed_fn(),
+            )
+            .await;
+            // the file is there even when the compression could not be set up
+            parts.push(dst);
+            match part {
+                Ok(mut part) => handles.push(tokio::spawn(async move { part.start().await })),
+                Err(err) => {
+                    res = Err(err);
+                    break;
+                }
+            }
+
+            next_offset = end_offset;
+        }
+
+        // wait for all the parts, the first error stops the others
+        for handle in handles.iter_mut() {
+            if res.is_err() {
+                break;
+            }
+            res = match handle.await {
+                Ok(part_res) => part_res,
+                Err(err) => {
+                    log::error!("{}: {}", self.src.to_string_lossy(), err);
+                    Err(PropErrno::InterruptedVal(self.src.parent_and_current()))
+                }
+            };
+        }
+
+        if let Err(err) = res {
+            self.abort(&handles, &parts).await;
+            return Err(err);
+        }
+
+        Ok(parts)
+    }
+
+    /// io_uring reads without taking turns on the file
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    async fn source(&self, src: File) -> PartSource {
+        if self.backend == CopyBackend::IoUring && uring::is_supported() {
+            return PartSource::Uring(Arc::new(src.into_std().await));
+        }
+
+        PartSource::Tokio(Arc::new(RwLock::new(src)))
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+    async fn source(&self, src: File) -> PartSource {
+        PartSource::Tokio(Arc::new(RwLock::new(src)))
+    }
+
+    /// stops the parts still running and removes all the parts of the file
+    async fn abort(&self, handles: &[JoinHandle<PropErrnoResult<()>>], parts: &[PathBuf]) {
+        for handle in handles.iter() {
+            handle.abort();
+        }
+
+        for part in parts {
+            let _ = tokio::fs::remove_file(part).await;
+        }
+
+        log::info!(
+            "aborting file splitting of {}",
+            self.src.parent_and_current()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compression::decompress::Decomprossor,
+        transfer::{chunk::ChunkPool, memory::MemoryBudget},
+        utils::tmp::tmp_dir,
+    };
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_split_covers_the_file() {
+        let dir = tmp_dir("test_split_covers_the_file");
+        let src = dir.join("file.txt");
+        let content: Vec<u8> = (0..100 * 1024u32)
+            .map(|i| b"the quick brown fox "[(i % 20) as usize] ^ (i / 997) as u8)
+            .collect();
+        std::fs::write(&src, &content).unwrap();
+
+        // io_uring falls back to tokio when it is not there
+        for backend in [CopyBackend::Tokio, CopyBackend::IoUring] {
+            // room for a couple of chunks only, the parts have to wait on each other
+            let allocator = ChunkAllocator::new(MemoryBudget::new(64 * 1024), ChunkPool::new());
+            let splitter = FileSplitter::new(
+                src.clone(),
+                dir.join("copy").join("file.txt"),
+                Performance::Fast,
+                CompressionPolicy::default(),
+                allocator.clone(),
+                Reporter::new(None),
+            )
+            .set_backend(backend);
+            std::fs::create_dir_all(dir.join("copy")).unwrap();
+            let parts = splitter.split().await.unwrap();
+            assert_eq!(parts.len(), PartingInfo::worker_threads());
+            assert_eq!(allocator.budget().used(), 0);
+
+            let mut joined = Vec::new();
+            for (index, part) in parts.iter().enumerate() {
+                let bytes = std::fs::read(part).unwrap();
+                let header = Header::from_bytes(&bytes, part).unwrap();
+                assert_eq!(header.part_index() as usize, index);
+                assert_eq!(header.original_len(), content.len() as u64);
+
+                let compressed = std::io::Cursor::new(bytes[Header::len()..].to_vec());
+                let mut reader = Decomprossor::new(header.algorithm(), compressed);
+                reader.read_to_end(&mut joined).await.unwrap();
+            }
+            assert_eq!(joined, content);
+        }
+    }
+}
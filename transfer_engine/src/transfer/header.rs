@@ -0,0 +1,350 @@
+use std::path::Path;
+
+use crate::{
+    compression::{algorithm::Algorithm, detect::PART_MAGIC},
+    errnos::{PropErrno, PropErrnoResult},
+    path::PathExt,
+};
+
+/// the version written by this build, 0 is the old header without a magic
+pub const HEADER_VERSION: u8 = 1;
+
+/// THis is the header bytes for a part
+const MAGIC_RANGE: std::ops::Range<usize> = 0..4;
+const VERSION_POS: usize = 4;
+const ALGORITHM_POS: usize = 5;
+const ORIGINAL_LEN_RANGE: std::ops::Range<usize> = 6..14;
+const PART_INDEX_RANGE: std::ops::Range<usize> = 14..16;
+const PART_COUNT_RANGE: std::ops::Range<usize> = 16..18;
+const PART_SIZE_RANGE: std::ops::Range<usize> = 18..26;
+const MTIME_RANGE: std::ops::Range<usize> = 26..34;
+const PART_CRC_RANGE: std::ops::Range<usize> = 34..38;
+/// crc32 of all the bytes before it
+const HEADER_CRC_RANGE: std::ops::Range<usize> = 38..42;
+/// Each header is this bytes long
+const HEADER_BYTES_LEN: usize = HEADER_CRC_RANGE.end;
+/// set in the algorithm byte when the compressed bytes are encrypted,
+/// the builds before encryption take it for an unknown algorithm
+const ENCRYPTED_FLAG: u8 = 0x80;
+
+/// the old header, the first 8 bytes are the part size and the next 2 bytes are the part count
+const LEGACY_PART_SIZE_RANGE: std::ops::Range<usize> = 0..8;
+const LEGACY_PART_COUNT_RANGE: std::ops::Range<usize> = 8..10;
+const LEGACY_HEADER_BYTES_LEN: usize = LEGACY_PART_COUNT_RANGE.end;
+
+/// This is structure for the header bytes
+/// all the numbers are big endian
+/// | magic 4 | version 1 | algorithm 1 | original length 8 | part index 2 | part count 2 |
+/// | part size 8 | mtime 8 | part crc32 4 | header crc32 4 |
+/// the high bit of the algorithm is set when the part is encrypted, the header is followed
+/// by the stream of `EncryptWriter`, which starts with the header again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    version: u8,
+    algorithm: Algorithm,
+    encrypted: bool,
+    /// size of the whole file before it was split and compressed
+    original_len: u64,
+    part_index: u16,
+    part_count: u16,
+    part_size: u64,
+    /// seconds since the unix epoch
+    mtime: u64,
+    /// crc32 of the part as it was read from the source, the old headers have none
+    part_crc: u32,
+}
+
+impl From<Header> for [u8; HEADER_BYTES_LEN] {
+    fn from(header: Header) -> Self {
+        header.bytes()
+    }
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Header {
+    pub const fn new() -> Self {
+        Self {
+            version: HEADER_VERSION,
+            algorithm: Algorithm::None,
+            encrypted: false,
+            original_len: 0,
+            part_index: 0,
+            part_count: 0,
+            part_size: 0,
+            mtime: 0,
+            part_crc: 0,
+        }
+    }
+
+    /// length of the headers written by this build
+    pub const fn len() -> usize {
+        HEADER_BYTES_LEN
+    }
+
+    /// how many bytes this header took, old headers are shorter
+    pub fn bytes_len(&self) -> usize {
+        if self.version == 0 {
+            LEGACY_HEADER_BYTES_LEN
+        } else {
+            HEADER_BYTES_LEN
+        }
+    }
+
+    /// parses the header at the start of `bytes`, the rest of `bytes` is left alone
+    /// the bytes without our magic are read as the old header
+    /// # Arguments
+    /// * `path` - the part the bytes were read from, for the error
+    pub fn from_bytes<P: AsRef<Path>>(bytes: &[u8], path: P) -> PropErrnoResult<Self> {
+        let corrupted = || {
+            log::error!("{}: corrupted header", path.as_ref().to_string_lossy());
+            PropErrno::CorruptedHeaderVal(path.as_ref().parent_and_current())
+        };
+
+        if !bytes.starts_with(&PART_MAGIC) {
+            if bytes.len() < LEGACY_HEADER_BYTES_LEN {
+                return Err(corrupted());
+            }
+
+            let mut header = Self::new();
+            header.version = 0;
+            header.part_size =
+                u64::from_be_bytes(bytes[LEGACY_PART_SIZE_RANGE].try_into().unwrap());
+            header.part_count =
+                u16::from_be_bytes(bytes[LEGACY_PART_COUNT_RANGE].try_into().unwrap());
+            return Ok(header);
+        }
+
+        if bytes.len() < HEADER_BYTES_LEN {
+            return Err(corrupted());
+        }
+        let bytes = &bytes[..HEADER_BYTES_LEN];
+        let crc = u32::from_be_bytes(bytes[HEADER_CRC_RANGE].try_into().unwrap());
+        if crc != crc32fast::hash(&bytes[..HEADER_CRC_RANGE.start]) {
+            return Err(corrupted());
+        }
+        // a newer build wrote it, the fields might not mean the same thing
+        if bytes[VERSION_POS] != HEADER_VERSION {
+            return Err(corrupted());
+        }
+
+        let algorithm = bytes[ALGORITHM_POS];
+        Ok(Self {
+            version: bytes[VERSION_POS],
+            algorithm: algorithm_from_id(algorithm & !ENCRYPTED_FLAG).ok_or_else(corrupted)?,
+            encrypted: algorithm & ENCRYPTED_FLAG != 0,
+            original_len: u64::from_be_bytes(bytes[ORIGINAL_LEN_RANGE].try_into().unwrap()),
+            part_index: u16::from_be_bytes(bytes[PART_INDEX_RANGE].try_into().unwrap()),
+            part_count: u16::from_be_bytes(bytes[PART_COUNT_RANGE].try_into().unwrap()),
+            part_size: u64::from_be_bytes(bytes[PART_SIZE_RANGE].try_into().unwrap()),
+            mtime: u64::from_be_bytes(bytes[MTIME_RANGE].try_into().unwrap()),
+            part_crc: u32::from_be_bytes(bytes[PART_CRC_RANGE].try_into().unwrap()),
+        })
+    }
+
+    /// the header in the current version, whatever version it was read from
+    pub fn bytes(&self) -> [u8; HEADER_BYTES_LEN] {
+        let mut bytes = [0; HEADER_BYTES_LEN];
+        bytes[MAGIC_RANGE].copy_from_slice(&PART_MAGIC);
+        bytes[VERSION_POS] = HEADER_VERSION;
+        bytes[ALGORITHM_POS] = algorithm_id(&self.algorithm);
+        if self.encrypted {
+            bytes[ALGORITHM_POS] |= ENCRYPTED_FLAG;
+        }
+        bytes[ORIGINAL_LEN_RANGE].copy_from_slice(&self.original_len.to_be_bytes());
+        bytes[PART_INDEX_RANGE].copy_from_slice(&self.part_index.to_be_bytes());
+        bytes[PART_COUNT_RANGE].copy_from_slice(&self.part_count.to_be_bytes());
+        bytes[PART_SIZE_RANGE].copy_from_slice(&self.part_size.to_be_bytes());
+        bytes[MTIME_RANGE].copy_from_slice(&self.mtime.to_be_bytes());
+        bytes[PART_CRC_RANGE].copy_from_slice(&self.part_crc.to_be_bytes());
+        let crc = crc32fast::hash(&bytes[..HEADER_CRC_RANGE.start]);
+        bytes[HEADER_CRC_RANGE].copy_from_slice(&crc.to_be_bytes());
+        bytes
+    }
+
+    pub fn set_part_size(&mut self, size: &u64) {
+        self.part_size = *size;
+    }
+
+    pub fn set_part_count(&mut self, count: &u16) {
+        self.part_count = *count;
+    }
+
+    pub fn set_part_index(&mut self, index: &u16) {
+        self.part_index = *index;
+    }
+
+    pub fn set_algorithm(&mut self, algorithm: Algorithm) {
+        self.algorithm = algorithm;
+    }
+
+    pub fn set_encrypted(&mut self, encrypted: bool) {
+        self.encrypted = encrypted;
+    }
+
+    pub fn set_original_len(&mut self, len: &u64) {
+        self.original_len = *len;
+    }
+
+    pub fn set_mtime(&mut self, mtime: &u64) {
+        self.mtime = *mtime;
+    }
+
+    /// # Arguments
+    /// * `crc` - crc32 of the bytes of the part before compression
+    pub fn set_part_crc(&mut self, crc: &u32) {
+        self.part_crc = *crc;
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    pub fn original_len(&self) -> u64 {
+        self.original_len
+    }
+
+    pub fn part_index(&self) -> u16 {
+        self.part_index
+    }
+
+    pub fn part_size(&self) -> u64 {
+        self.part_size
+    }
+
+    pub fn part_count(&self) -> u16 {
+        self.part_count
+    }
+
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    pub fn part_crc(&self) -> u32 {
+        self.part_crc
+    }
+
+    /// checks the part against its crc, old headers have no crc and always pass
+    /// NOTE: 0 is a real crc, the crc of an empty part is 0
+    /// # Arguments
+    /// * `crc` - crc32 of the bytes of the part after decompression
+    /// * `path` - the part the bytes were read from, for the error
+    pub fn verify_part<P: AsRef<Path>>(&self, crc: &u32, path: P) -> PropErrnoResult<()> {
+        if self.version == 0 || self.part_crc == *crc {
+            return Ok(());
+        }
+
+        log::error!(
+            "{}: the part does not match its crc",
+            path.as_ref().to_string_lossy()
+        );
+        Err(PropErrno::CorruptedFileVal(
+            path.as_ref().parent_and_current(),
+        ))
+    }
+}
+
+/// NOTE: these ids are written to disk, never reuse or change one
+fn algorithm_id(algorithm: &Algorithm) -> u8 {
+    match algorithm {
+        Algorithm::None => 0,
+        Algorithm::Bzip2 => 1,
+        Algorithm::Xz => 2,
+        Algorithm::Brotli => 3,
+        Algorithm::Zstd => 4,
+        Algorithm::Lz4 => 5,
+        Algorithm::Gzip => 6,
+    }
+}
+
+fn algorithm_from_id(id: u8) -> Option<Algorithm> {
+    match id {
+        0 => Some(Algorithm::None),
+        1 => Some(Algorithm::Bzip2),
+        2 => Some(Algorithm::Xz),
+        3 => Some(Algorithm::Brotli),
+        4 => Some(Algorithm::Zstd),
+        5 => Some(Algorithm::Lz4),
+        6 => Some(Algorithm::Gzip),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_roundtrip() {
+        let part = b"the quick brown fox".repeat(16);
+        let mut header = Header::new();
+        header.set_algorithm(Algorithm::Zstd);
+        header.set_encrypted(true);
+        header.set_original_len(&(1 << 40));
+        header.set_part_index(&2);
+        header.set_part_count(&4);
+        header.set_part_size(&(1 << 38));
+        header.set_mtime(&1_700_000_000);
+        header.set_part_crc(&crc32fast::hash(&part));
+
+        let mut bytes = header.bytes().to_vec();
+        // whatever follows the header is not part of it
+        bytes.extend_from_slice(&part);
+        let read = Header::from_bytes(&bytes, "dst/file.zst0").unwrap();
+        assert_eq!(read, header);
+        assert_eq!(read.bytes_len(), Header::len());
+        assert!(read
+            .verify_part(&crc32fast::hash(&part), "dst/file.zst0")
+            .is_ok());
+        assert!(read
+            .verify_part(&crc32fast::hash(b"something else"), "dst/file.zst0")
+            .is_err());
+
+        // a flipped bit is caught by the header crc
+        bytes[ORIGINAL_LEN_RANGE.start] ^= 1;
+        assert!(matches!(
+            Header::from_bytes(&bytes, "dst/file.zst0"),
+            Err(PropErrno::CorruptedHeaderVal(_))
+        ));
+        // too short
+        assert!(Header::from_bytes(&header.bytes()[..20], "dst/file.zst0").is_err());
+
+        // an empty part has a crc of 0, it is still checked
+        header.set_part_crc(&crc32fast::hash(&[]));
+        assert_eq!(header.part_crc(), 0);
+        let read = Header::from_bytes(&header.bytes(), "dst/file.zst0").unwrap();
+        assert!(read.verify_part(&0, "dst/file.zst0").is_ok());
+        assert!(read
+            .verify_part(&crc32fast::hash(&part), "dst/file.zst0")
+            .is_err());
+        assert!(Header::from_bytes(&[0; 4], "dst/file.zst0").is_err());
+    }
+
+    #[test]
+    fn test_legacy_header() {
+        let mut bytes = [0; LEGACY_HEADER_BYTES_LEN];
+        bytes[LEGACY_PART_SIZE_RANGE].copy_from_slice(&8192u64.to_be_bytes());
+        bytes[LEGACY_PART_COUNT_RANGE].copy_from_slice(&3u16.to_be_bytes());
+
+        let header = Header::from_bytes(&bytes, "dst/file.xz0").unwrap();
+        assert_eq!(header.version(), 0);
+        assert_eq!(header.part_size(), 8192);
+        assert_eq!(header.part_count(), 3);
+        assert_eq!(header.bytes_len(), LEGACY_HEADER_BYTES_LEN);
+        assert!(header
+            .verify_part(&crc32fast::hash(b"anything"), "dst/file.xz0")
+            .is_ok());
+    }
+}
@@ -0,0 +1,1238 @@
+use super::{
+    batch::Batch,
+    buffer_tuner::{DeviceBufferSizes, MAX_TUNED_SIZE},
+    chunk::{ChunkAllocator, ChunkPool},
+    concurrency::ConcurrencyController,
+    dst_path::DstPath,
+    file_copier::FileCopier,
+    file_info::FileInfo,
+    file_splitter::FileSplitter,
+    memory::MemoryBudget,
+    observer::{Reporter, TransferEvent, TransferObserver},
+    scheduler::{Scheduled, Scheduler},
+    settings::{ConflictPolicy, FileSplitterKind, Settings, MIN_WORKERS},
+    worker::Worker,
+};
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use super::{settings::CopyBackend, uring};
+use crate::{
+    archive::{ArchiveWriter, ARCHIVE_EXT, WRITER_MEMORY},
+    compression::{
+        dictionary::{DictionaryTrainer, MAX_SAMPLES_SIZE},
+        policy::DictionaryPolicy,
+    },
+    dedup::{EntryStorer, SnapshotWriter, StoredEntry, FILE_MEMORY, STORE_EXT},
+    encryption::{check_password, parse_recipient, EncryptionKey},
+    errnos::{Errno, ErrnoResult, PropErrno, PropErrnoParams, PropErrnoResult},
+    fs::{
+        decision::{Decider, Decision, DecisionEntry, UserDecision},
+        traversal::DirTraversal,
+    },
+    path::PathExt,
+    shared::throttle::RateLimiter,
+};
+use futures::{
+    stream::{FuturesOrdered, FuturesUnordered},
+    StreamExt,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+use walkdir::DirEntry as WalkDirEntry;
+
+/// A batch is handed to a worker once its files add up to this many bytes
+const MAX_BATCH_BYTES: u64 = 8 * 1024 * 1024; // 8MB
+/// The idle buffers of the chunk pool can take up this fraction of the memory budget
+const IDLE_POOL_SHARE: u64 = 8;
+
+/// Sets up a [`TransferJob`]
+/// # Example
+/// ```no_run
+/// # async fn run() -> transfer_engine::errnos::ErrnoResult<()> {
+/// use transfer_engine::transfer::{TransferBuilder, TransferEvent};
+///
+/// let (sender, receiver) = async_channel::unbounded::<TransferEvent>();
+/// let job = TransferBuilder::new("/home/user/photos", "/media/usb")
+///     .set_observer(sender)
+///     .build()?;
+/// tokio::spawn(job.run());
+/// while let Ok(event) = receiver.recv().await {
+///     println!("{:?}", event);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct TransferBuilder {
+    src: PathBuf,
+    dst: PathBuf,
+    settings: Settings,
+    observer: Option<Arc<dyn TransferObserver>>,
+    decider: Option<Arc<dyn Decider>>,
+    buffer_sizes: DeviceBufferSizes,
+    limiter: Option<RateLimiter>,
+    memory_budget: Option<MemoryBudget>,
+    password: Option<String>,
+}
+
+impl TransferBuilder {
+    /// # Arguments
+    /// * `src` - the file or directory to copy
+    /// * `dst` - the directory the `src` will be copied into
+    pub fn new<P: AsRef<Path>>(src: P, dst: P) -> Self {
+        Self {
+            src: src.as_ref().to_path_buf(),
+            dst: dst.as_ref().to_path_buf(),
+            settings: Settings::default(),
+            observer: None,
+            decider: None,
+            buffer_sizes: DeviceBufferSizes::new(),
+            limiter: None,
+            memory_budget: None,
+            password: None,
+        }
+    }
+
+    pub fn set_settings(mut self, settings: Settings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// all the progress and errors of the transfer will be sent to this observer
+    pub fn set_observer<O: TransferObserver + 'static>(mut self, observer: O) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// this will be asked what to do whenever a file already exists in the destination
+    pub fn set_decider<D: Decider + 'static>(mut self, decider: D) -> Self {
+        self.decider = Some(Arc::new(decider));
+        self
+    }
+
+    /// the best buffer sizes found by other jobs, this is shared by all the jobs of a `JobManager`
+    pub fn set_buffer_sizes(mut self, buffer_sizes: DeviceBufferSizes) -> Self {
+        self.buffer_sizes = buffer_sizes;
+        self
+    }
+
+    /// shares the bandwidth with other jobs, the limit can be changed while the job runs.
+    /// without it the job gets its own limiter with the bandwidth limit of the settings
+    pub fn set_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// shares the memory budget with other jobs.
+    /// without it the job gets its own budget with the size from the settings
+    pub fn set_memory_budget(mut self, memory_budget: MemoryBudget) -> Self {
+        self.memory_budget = Some(memory_budget);
+        self
+    }
+
+    /// encrypts the archive or the parts with a key derived from the password.
+    /// it is not part of the settings so it is never saved with a profile
+    /// NOTE: only the archive and the split splitters can encrypt,
+    /// the recipients of the settings only work with the archive
+    pub fn set_password(mut self, password: Option<String>) -> Self {
+        self.password = password;
+        self
+    }
+
+    pub fn build(self) -> ErrnoResult<TransferJob> {
+        let mut params = PropErrnoParams::new_with_src_and_dst(
+            self.src.parent_and_current(),
+            self.dst.parent_and_current(),
+        );
+
+        if !self.src.exists() {
+            return Err(Errno::from_prop_errno(PropErrno::PathNotFound, &mut params));
+        }
+
+        if !self.dst.is_dir() {
+            return Err(Errno::from_prop_errno(
+                PropErrno::ExpectedDstDir,
+                &mut params,
+            ));
+        }
+
+        self.settings.validate()?;
+
+        let recipients = !self.settings.recipients().is_empty();
+        let splitter = self.settings.splitter();
+        // the copies are plain files, there is nowhere to keep the encrypted data.
+        // the parts have no index to keep the key wrapped to the recipients in
+        let can_encrypt = match splitter {
+            Some(FileSplitterKind::Archive) => true,
+            Some(FileSplitterKind::Split) => !recipients,
+            _ => false,
+        };
+        if (self.password.is_some() || recipients) && !can_encrypt {
+            return Err(Errno::from_prop_errno(PropErrno::Encrypt, &mut params));
+        }
+        if let Some(password) = &self.password {
+            // the archive has a single key, it comes from one or the other
+            if recipients {
+                return Err(Errno::from_prop_errno(PropErrno::Encrypt, &mut params));
+            }
+            check_password(password).map_err(|err| Errno::from_prop_errno(err, &mut params))?;
+        }
+
+        let dst_path = DstPath::new(self.dst.clone())
+            .ok_or_else(|| Errno::from_prop_errno(PropErrno::PathNormalize, &mut params))?;
+
+        // the decider is only asked when the settings leave it up to the user
+        let decision_all = match self.settings.conflict() {
+            ConflictPolicy::Ask => None,
+            ConflictPolicy::Skip => Some(Decision::Skip),
+            ConflictPolicy::Replace => Some(Decision::Replace),
+        };
+
+        let scheduler = Scheduler::new(self.settings.schedule());
+        let limiter = self
+            .limiter
+            .unwrap_or_else(|| RateLimiter::new(self.settings.bandwidth_limit()));
+        let memory_budget = self
+            .memory_budget
+            .unwrap_or_else(|| MemoryBudget::new(self.settings.memory_budget()));
+        let chunk_pool = ChunkPool::with_idle_limit(memory_budget.limit() / IDLE_POOL_SHARE);
+
+        Ok(TransferJob {
+            src: self.src,
+            dst: self.dst,
+            dst_path,
+            settings: self.settings,
+            reporter: Reporter::new(self.observer),
+            decider: self.decider,
+            decision_all,
+            buffer_sizes: self.buffer_sizes,
+            limiter,
+            memory_budget,
+            chunk_pool,
+            batch: Batch::new(),
+            scheduler,
+            is_walked: false,
+            password: self.password,
+            key: None,
+        })
+    }
+}
+
+/// A single copy of `src` into `dst`
+/// nothing happens until [`TransferJob::run`] is awaited
+pub struct TransferJob {
+    src: PathBuf,
+    dst: PathBuf,
+    dst_path: DstPath,
+    settings: Settings,
+    reporter: Reporter,
+    decider: Option<Arc<dyn Decider>>,
+    /// set once the user chooses to skip or replace all the existing files
+    decision_all: Option<Decision>,
+    buffer_sizes: DeviceBufferSizes,
+    limiter: RateLimiter,
+    /// a worker only starts once the memory of its buffers is reserved
+    memory_budget: MemoryBudget,
+    /// buffers of the files copied with O_DIRECT, of the batches and of the parts
+    /// its idle buffers are reserved from the memory budget while the job runs
+    chunk_pool: ChunkPool,
+    /// small files waiting to be copied together
+    batch: Batch,
+    /// files ready to be copied, in the order of the schedule policy
+    scheduler: Scheduler,
+    /// the traversal has no more entries
+    is_walked: bool,
+    /// the archive or the parts are encrypted with a key derived from it
+    password: Option<String>,
+    /// derived from the password once for all the parts
+    key: Option<EncryptionKey>,
+}
+
+impl TransferJob {
+    pub fn src(&self) -> &Path {
+        &self.src
+    }
+
+    pub fn dst(&self) -> &Path {
+        &self.dst
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// runs the transfer to completion
+    /// errors with individual entries do not stop the transfer,
+    /// they are sent to the observer instead
+    /// NOTE: this must be called from within a tokio runtime
+    pub async fn run(mut self) {
+        match self.settings.splitter() {
+            Some(FileSplitterKind::Archive) => return self.run_archive().await,
+            Some(FileSplitterKind::Dedup) => return self.run_dedup().await,
+            _ => {}
+        }
+
+        if let Some(password) = &self.password {
+            match EncryptionKey::from_password(password).await {
+                Ok(key) => self.key = Some(key),
+                Err(err) => {
+                    self.reporter.prop_error(err, &self.src, &self.dst);
+                    self.reporter.notify(TransferEvent::Completed);
+                    return;
+                }
+            }
+        }
+
+        // the buffers kept by the pool are not held by any chunk so they are counted here
+        let _idle = self
+            .memory_budget
+            .reserve(self.chunk_pool.idle_limit())
+            .await;
+        let mut traversal = DirTraversal::new(&self.src);
+        let mut workers = FuturesUnordered::new();
+        let mut controller = self.controller();
+        // ids of the workers that are done, they are handed to the next workers
+        let mut free_ids: Vec<u8> = Vec::new();
+        let mut next_id: u8 = 0;
+        let mut is_traversed = false;
+
+        loop {
+            while !is_traversed && workers.len() < controller.limit() {
+                let id = free_ids.pop().unwrap_or_else(|| {
+                    next_id += 1;
+                    next_id - 1
+                });
+
+                match self.create_new(&mut traversal, id).await {
+                    Some(worker) => workers.push(worker),
+                    None => is_traversed = true,
+                }
+            }
+
+            let (id, latency) = match workers.next().await {
+                Some(done) => done,
+                // nothing running and nothing left to run
+                None => break,
+            };
+
+            self.reporter.notify(TransferEvent::WorkerDone(id));
+            self.reporter
+                .notify(TransferEvent::Memory(self.memory_budget.used()));
+            self.update_total(&mut traversal).await;
+            controller.record(self.reporter.take_processed(), latency);
+            free_ids.push(id);
+        }
+
+        self.update_total(&mut traversal).await;
+        self.reporter.notify_compression();
+        self.reporter
+            .notify(TransferEvent::Pool(self.chunk_pool.stats()));
+        self.reporter.notify(TransferEvent::Completed);
+    }
+
+    /// packs the source into a single archive in the destination instead of copying it
+    async fn run_archive(mut self) {
+        let mut name = self.src.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}", ARCHIVE_EXT));
+        let archive = self.dst.join(name);
+        if !self.should_replace(&archive).await {
+            self.reporter.notify(TransferEvent::Completed);
+            return;
+        }
+
+        let _permit = self.memory_budget.reserve(WRITER_MEMORY).await;
+        // the archive has no performance of its own, the levels follow the one of the job
+        let policy = self.settings.compression().for_perf(self.settings.perf());
+        let writer = ArchiveWriter::create(&archive, policy).await;
+        let writer = match writer {
+            Ok(writer) => self.encrypt_archive(writer).await,
+            Err(err) => Err(err),
+        };
+        let mut writer = match writer {
+            Ok(writer) => writer
+                .set_processed_cb(Some(self.reporter.processed_fn()))
+                .set_rate_limiter(self.limiter.clone())
+                .set_memory_budget(Some(self.memory_budget.clone())),
+            Err(err) => {
+                self.reporter.prop_error(err, &self.src, &archive);
+                self.reporter.notify(TransferEvent::Completed);
+                return;
+            }
+        };
+
+        // the files are compressed without them when they could not be stored
+        if let Err(err) = self.train_dictionaries(&mut writer).await {
+            self.reporter.prop_error(err, &self.src, &archive);
+        }
+
+        // the entries keep the name of the source like the copies do
+        let root = self.src.parent().unwrap_or(Path::new(""));
+        let mut traversal = DirTraversal::new(&self.src);
+        // the entries are written one after the other,
+        // the workers of the controller probe the files ahead of the writer
+        let mut controller = self.controller();
+        let mut probes = FuturesOrdered::new();
+        let mut is_traversed = false;
+        loop {
+            while !is_traversed && probes.len() < controller.limit() {
+                match traversal.get_next() {
+                    Some(Ok(entry)) => probes.push_back(self.probe(entry)),
+                    Some(Err(err)) => {
+                        self.reporter
+                            .prop_error(err, Path::unknown_path(), Path::unknown_path());
+                    }
+                    None => is_traversed = true,
+                }
+            }
+
+            let (entry, info, started) = match probes.next().await {
+                Some(probed) => probed,
+                None => break,
+            };
+            let name = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            let res = match info {
+                Ok(None) if entry.file_type().is_dir() => writer.add_dir(name, entry.path()).await,
+                Ok(None) => writer.add_symlink(name, entry.path()).await,
+                Ok(Some(info)) => {
+                    self.reporter.compression(&info.compression_report());
+                    let algorithm = info.compression().copied().unwrap_or_default();
+                    writer.add_file(name, entry.path(), &algorithm).await
+                }
+                Err(err) => Err(err),
+            };
+
+            if let Err(err) = res {
+                self.reporter.prop_error(err, entry.path(), &archive);
+            }
+            controller.record(self.reporter.take_processed(), started.elapsed());
+            self.update_total(&mut traversal).await;
+        }
+
+        if let Err(err) = writer.finish().await {
+            self.reporter.prop_error(err, &self.src, &archive);
+        }
+        self.update_total(&mut traversal).await;
+        self.reporter.notify_compression();
+        self.reporter.notify(TransferEvent::Completed);
+    }
+
+    /// adds the chunks of the source that are new to the chunk store in the destination
+    /// and writes a snapshot of the source, instead of copying it
+    async fn run_dedup(self) {
+        let mut name = self.src.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}", STORE_EXT));
+        let store = self.dst.join(name);
+        let mut writer = match SnapshotWriter::create(&store).await {
+            Ok(writer) => writer
+                .set_processed_cb(Some(self.reporter.processed_fn()))
+                .set_rate_limiter(self.limiter.clone()),
+            Err(err) => {
+                self.reporter.prop_error(err, &self.src, &store);
+                self.reporter.notify(TransferEvent::Completed);
+                return;
+            }
+        };
+
+        // the entries keep the name of the source like the copies do
+        let root = self.src.parent().unwrap_or(Path::new(""));
+        let mut traversal = DirTraversal::new(&self.src);
+        // the workers of the controller store the files at the same time,
+        // the snapshot keeps them in the order of the traversal
+        let storer = writer.storer();
+        let mut controller = self.controller();
+        let mut stored = FuturesOrdered::new();
+        let mut is_traversed = false;
+        loop {
+            while !is_traversed && stored.len() < controller.limit() {
+                match traversal.get_next() {
+                    Some(Ok(entry)) => stored.push_back(self.store_entry(&storer, root, entry)),
+                    Some(Err(err)) => {
+                        self.reporter
+                            .prop_error(err, Path::unknown_path(), Path::unknown_path());
+                    }
+                    None => is_traversed = true,
+                }
+            }
+
+            let (entry, res, started) = match stored.next().await {
+                Some(done) => done,
+                None => break,
+            };
+            match res {
+                Ok(entry) => writer.push(entry),
+                Err(err) => self.reporter.prop_error(err, entry.path(), &store),
+            }
+            controller.record(self.reporter.take_processed(), started.elapsed());
+            self.update_total(&mut traversal).await;
+        }
+
+        match writer.finish().await {
+            Ok((_, report)) => self.reporter.notify(TransferEvent::Dedup(report)),
+            Err(err) => self.reporter.prop_error(err, &self.src, &store),
+        }
+        self.update_total(&mut traversal).await;
+        self.reporter.notify(TransferEvent::Completed);
+    }
+
+    /// how many workers run at a time, tuned to the disks unless the settings say how many
+    fn controller(&self) -> ConcurrencyController {
+        match self.settings.workers() {
+            Some(workers) => ConcurrencyController::fixed(workers),
+            None => ConcurrencyController::new(MIN_WORKERS, self.settings.worker_threads()),
+        }
+    }
+
+    /// whether to write the archive over the one already in the destination, if any.
+    /// it is decided like for the files of a copy, the source stands for the archive
+    async fn should_replace(&mut self, archive: &Path) -> bool {
+        let entry = match walkdir::WalkDir::new(&self.src)
+            .max_depth(0)
+            .into_iter()
+            .next()
+        {
+            Some(Ok(entry)) => entry,
+            _ => return true,
+        };
+        self.decide(entry, archive).await.is_some()
+    }
+
+    /// probes the compression of a file before it is added to the archive
+    /// returns None for the entries that are not files
+    async fn probe(
+        &self,
+        entry: WalkDirEntry,
+    ) -> (WalkDirEntry, PropErrnoResult<Option<FileInfo>>, Instant) {
+        let started = Instant::now();
+        if entry.file_type().is_dir() || entry.file_type().is_symlink() {
+            return (entry, Ok(None), started);
+        }
+
+        let info = FileInfo::from_path_and_detect(entry.path(), true, self.settings.perf()).await;
+        (entry, info.map(Some), started)
+    }
+
+    /// puts the entry in the chunk store, the files within the memory budget
+    async fn store_entry(
+        &self,
+        storer: &EntryStorer,
+        root: &Path,
+        entry: WalkDirEntry,
+    ) -> (WalkDirEntry, PropErrnoResult<StoredEntry>, Instant) {
+        let started = Instant::now();
+        let name = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        let res = if entry.file_type().is_dir() {
+            storer.dir(name, entry.path()).await
+        } else if entry.file_type().is_symlink() {
+            storer.symlink(name, entry.path()).await
+        } else {
+            let _permit = self.memory_budget.reserve(FILE_MEMORY).await;
+            storer.file(name, entry.path()).await
+        };
+        (entry, res, started)
+    }
+
+    /// samples the small files of the source before any of them is added to the archive
+    /// and stores the dictionaries trained on them in it, if the policy asks for them
+    async fn train_dictionaries(&self, writer: &mut ArchiveWriter) -> PropErrnoResult<()> {
+        let by_ext = match self.settings.compression().dictionary() {
+            DictionaryPolicy::None => return Ok(()),
+            DictionaryPolicy::Job => false,
+            DictionaryPolicy::Extension => true,
+        };
+
+        let _permit = self.memory_budget.reserve(MAX_SAMPLES_SIZE as u64).await;
+        let src = self.src.clone();
+        let trained =
+            tokio::task::spawn_blocking(move || DictionaryTrainer::from_dir(src, by_ext).train())
+                .await;
+        match trained {
+            Ok(dictionaries) => writer.set_dictionaries(dictionaries).await,
+            Err(err) => {
+                log::error!("{}: {}", self.src.to_string_lossy(), err);
+                Ok(())
+            }
+        }
+    }
+
+    /// encrypts the archive to the recipients of the settings or with the password, if any
+    async fn encrypt_archive(&self, writer: ArchiveWriter) -> PropErrnoResult<ArchiveWriter> {
+        if !self.settings.recipients().is_empty() {
+            let recipients = self
+                .settings
+                .recipients()
+                .iter()
+                .map(|recipient| parse_recipient(recipient))
+                .collect::<PropErrnoResult<Vec<_>>>()?;
+            return writer.encrypt_to(&recipients);
+        }
+
+        match &self.password {
+            // the key is derived once, every file gets its own nonce
+            Some(password) => {
+                let key = EncryptionKey::from_password(password).await?;
+                Ok(writer.set_encryption(Some(key)))
+            }
+            None => Ok(writer),
+        }
+    }
+
+    /// sets the total of the progress as soon as the status is calculated
+    async fn update_total(&self, traversal: &mut DirTraversal) {
+        // status has been calculated but not assigned so do that here
+        if traversal.status().is_done() && traversal.status().is_calculating() {
+            traversal.mut_status().calculate().await;
+            if let Some(info) = traversal.status().get_info() {
+                self.reporter.set_total(*info.size());
+            }
+        }
+    }
+
+    /// creates a worker for the next file (or batch of small files) of the schedule
+    /// returns None once there is nothing left to copy
+    async fn create_new(&mut self, traversal: &mut DirTraversal, id: u8) -> Option<Worker> {
+        loop {
+            self.fill_scheduler(traversal).await;
+            let scheduled = match self.scheduler.pop() {
+                Some(scheduled) => scheduled,
+                // whatever is left in the batch is the last worker
+                None => return self.take_batch(id).await,
+            };
+
+            if let Some(FileSplitterKind::Split) = self.settings.splitter() {
+                let splitter = self.new_splitter(scheduled.src, scheduled.dst);
+                return Some(Worker::create_new_splitter(
+                    id,
+                    splitter,
+                    self.reporter.clone(),
+                ));
+            }
+
+            let copier = self.new_copier(scheduled.src, scheduled.dst, scheduled.len);
+            if scheduled.len >= self.settings.batch_threshold() {
+                let permit = self
+                    .memory_budget
+                    .reserve(self.copier_memory(scheduled.len))
+                    .await;
+                return Some(Worker::create_new_copier(
+                    id,
+                    copier,
+                    self.reporter.clone(),
+                    permit,
+                ));
+            }
+
+            self.batch.push(copier, scheduled.len);
+            if self.batch.len() >= self.settings.batch_files()
+                || self.batch.bytes() >= MAX_BATCH_BYTES
+            {
+                return self.take_batch(id).await;
+            }
+        }
+    }
+
+    /// walks the traversal until the scheduler has enough files to choose from
+    async fn fill_scheduler(&mut self, traversal: &mut DirTraversal) {
+        while !self.is_walked && !self.scheduler.is_full() {
+            // get next entry
+            let entry = match traversal.get_next() {
+                Some(Ok(entry)) => entry,
+                None => {
+                    self.is_walked = true;
+                    return;
+                }
+                Some(Err(err)) => {
+                    // these are unknown paths because error will be populated with the correct paths
+                    self.reporter
+                        .prop_error(err, Path::unknown_path(), Path::unknown_path());
+                    continue;
+                }
+            };
+
+            let dst = match self.dst_path.build_dst(&entry) {
+                Ok(dst) => dst.to_path_buf(),
+                Err(err) => {
+                    self.reporter.prop_error(err, entry.path(), &self.dst);
+                    continue;
+                }
+            };
+
+            // directories are created by the DstPath
+            if entry.file_type().is_dir() {
+                continue;
+            }
+
+            if let Some(entry) = self.decide(entry, &dst).await {
+                let len = entry.metadata().map(|meta| meta.len()).unwrap_or(u64::MAX);
+                self.scheduler.push(Scheduled {
+                    src: entry.into_path(),
+                    dst,
+                    len,
+                });
+            }
+        }
+    }
+
+    /// hands the batch of small files to a worker, None if there are none
+    async fn take_batch(&mut self, id: u8) -> Option<Worker> {
+        if self.batch.is_empty() {
+            return None;
+        }
+
+        let batch = std::mem::take(&mut self.batch);
+        let permit = self.memory_budget.reserve(batch.largest()).await;
+        Some(Worker::create_new_batch(
+            id,
+            batch,
+            self.chunk_pool.clone(),
+            self.reporter.clone(),
+            permit,
+        ))
+    }
+
+    /// the most memory the buffers of a copier of a `len` bytes file can take
+    fn copier_memory(&self, len: u64) -> u64 {
+        // the tuner never grows the buffer past the file
+        // and O_DIRECT never has more than this in flight either
+        if self.settings.adaptive_buffer() || self.is_direct(len) {
+            return (MAX_TUNED_SIZE as u64).min(len);
+        }
+        // the buffers of io_uring are only as big as the file
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if self.settings.backend() == CopyBackend::IoUring && uring::is_supported() {
+            return uring::copy_memory(len);
+        }
+
+        // a read and a write buffer
+        2 * self.settings.buffer_size() as u64
+    }
+
+    /// whether a file of `len` bytes should skip the page cache
+    fn is_direct(&self, len: u64) -> bool {
+        self.settings
+            .direct_io_threshold()
+            .is_some_and(|threshold| len >= threshold)
+    }
+
+    fn new_copier(&self, src: PathBuf, dst: PathBuf, len: u64) -> FileCopier {
+        let tuning = self
+            .settings
+            .adaptive_buffer()
+            .then(|| self.buffer_sizes.clone());
+
+        FileCopier::new(src, dst, self.reporter.processed_fn())
+            .set_buffer_size(self.settings.buffer_size())
+            .set_tuning(tuning)
+            .set_metadata(self.settings.metadata())
+            .set_durability(self.settings.durability())
+            .set_rate_limiter(self.limiter.clone())
+            .set_backend(self.settings.backend())
+            .set_direct_io(self.is_direct(len).then(|| self.chunk_pool.clone()))
+            .set_cache(self.settings.cache())
+    }
+
+    /// the parts are read in chunks of the pool within the memory budget of the job
+    fn new_splitter(&self, src: PathBuf, dst: PathBuf) -> FileSplitter {
+        FileSplitter::new(
+            src,
+            dst,
+            *self.settings.perf(),
+            self.settings.compression().clone(),
+            ChunkAllocator::new(self.memory_budget.clone(), self.chunk_pool.clone()),
+            self.reporter.clone(),
+        )
+        .set_backend(self.settings.backend())
+        .set_encryption(self.key.clone())
+    }
+
+    /// checks if the destination already exists and if so asks the decider what to do
+    /// returns the entry back if it should be copied
+    async fn decide(&mut self, entry: WalkDirEntry, dst: &Path) -> Option<WalkDirEntry> {
+        let dst_meta = match dst.metadata() {
+            Ok(meta) => meta,
+            // nothing to replace
+            Err(_) => return Some(entry),
+        };
+
+        let decision = match (&self.decision_all, &self.decider) {
+            (Some(decision), _) => *decision,
+            // nobody to ask, keep the old behaviour of replacing the file
+            (None, None) => Decision::Replace,
+            (None, Some(decider)) => {
+                let is_duplicate = entry.metadata().ok().is_some_and(|src_meta| {
+                    src_meta.len() == dst_meta.len()
+                        && src_meta.modified().ok() == dst_meta.modified().ok()
+                });
+
+                let decision_entry = if is_duplicate {
+                    DecisionEntry::Duplicate(entry, Decision::NeedInput)
+                } else {
+                    DecisionEntry::Modified(entry, Decision::NeedInput)
+                };
+
+                let user_decision = decider.decide(&decision_entry).await;
+                let decision_entry = decision_entry.update_decision(user_decision);
+                let decision = *decision_entry.decision();
+                if let UserDecision::SkipAll | UserDecision::ReplaceAll = user_decision {
+                    self.decision_all = Some(decision);
+                }
+
+                return match decision {
+                    Decision::Replace => Some(decision_entry.into_entry()),
+                    _ => None,
+                };
+            }
+        };
+
+        match decision {
+            Decision::Replace => Some(entry),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compression::policy::CompressionPolicy, utils::tmp::tmp_dir};
+    use async_trait::async_trait;
+
+    async fn collect(receiver: async_channel::Receiver<TransferEvent>) -> Vec<TransferEvent> {
+        let mut events = Vec::new();
+        while let Ok(event) = receiver.recv().await {
+            let completed = matches!(event, TransferEvent::Completed);
+            events.push(event);
+            if completed {
+                break;
+            }
+        }
+
+        events
+    }
+
+    #[tokio::test]
+    async fn test_transfer_job() {
+        let src = PathBuf::from("../testing");
+        let dst = tmp_dir("transfer_engine_job");
+        let (sender, receiver) = async_channel::unbounded();
+
+        let job = TransferBuilder::new(&src, &dst)
+            .set_observer(sender)
+            .build()
+            .unwrap();
+        job.run().await;
+
+        let events = collect(receiver).await;
+        assert!(!events.iter().any(|e| matches!(e, TransferEvent::Error(_))));
+
+        let processed: u64 = events
+            .iter()
+            .map(|e| match e {
+                TransferEvent::Processed(n) => *n,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(processed, 249);
+        assert_eq!(
+            std::fs::read("../testing/dir3/item3").unwrap(),
+            std::fs::read(dst.join("testing/dir3/item3")).unwrap()
+        );
+        assert!(dst.join("testing/dir2/dir /item_in2.txt").is_file());
+    }
+
+    #[tokio::test]
+    async fn test_split_job() {
+        let src = PathBuf::from("../testing");
+        let dst = tmp_dir("transfer_engine_split_job");
+        let (sender, receiver) = async_channel::unbounded();
+        let budget = MemoryBudget::new(64 * 1024);
+
+        let settings = Settings::default().set_splitter(Some(FileSplitterKind::Split));
+        let job = TransferBuilder::new(&src, &dst)
+            .set_settings(settings)
+            .set_observer(sender)
+            .set_memory_budget(budget.clone())
+            .build()
+            .unwrap();
+        job.run().await;
+
+        let events = collect(receiver).await;
+        assert!(!events.iter().any(|e| matches!(e, TransferEvent::Error(_))));
+        let processed: u64 = events
+            .iter()
+            .map(|e| match e {
+                TransferEvent::Processed(n) => *n,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(processed, 249);
+        assert_eq!(budget.used(), 0);
+        let stats = events
+            .iter()
+            .find_map(|e| match e {
+                TransferEvent::Pool(stats) => Some(*stats),
+                _ => None,
+            })
+            .unwrap();
+        assert!(stats.misses > 0);
+        assert!(stats.idle <= budget.limit() / IDLE_POOL_SHARE);
+        let compressed = events
+            .iter()
+            .filter(|e| matches!(e, TransferEvent::Compression(summary) if summary.files == 7))
+            .count();
+        assert_eq!(compressed, 1);
+
+        // the file is replaced by its parts
+        let names: Vec<_> = std::fs::read_dir(dst.join("testing/dir3"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(names.len(), 1);
+        assert!(names[0].starts_with("item3.") && names[0].ends_with('0'));
+    }
+
+    #[tokio::test]
+    async fn test_archive_job() {
+        let src = PathBuf::from("../testing");
+        let dst = tmp_dir("transfer_engine_archive_job");
+        let (sender, receiver) = async_channel::unbounded();
+
+        let settings = Settings::default().set_splitter(Some(FileSplitterKind::Archive));
+        let job = TransferBuilder::new(&src, &dst)
+            .set_settings(settings)
+            .set_observer(sender)
+            .build()
+            .unwrap();
+        job.run().await;
+
+        let events = collect(receiver).await;
+        assert!(!events.iter().any(|e| matches!(e, TransferEvent::Error(_))));
+        // nothing but the archive is written
+        assert!(!dst.join("testing").exists());
+        // one summary for all the files
+        let summaries: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                TransferEvent::Compression(summary) => Some(summary),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!((summaries[0].files, summaries[0].bytes), (7, 249));
+
+        let mut reader = crate::archive::ArchiveReader::open(dst.join("testing.sppa"))
+            .await
+            .unwrap();
+        assert!(reader.find("testing/dir2/dir /item_in2.txt").is_some());
+        let unpacked = dst.join("unpacked");
+        reader.unpack(&unpacked).await.unwrap();
+        assert_eq!(
+            std::fs::read("../testing/dir3/item3").unwrap(),
+            std::fs::read(unpacked.join("testing/dir3/item3")).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_archive_job_conflict() {
+        let src = PathBuf::from("../testing/dir3");
+        let dst = tmp_dir("transfer_engine_archive_job_conflict");
+        std::fs::write(dst.join("dir3.sppa"), "old").unwrap();
+        let archive = Settings::default().set_splitter(Some(FileSplitterKind::Archive));
+
+        for (conflict, is_replaced) in [
+            (ConflictPolicy::Skip, false),
+            (ConflictPolicy::Replace, true),
+        ] {
+            let (sender, receiver) = async_channel::unbounded();
+            let settings = archive
+                .clone()
+                .set_conflict(conflict)
+                .set_bandwidth_limit(Some(1024 * 1024));
+            let job = TransferBuilder::new(&src, &dst)
+                .set_settings(settings)
+                .set_observer(sender)
+                .build()
+                .unwrap();
+            job.run().await;
+
+            let events = collect(receiver).await;
+            assert!(!events.iter().any(|e| matches!(e, TransferEvent::Error(_))));
+            let is_archive = crate::archive::ArchiveReader::open(dst.join("dir3.sppa"))
+                .await
+                .is_ok();
+            assert_eq!(is_archive, is_replaced);
+        }
+
+        // nothing is left of the archive while it was written
+        let names: Vec<_> = std::fs::read_dir(&dst)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["dir3.sppa"]);
+    }
+
+    #[tokio::test]
+    async fn test_dictionary_archive_job() {
+        let dir = tmp_dir("transfer_engine_dictionary_archive_job");
+        let src = dir.join("logs");
+        std::fs::create_dir_all(&src).unwrap();
+        for i in 0..32 {
+            let line = format!(
+                "2024-01-{:02} INFO request {} served in {}ms\n",
+                i % 28,
+                i,
+                i * 3
+            );
+            std::fs::write(src.join(format!("{}.log", i)), line.repeat(20)).unwrap();
+        }
+        let dst = dir.join("dst");
+        std::fs::create_dir_all(&dst).unwrap();
+        let (sender, receiver) = async_channel::unbounded();
+
+        let policy = CompressionPolicy::default().set_dictionary(DictionaryPolicy::Job);
+        let settings = Settings::default()
+            .set_splitter(Some(FileSplitterKind::Archive))
+            .set_compression(policy);
+        let job = TransferBuilder::new(&src, &dst)
+            .set_settings(settings)
+            .set_observer(sender)
+            .build()
+            .unwrap();
+        job.run().await;
+
+        let events = collect(receiver).await;
+        assert!(!events.iter().any(|e| matches!(e, TransferEvent::Error(_))));
+        let mut reader = crate::archive::ArchiveReader::open(dst.join("logs.sppa"))
+            .await
+            .unwrap();
+        let entry = reader.find("logs/3.log").unwrap();
+        assert_eq!(entry.dictionary(), Some("_"));
+        let unpacked = dir.join("unpacked");
+        reader.unpack(&unpacked).await.unwrap();
+        assert_eq!(
+            std::fs::read(src.join("3.log")).unwrap(),
+            std::fs::read(unpacked.join("logs/3.log")).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_archive_job() {
+        let src = PathBuf::from("../testing/dir3");
+        let dst = tmp_dir("transfer_engine_encrypted_archive_job");
+        let (sender, receiver) = async_channel::unbounded();
+        let password = "correct horse battery".to_string();
+
+        let res = TransferBuilder::new(&src, &dst)
+            .set_password(Some(password.clone()))
+            .build();
+        assert_eq!(res.err().unwrap().code(), "encrypt_err");
+
+        let settings = Settings::default().set_splitter(Some(FileSplitterKind::Archive));
+        let res = TransferBuilder::new(&src, &dst)
+            .set_settings(settings.clone())
+            .set_password(Some("short".to_string()))
+            .build();
+        assert_eq!(res.err().unwrap().code(), "password_len_err");
+
+        let job = TransferBuilder::new(&src, &dst)
+            .set_settings(settings)
+            .set_password(Some(password.clone()))
+            .set_observer(sender)
+            .build()
+            .unwrap();
+        job.run().await;
+        let events = collect(receiver).await;
+        assert!(!events.iter().any(|e| matches!(e, TransferEvent::Error(_))));
+
+        let reader = crate::archive::ArchiveReader::open(dst.join("dir3.sppa"))
+            .await
+            .unwrap();
+        assert!(reader.find("dir3/item3").unwrap().is_encrypted());
+        let mut reader = reader.set_password(Some(password));
+        let unpacked = dst.join("unpacked");
+        reader.unpack(&unpacked).await.unwrap();
+        assert_eq!(
+            std::fs::read(src.join("item3")).unwrap(),
+            std::fs::read(unpacked.join("dir3/item3")).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_split_job() {
+        use crate::transfer::{header::Header, FileAssembler};
+
+        let src = PathBuf::from("../testing/dir3");
+        let dst = tmp_dir("transfer_engine_encrypted_split_job");
+        let (sender, receiver) = async_channel::unbounded();
+        let password = "correct horse battery".to_string();
+
+        let settings = Settings::default()
+            .set_splitter(Some(FileSplitterKind::Split))
+            .set_recipients(vec!["age1somebody".to_string()]);
+        let res = TransferBuilder::new(&src, &dst)
+            .set_settings(settings)
+            .build();
+        assert_eq!(res.err().unwrap().code(), "encrypt_err");
+
+        let settings = Settings::default().set_splitter(Some(FileSplitterKind::Split));
+        let job = TransferBuilder::new(&src, &dst)
+            .set_settings(settings)
+            .set_password(Some(password.clone()))
+            .set_observer(sender)
+            .build()
+            .unwrap();
+        job.run().await;
+        let events = collect(receiver).await;
+        assert!(!events.iter().any(|e| matches!(e, TransferEvent::Error(_))));
+
+        let first = std::fs::read_dir(dst.join("dir3"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .next()
+            .unwrap();
+        let header = std::fs::read(&first).unwrap();
+        assert!(Header::from_bytes(&header, &first).unwrap().is_encrypted());
+        let assembled = dst.join("item3");
+        for password in [None, Some("wrong password".to_string())] {
+            let res = FileAssembler::new(&first, &assembled)
+                .set_password(password)
+                .assemble()
+                .await;
+            assert!(res.is_err());
+            assert!(!assembled.exists());
+        }
+
+        FileAssembler::new(&first, &assembled)
+            .set_password(Some(password))
+            .assemble()
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(src.join("item3")).unwrap(),
+            std::fs::read(&assembled).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_archive_job_to_recipients() {
+        use crate::encryption::Identity;
+
+        let src = PathBuf::from("../testing/dir3");
+        let dst = tmp_dir("transfer_engine_recipients_archive_job");
+        let (sender, receiver) = async_channel::unbounded();
+        let identity = Identity::generate();
+        let archive = Settings::default().set_splitter(Some(FileSplitterKind::Archive));
+
+        let settings = archive
+            .clone()
+            .set_recipients(vec!["age1notakey".to_string()]);
+        let res = TransferBuilder::new(&src, &dst)
+            .set_settings(settings)
+            .build();
+        assert_eq!(res.err().unwrap().code(), "encrypt_err");
+
+        let settings = archive.set_recipients(vec![identity.to_public().to_string()]);
+        let res = TransferBuilder::new(&src, &dst)
+            .set_settings(settings.clone())
+            .set_password(Some("correct horse battery".to_string()))
+            .build();
+        assert_eq!(res.err().unwrap().code(), "encrypt_err");
+
+        let job = TransferBuilder::new(&src, &dst)
+            .set_settings(settings)
+            .set_observer(sender)
+            .build()
+            .unwrap();
+        job.run().await;
+        let events = collect(receiver).await;
+        assert!(!events.iter().any(|e| matches!(e, TransferEvent::Error(_))));
+
+        let reader = crate::archive::ArchiveReader::open(dst.join("dir3.sppa"))
+            .await
+            .unwrap();
+        assert!(reader.has_recipients());
+        let mut reader = reader.set_identities(vec![identity]);
+        let unpacked = dst.join("unpacked");
+        reader.unpack(&unpacked).await.unwrap();
+        assert_eq!(
+            std::fs::read(src.join("item3")).unwrap(),
+            std::fs::read(unpacked.join("dir3/item3")).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dedup_job() {
+        let src = PathBuf::from("../testing");
+        let dst = tmp_dir("transfer_engine_dedup_job");
+        let settings = Settings::default().set_splitter(Some(FileSplitterKind::Dedup));
+
+        let mut reports = Vec::new();
+        for _ in 0..2 {
+            let (sender, receiver) = async_channel::unbounded();
+            let job = TransferBuilder::new(&src, &dst)
+                .set_settings(settings.clone())
+                .set_observer(sender)
+                .build()
+                .unwrap();
+            job.run().await;
+
+            let events = collect(receiver).await;
+            assert!(!events.iter().any(|e| matches!(e, TransferEvent::Error(_))));
+            reports.extend(events.into_iter().filter_map(|e| match e {
+                TransferEvent::Dedup(report) => Some(report),
+                _ => None,
+            }));
+        }
+
+        // nothing but the store is written, and nothing new the second time
+        assert!(!dst.join("testing").exists());
+        assert_eq!(reports.len(), 2);
+        assert!(reports[0].new_bytes > 0);
+        assert_eq!(reports[1].new_bytes, 0);
+        assert_eq!(reports[0].bytes, reports[1].bytes);
+
+        let store = crate::dedup::ChunkStore::open(dst.join("testing.sppd"))
+            .await
+            .unwrap();
+        let snapshots = store.snapshots().await.unwrap();
+        assert_eq!(snapshots.len(), 2);
+        let snapshot = store.read_snapshot(&snapshots[1]).await.unwrap();
+        let restored = dst.join("restored");
+        store.restore(&snapshot, &restored).await.unwrap();
+        assert_eq!(
+            std::fs::read("../testing/dir3/item3").unwrap(),
+            std::fs::read(restored.join("testing/dir3/item3")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_missing_src() {
+        let res = TransferBuilder::new("../testing/does_not_exist", "../testing").build();
+        assert_eq!(res.err().unwrap().code(), "path_not_found_err");
+    }
+
+    struct SkipAll;
+
+    #[async_trait]
+    impl Decider for SkipAll {
+        async fn decide(&self, _entry: &DecisionEntry) -> UserDecision {
+            UserDecision::SkipAll
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decider_skips_existing() {
+        let src = PathBuf::from("../testing/dir4");
+        let dst = tmp_dir("transfer_engine_decider");
+        std::fs::create_dir_all(dst.join("dir4")).unwrap();
+        std::fs::write(dst.join("dir4/item4.txt"), "old").unwrap();
+
+        let job = TransferBuilder::new(&src, &dst)
+            .set_decider(SkipAll)
+            .build()
+            .unwrap();
+        job.run().await;
+
+        assert_eq!(std::fs::read(dst.join("dir4/item4.txt")).unwrap(), b"old");
+    }
+}
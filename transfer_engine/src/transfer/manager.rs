@@ -0,0 +1,596 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
+
+use super::{
+    buffer_tuner::DeviceBufferSizes,
+    job::{TransferBuilder, TransferJob},
+    memory::MemoryBudget,
+    observer::{TransferEvent, TransferObserver},
+    settings::DEFAULT_MEMORY_BUDGET,
+};
+use crate::{
+    errnos::{Errno, ErrnoResult},
+    shared::throttle::RateLimiter,
+};
+
+/// Number of jobs allowed to run at the same time by default
+/// every job already runs several workers so this is kept low
+pub const MAX_RUNNING_JOBS: usize = 2;
+
+/// Identifies a job for as long as its manager is alive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct JobId(u64);
+
+impl fmt::Display for JobId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobStatus {
+    /// waiting for a free slot
+    Queued,
+    Running,
+    /// the job ran to the end, see `JobInfo::errors` for the entries that failed
+    Completed,
+    /// the job stopped before it got to the end, it panicked
+    Failed,
+}
+
+/// A snapshot of a job, this is what gets sent to the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    id: JobId,
+    src: PathBuf,
+    dst: PathBuf,
+    status: JobStatus,
+    /// bytes written so far
+    processed: u64,
+    /// percentage, stays at 0 until the size of the source is known
+    progress: u8,
+    /// number of entries that failed
+    errors: usize,
+    /// bytes of the memory budget in use the last time the job heard about it
+    memory: u64,
+}
+
+impl JobInfo {
+    fn new(id: JobId, job: &TransferJob) -> Self {
+        Self {
+            id,
+            src: job.src().to_path_buf(),
+            dst: job.dst().to_path_buf(),
+            status: JobStatus::Queued,
+            processed: 0,
+            progress: 0,
+            errors: 0,
+            memory: 0,
+        }
+    }
+
+    pub fn id(&self) -> JobId {
+        self.id
+    }
+
+    pub fn src(&self) -> &PathBuf {
+        &self.src
+    }
+
+    pub fn dst(&self) -> &PathBuf {
+        &self.dst
+    }
+
+    pub fn status(&self) -> JobStatus {
+        self.status
+    }
+
+    pub fn processed(&self) -> u64 {
+        self.processed
+    }
+
+    pub fn progress(&self) -> u8 {
+        self.progress
+    }
+
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    pub fn memory(&self) -> u64 {
+        self.memory
+    }
+}
+
+/// Same as [`TransferObserver`] but for every job of a [`JobManager`]
+pub trait JobObserver: Send + Sync {
+    fn notify(&self, id: JobId, event: TransferEvent);
+}
+
+impl JobObserver for async_channel::Sender<(JobId, TransferEvent)> {
+    fn notify(&self, id: JobId, event: TransferEvent) {
+        let _ = self.try_send((id, event));
+    }
+}
+
+/// The numbers of a job that change with every write, they are kept out of the lock
+/// of the manager so the jobs do not wait on each other to report their progress
+#[derive(Default)]
+struct JobCounters {
+    processed: AtomicU64,
+    progress: AtomicU8,
+    errors: AtomicUsize,
+    memory: AtomicU64,
+}
+
+impl JobCounters {
+    /// the info with the numbers as they are now
+    fn info(&self, info: &JobInfo) -> JobInfo {
+        JobInfo {
+            processed: self.processed.load(Ordering::Relaxed),
+            progress: self.progress.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            memory: self.memory.load(Ordering::Relaxed),
+            ..info.clone()
+        }
+    }
+}
+
+/// A job of the manager, the status only changes under the lock
+struct TrackedJob {
+    info: JobInfo,
+    counters: Arc<JobCounters>,
+}
+
+/// Keeps the `JobInfo` of a job up to date before passing the event along
+/// NOTE: only the events that change the status of the job take the lock of the manager
+struct JobTracker {
+    id: JobId,
+    inner: Weak<Mutex<Inner>>,
+    counters: Arc<JobCounters>,
+    observer: Option<Arc<dyn JobObserver>>,
+}
+
+impl TransferObserver for JobTracker {
+    fn notify(&self, event: TransferEvent) {
+        let counters = &self.counters;
+        match &event {
+            TransferEvent::Processed(processed) => {
+                counters.processed.fetch_add(*processed, Ordering::Relaxed);
+            }
+            TransferEvent::Progress(progress) => {
+                counters.progress.store(*progress, Ordering::Relaxed);
+            }
+            TransferEvent::Error(_) => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+            }
+            TransferEvent::Memory(memory) => counters.memory.store(*memory, Ordering::Relaxed),
+            TransferEvent::Completed => {
+                counters.progress.store(100, Ordering::Relaxed);
+                if let Some(inner) = self.inner.upgrade() {
+                    if let Some(info) = inner.lock().info_mut(self.id) {
+                        info.status = JobStatus::Completed;
+                    }
+                }
+            }
+            TransferEvent::WorkerDone(_)
+            | TransferEvent::Compression(_)
+            | TransferEvent::Dedup(_)
+            | TransferEvent::Pool(_) => {}
+        }
+
+        if let Some(observer) = &self.observer {
+            observer.notify(self.id, event);
+        }
+    }
+}
+
+struct Inner {
+    next_id: u64,
+    max_running: usize,
+    running: usize,
+    /// jobs waiting for a free slot, the front is started first
+    queue: VecDeque<(JobId, TransferJob)>,
+    /// every job ever added in the order they were added
+    jobs: Vec<TrackedJob>,
+    observer: Option<Arc<dyn JobObserver>>,
+    /// so every job starts with the buffer sizes the earlier jobs settled on
+    buffer_sizes: DeviceBufferSizes,
+    /// the bandwidth is shared by all the running jobs
+    limiter: RateLimiter,
+    /// the memory is shared by all the running jobs
+    memory_budget: MemoryBudget,
+    /// the runtime of the last call made from one, the jobs are started on it
+    /// even when a slot is given back outside of a runtime
+    handle: Option<Handle>,
+}
+
+impl Inner {
+    fn info_mut(&mut self, id: JobId) -> Option<&mut JobInfo> {
+        self.jobs
+            .iter_mut()
+            .map(|job| &mut job.info)
+            .find(|info| info.id == id)
+    }
+
+    fn info(&self, id: JobId) -> Option<JobInfo> {
+        self.jobs
+            .iter()
+            .find(|job| job.info.id == id)
+            .map(|job| job.counters.info(&job.info))
+    }
+}
+
+/// Accepts any number of transfers and runs at most `max_running` of them at a time
+/// the rest wait in a queue that can be reordered.
+/// NOTE: cloning the manager gives another handle to the same jobs
+#[derive(Clone)]
+pub struct JobManager {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Default for JobManager {
+    fn default() -> Self {
+        Self::new(MAX_RUNNING_JOBS)
+    }
+}
+
+impl JobManager {
+    /// # Arguments
+    /// * `max_running` - number of jobs that can run at the same time, 0 pauses the queue
+    pub fn new(max_running: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                next_id: 0,
+                max_running,
+                running: 0,
+                queue: VecDeque::new(),
+                jobs: Vec::new(),
+                observer: None,
+                buffer_sizes: DeviceBufferSizes::new(),
+                limiter: RateLimiter::default(),
+                memory_budget: MemoryBudget::new(DEFAULT_MEMORY_BUDGET),
+                handle: None,
+            })),
+        }
+    }
+
+    /// the events of every job will be sent to this observer along with the job id
+    pub fn set_observer<O: JobObserver + 'static>(self, observer: O) -> Self {
+        self.inner.lock().observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// most bytes the buffers of all the jobs can use together
+    /// this only applies to the jobs added after this
+    pub fn set_memory_budget(self, limit: u64) -> Self {
+        self.inner.lock().memory_budget = MemoryBudget::new(limit);
+        self
+    }
+
+    /// starts the queued jobs right away if the limit was raised
+    /// NOTE: the jobs only start once the manager was called from within a tokio runtime
+    pub fn set_max_running(&self, max_running: usize) {
+        self.inner.lock().max_running = max_running;
+        start_queued(&self.inner);
+    }
+
+    /// most bytes per second written by all the jobs together, None for unlimited
+    /// this applies right away, including to the running jobs
+    pub fn set_bandwidth_limit(&self, limit: Option<u64>) {
+        self.inner.lock().limiter.set_rate(limit);
+    }
+
+    /// adds the job at the back of the queue, it is started as soon as there is a free slot
+    /// any observer already set on the builder is replaced by the manager's,
+    /// the bandwidth limit and memory budget of the job settings are ignored in favor of the manager's
+    /// NOTE: the jobs only start once the manager was called from within a tokio runtime
+    pub fn push(&self, builder: TransferBuilder) -> ErrnoResult<JobId> {
+        let mut lock = self.inner.lock();
+        let id = JobId(lock.next_id);
+        let counters = Arc::new(JobCounters::default());
+        let tracker = JobTracker {
+            id,
+            inner: Arc::downgrade(&self.inner),
+            counters: Arc::clone(&counters),
+            observer: lock.observer.clone(),
+        };
+
+        let job = builder
+            .set_observer(tracker)
+            .set_buffer_sizes(lock.buffer_sizes.clone())
+            .set_rate_limiter(lock.limiter.clone())
+            .set_memory_budget(lock.memory_budget.clone())
+            .build()?;
+        lock.next_id += 1;
+        lock.jobs.push(TrackedJob {
+            info: JobInfo::new(id, &job),
+            counters,
+        });
+        lock.queue.push_back((id, job));
+        drop(lock);
+
+        start_queued(&self.inner);
+        Ok(id)
+    }
+
+    /// all the jobs, the finished and running ones first then the queued ones in the order they will run
+    pub fn jobs(&self) -> Vec<JobInfo> {
+        let lock = self.inner.lock();
+        let mut jobs: Vec<JobInfo> = lock
+            .jobs
+            .iter()
+            .filter(|job| job.info.status != JobStatus::Queued)
+            .map(|job| job.counters.info(&job.info))
+            .collect();
+
+        jobs.extend(lock.queue.iter().filter_map(|(id, _)| lock.info(*id)));
+        jobs
+    }
+
+    pub fn job(&self, id: JobId) -> ErrnoResult<JobInfo> {
+        self.inner
+            .lock()
+            .info(id)
+            .ok_or_else(|| Errno::job_not_found(id.to_string()))
+    }
+
+    /// moves a queued job to the given position of the queue
+    /// positions past the end of the queue move the job to the back
+    pub fn move_job(&self, id: JobId, position: usize) -> ErrnoResult<()> {
+        let mut lock = self.inner.lock();
+        let index = match lock.queue.iter().position(|(queued, _)| *queued == id) {
+            Some(index) => index,
+            None if lock.info(id).is_some() => {
+                return Err(Errno::job_not_queued(id.to_string()));
+            }
+            None => return Err(Errno::job_not_found(id.to_string())),
+        };
+
+        // SAFE because the index was just found
+        let job = lock.queue.remove(index).unwrap();
+        let position = position.min(lock.queue.len());
+        lock.queue.insert(position, job);
+        Ok(())
+    }
+
+    /// true when there is nothing running or waiting
+    pub fn is_idle(&self) -> bool {
+        let lock = self.inner.lock();
+        lock.running == 0 && lock.queue.is_empty()
+    }
+}
+
+/// runs as many queued jobs as the limit allows
+/// every job starts the next one when it is done
+fn start_queued(inner: &Arc<Mutex<Inner>>) {
+    let mut lock = inner.lock();
+    if let Ok(handle) = Handle::try_current() {
+        lock.handle = Some(handle);
+    }
+    // never called from a runtime, there is nothing to run the jobs on yet
+    let handle = match &lock.handle {
+        Some(handle) => handle.clone(),
+        None => return,
+    };
+
+    let mut started = Vec::new();
+    while lock.running < lock.max_running {
+        let (id, job) = match lock.queue.pop_front() {
+            Some(queued) => queued,
+            None => break,
+        };
+
+        lock.running += 1;
+        if let Some(info) = lock.info_mut(id) {
+            info.status = JobStatus::Running;
+        }
+        started.push((id, job));
+    }
+    // a runtime that shuts down drops the job right away, and its slot takes the lock
+    drop(lock);
+
+    for (id, job) in started {
+        let slot = RunningSlot {
+            id,
+            inner: Arc::clone(inner),
+        };
+        handle.spawn(async move {
+            job.run().await;
+            drop(slot);
+        });
+    }
+}
+
+/// The slot of a running job, it is given back once the job is done
+/// NOTE: this is a guard so a job that panics does not keep its slot forever
+struct RunningSlot {
+    id: JobId,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Drop for RunningSlot {
+    fn drop(&mut self) {
+        let mut lock = self.inner.lock();
+        lock.running -= 1;
+        if let Some(info) = lock.info_mut(self.id) {
+            // it never got to send `Completed`
+            if info.status == JobStatus::Running {
+                log::error!("job {} stopped before it was done", self.id);
+                info.status = JobStatus::Failed;
+            }
+        }
+        drop(lock);
+
+        start_queued(&self.inner);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fs::decision::{Decider, DecisionEntry, UserDecision},
+        utils::tmp::tmp_dir,
+    };
+
+    #[tokio::test]
+    async fn test_jobs_run_one_at_a_time() {
+        let dst = tmp_dir("transfer_engine_manager");
+        let (sender, receiver) = async_channel::unbounded();
+        let manager = JobManager::new(1).set_observer(sender);
+
+        let first = manager
+            .push(TransferBuilder::new(
+                "../testing/dir3",
+                dst.to_str().unwrap(),
+            ))
+            .unwrap();
+        let second = manager
+            .push(TransferBuilder::new(
+                "../testing/dir5",
+                dst.to_str().unwrap(),
+            ))
+            .unwrap();
+        assert_eq!(manager.job(second).unwrap().status(), JobStatus::Queued);
+
+        let mut completed = Vec::new();
+        while completed.len() < 2 {
+            let (id, event) = receiver.recv().await.unwrap();
+            if let TransferEvent::Completed = event {
+                completed.push(id);
+            }
+        }
+
+        assert_eq!(completed, vec![first, second]);
+        assert_eq!(manager.job(first).unwrap().processed(), 161);
+        assert_eq!(manager.job(second).unwrap().processed(), 20);
+        assert_eq!(manager.job(second).unwrap().status(), JobStatus::Completed);
+    }
+
+    struct Panics;
+
+    #[async_trait::async_trait]
+    impl Decider for Panics {
+        async fn decide(&self, _entry: &DecisionEntry) -> UserDecision {
+            panic!("the decider panicked");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_panicked_job_frees_its_slot() {
+        let dst = tmp_dir("transfer_engine_manager_panic");
+        std::fs::create_dir_all(dst.join("dir4")).unwrap();
+        std::fs::write(dst.join("dir4/item4.txt"), "old").unwrap();
+        let (sender, receiver) = async_channel::unbounded();
+        let manager = JobManager::new(1).set_observer(sender);
+
+        let panics = manager
+            .push(
+                TransferBuilder::new("../testing/dir4", dst.to_str().unwrap()).set_decider(Panics),
+            )
+            .unwrap();
+        let next = manager
+            .push(TransferBuilder::new(
+                "../testing/dir5",
+                dst.to_str().unwrap(),
+            ))
+            .unwrap();
+
+        loop {
+            let (id, event) = receiver.recv().await.unwrap();
+            if let TransferEvent::Completed = event {
+                assert_eq!(id, next);
+                break;
+            }
+        }
+
+        assert_eq!(manager.job(panics).unwrap().status(), JobStatus::Failed);
+        assert_eq!(manager.job(next).unwrap().status(), JobStatus::Completed);
+        // the slot is only given back once the task is gone
+        while !manager.is_idle() {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[test]
+    fn test_slot_given_back_outside_of_a_runtime() {
+        let dst = tmp_dir("transfer_engine_manager_no_runtime");
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let manager = JobManager::new(0);
+        // the manager keeps the runtime it was called from
+        runtime.block_on(async { manager.set_max_running(0) });
+        let id = manager
+            .push(TransferBuilder::new(
+                "../testing/dir5",
+                dst.to_str().unwrap(),
+            ))
+            .unwrap();
+        assert_eq!(manager.job(id).unwrap().status(), JobStatus::Queued);
+
+        // a job that ran on the runtime and ended on another thread
+        let inner = Arc::clone(&manager.inner);
+        {
+            let mut lock = inner.lock();
+            lock.max_running = 1;
+            lock.running = 1;
+        }
+        std::thread::spawn(move || {
+            drop(RunningSlot {
+                id: JobId(42),
+                inner,
+            })
+        })
+        .join()
+        .unwrap();
+        assert_eq!(manager.job(id).unwrap().status(), JobStatus::Running);
+
+        runtime.block_on(async {
+            while !manager.is_idle() {
+                tokio::task::yield_now().await;
+            }
+        });
+        assert_eq!(manager.job(id).unwrap().status(), JobStatus::Completed);
+        assert_eq!(manager.job(id).unwrap().processed(), 20);
+    }
+
+    #[test]
+    fn test_move_job() {
+        let manager = JobManager::new(0);
+        let ids: Vec<JobId> = ["dir3", "dir4", "dir5"]
+            .iter()
+            .map(|dir| {
+                manager
+                    .push(TransferBuilder::new(
+                        format!("../testing/{}", dir),
+                        "../testing".into(),
+                    ))
+                    .unwrap()
+            })
+            .collect();
+
+        manager.move_job(ids[2], 0).unwrap();
+        manager.move_job(ids[0], 10).unwrap();
+        let order: Vec<JobId> = manager.jobs().iter().map(|info| info.id()).collect();
+        assert_eq!(order, vec![ids[2], ids[1], ids[0]]);
+
+        assert_eq!(
+            manager.move_job(JobId(42), 0).err().unwrap().code(),
+            "job_not_found_err"
+        );
+    }
+}
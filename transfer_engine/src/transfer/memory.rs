@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::compression::{
+    algorithm::Algorithm,
+    parallel::{parallel_memory, parallel_threads_within},
+    policy::CompressionPolicy,
+};
+
+/// The budget is counted in KB so even a big one fits the permits of a semaphore
+const UNIT: u64 = 1024;
+
+/// Caps the memory used by the buffers of every worker together.
+/// whoever needs a buffer reserves its size first and waits while the budget is used up,
+/// the memory is given back once the [`MemoryPermit`] is dropped
+/// NOTE: cloning gives another handle to the same budget
+#[derive(Debug, Clone)]
+pub struct MemoryBudget {
+    semaphore: Arc<Semaphore>,
+    /// in units
+    limit: u64,
+}
+
+impl MemoryBudget {
+    /// # Arguments
+    /// * `limit` - most bytes in use at a time
+    pub fn new(limit: u64) -> Self {
+        let limit = (limit / UNIT).max(1);
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit as usize)),
+            limit,
+        }
+    }
+
+    /// in bytes
+    pub fn limit(&self) -> u64 {
+        self.limit * UNIT
+    }
+
+    /// bytes reserved right now
+    pub fn used(&self) -> u64 {
+        (self.limit - self.semaphore.available_permits() as u64) * UNIT
+    }
+
+    /// waits until `bytes` are available and reserves them
+    /// anything bigger than the whole budget waits for the whole budget instead of forever
+    pub async fn reserve(&self, bytes: u64) -> MemoryPermit {
+        let units = bytes.div_ceil(UNIT).clamp(1, self.limit);
+        let permit = Arc::clone(&self.semaphore)
+            .acquire_many_owned(units as u32)
+            .await
+            // SAFE because the semaphore is never closed
+            .unwrap();
+
+        MemoryPermit { _permit: permit }
+    }
+
+    /// reserves `bytes` only if they are available right now, without waiting
+    pub fn try_reserve(&self, bytes: u64) -> Option<MemoryPermit> {
+        let units = bytes.div_ceil(UNIT).clamp(1, self.limit);
+        let permit = Arc::clone(&self.semaphore)
+            .try_acquire_many_owned(units as u32)
+            .ok()?;
+
+        Some(MemoryPermit { _permit: permit })
+    }
+
+    /// reserves the blocks of compressing `size` bytes on many threads, when the policy asks for it
+    /// and returns the policy to compress with. it falls back to fewer threads, or only one,
+    /// when they do not fit in the budget right now
+    /// NOTE: this never waits, the chunks that would free the memory might be waiting on the caller
+    pub fn reserve_compression(
+        &self,
+        policy: &CompressionPolicy,
+        algorithm: &Algorithm,
+        size: u64,
+    ) -> (CompressionPolicy, Option<MemoryPermit>) {
+        let threads = match policy.parallel_threads(algorithm, size) {
+            Some(threads) => threads,
+            None => return (policy.clone(), None),
+        };
+
+        // half of the budget is left for the chunks and the buffers of the copies
+        let available = (self.semaphore.available_permits() as u64 * UNIT).min(self.limit() / 2);
+        let threads = threads.min(parallel_threads_within(available));
+        if threads > 1 {
+            if let Some(permit) = self.try_reserve(parallel_memory(threads)) {
+                return (policy.clone().set_threads(Some(threads)), Some(permit));
+            }
+        }
+
+        (policy.clone().set_threads(None), None)
+    }
+}
+
+/// Memory reserved from a [`MemoryBudget`], it is given back when this is dropped
+#[derive(Debug)]
+pub struct MemoryPermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_reserve_waits_for_memory() {
+        let budget = MemoryBudget::new(8 * 1024);
+        let permit = budget.reserve(6 * 1024).await;
+        assert_eq!(budget.used(), 6 * 1024);
+
+        // does not fit until the first permit is dropped
+        let waiting = budget.reserve(4 * 1024);
+        let res = tokio::time::timeout(Duration::from_millis(20), waiting).await;
+        assert!(res.is_err());
+
+        drop(permit);
+        let permit = budget.reserve(4 * 1024).await;
+        assert_eq!(budget.used(), 4 * 1024);
+
+        // bigger than the budget still gets through once everything is free
+        drop(permit);
+        let _permit = budget.reserve(1024 * 1024).await;
+        assert_eq!(budget.used(), budget.limit());
+        assert!(budget.try_reserve(1024).is_none());
+    }
+
+    #[test]
+    fn test_reserve_compression() {
+        let budget = MemoryBudget::new(2 * parallel_memory(4));
+        let policy = CompressionPolicy::default().set_threads(Some(8));
+        let size = u64::MAX;
+
+        // only half of the budget goes to the compression
+        let (four, permit) = budget.reserve_compression(&policy, &Algorithm::Zstd, size);
+        assert_eq!(four.threads(), Some(4));
+        assert_eq!(budget.used(), parallel_memory(4));
+
+        // what is left is not enough for two threads
+        let _chunks = budget.try_reserve(budget.limit() - parallel_memory(4) - 1024 * 1024);
+        let (one, none) = budget.reserve_compression(&policy, &Algorithm::Zstd, size);
+        assert_eq!(one.threads(), None);
+        assert!(none.is_none());
+
+        drop(permit);
+        let (small, none) = budget.reserve_compression(&policy, &Algorithm::Zstd, 1024);
+        assert_eq!(small.parallel_threads(&Algorithm::Zstd, 1024), None);
+        assert!(none.is_none());
+    }
+}
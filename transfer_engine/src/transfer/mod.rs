@@ -0,0 +1,50 @@
+// All implementation about the file transfer lives in this module
+mod batch;
+mod buffer_tuner;
+#[allow(unused)]
+mod chunk;
+mod concurrency;
+#[cfg(target_os = "linux")]
+mod direct;
+mod dst_path;
+// mod failed_part;
+mod file_assembler;
+// mod file_compressor;
+mod file_copier;
+#[allow(unused)]
+mod file_info;
+mod file_splitter;
+mod header;
+mod part;
+mod job;
+mod manager;
+mod memory;
+mod observer;
+mod page_cache;
+#[allow(unused)]
+mod parting_info;
+mod profiles;
+mod scheduler;
+mod settings;
+#[allow(unused)]
+mod status;
+#[allow(unused)]
+mod tracker;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+mod worker;
+pub use buffer_tuner::DeviceBufferSizes;
+pub use chunk::{Chunk, PoolStats};
+pub use file_assembler::FileAssembler;
+pub use job::{TransferBuilder, TransferJob};
+pub use manager::{JobId, JobInfo, JobManager, JobObserver, JobStatus, MAX_RUNNING_JOBS};
+pub use memory::MemoryBudget;
+pub use observer::{TransferEvent, TransferObserver};
+pub use profiles::{SettingsStore, SETTINGS_FILE};
+pub use scheduler::SchedulePolicy;
+pub use settings::{
+    CachePolicy, ConflictPolicy, CopyBackend, DurabilityPolicy, FileSplitterKind, MetadataPolicy,
+    Settings, MAX_BANDWIDTH_LIMIT, MAX_BUFFER_SIZE, MAX_DIRECT_IO_THRESHOLD, MAX_MEMORY_BUDGET,
+    MAX_WORKERS, MIN_BANDWIDTH_LIMIT, MIN_BUFFER_SIZE, MIN_DIRECT_IO_THRESHOLD, MIN_MEMORY_BUDGET,
+    MIN_WORKERS,
+};
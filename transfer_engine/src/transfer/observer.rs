@@ -0,0 +1,146 @@
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    compression::probe::{CompressionReport, CompressionSummary},
+    dedup::DedupReport,
+    errnos::{Errno, PropErrno, PropErrnoParams},
+    path::PathExt,
+    shared::progress::{Progress, ProgressProcessedFn, ProgressUpdater},
+    transfer::chunk::PoolStats,
+};
+
+/// Everything a running transfer has to say to the outside world
+#[derive(Debug, Clone)]
+pub enum TransferEvent {
+    /// number of bytes written since the last `Processed` event
+    Processed(u64),
+    /// the overall progress in percentage
+    /// only sent once the total size of the source is known
+    Progress(u8),
+    /// the worker with the given id is done with its file
+    WorkerDone(u8),
+    /// bytes of the memory budget in use, sent along with `WorkerDone`
+    /// the budget can be shared with other jobs so this is not only this job's memory
+    Memory(u64),
+    /// the compression picked for the files of the transfer, sent before `Completed`
+    /// only sent when something was compressed or probed
+    Compression(CompressionSummary),
+    /// how much of the source was already in the chunk store, sent before `Completed`
+    Dedup(DedupReport),
+    /// how often the buffers of the chunks were reused, sent before `Completed`
+    Pool(PoolStats),
+    /// something went wrong, the transfer will carry on with the next entry
+    Error(Errno),
+    /// the transfer is completed, no more events will be sent
+    Completed,
+}
+
+/// Receives the events of a transfer
+/// this is what the tauri app, a cli or any other embedder implements
+/// to show the progress and errors to the user.
+/// NOTE: this will be called from the worker tasks so it should return quickly
+pub trait TransferObserver: Send + Sync {
+    fn notify(&self, event: TransferEvent);
+}
+
+/// Allows the events to be consumed from a channel instead of a callback
+/// events sent after the receiver is dropped are ignored
+impl TransferObserver for async_channel::Sender<TransferEvent> {
+    fn notify(&self, event: TransferEvent) {
+        let _ = self.try_send(event);
+    }
+}
+
+/// This is handed to every worker of a transfer
+/// it forwards the events to the observer (if any) and keeps track of the progress
+#[derive(Clone)]
+pub struct Reporter {
+    observer: Option<Arc<dyn TransferObserver>>,
+    progress: Arc<Mutex<Progress>>,
+    /// bytes written since the last `take_processed`
+    processed: Arc<AtomicU64>,
+    /// the compression of the files so far, sent once the transfer is done
+    compression: Arc<Mutex<CompressionSummary>>,
+}
+
+impl Reporter {
+    pub fn new(observer: Option<Arc<dyn TransferObserver>>) -> Self {
+        let mut progress = Progress::new_no_total();
+        if let Some(observer) = &observer {
+            let observer = Arc::clone(observer);
+            progress.set_progress_tracker(Box::new(move |percent| {
+                observer.notify(TransferEvent::Progress(percent))
+            }));
+        }
+
+        Self {
+            observer,
+            progress: Arc::new(Mutex::new(progress)),
+            processed: Arc::new(AtomicU64::new(0)),
+            compression: Arc::new(Mutex::new(CompressionSummary::default())),
+        }
+    }
+
+    pub fn notify(&self, event: TransferEvent) {
+        if let Some(observer) = &self.observer {
+            observer.notify(event);
+        }
+    }
+
+    /// updates the progress with the processed bytes and lets the observer know
+    pub fn processed(&self, processed: u64) {
+        self.progress.lock().update(processed);
+        self.processed.fetch_add(processed, Ordering::Relaxed);
+        self.notify(TransferEvent::Processed(processed));
+    }
+
+    /// bytes written by all the workers since the last call
+    pub fn take_processed(&self) -> u64 {
+        self.processed.swap(0, Ordering::Relaxed)
+    }
+
+    /// callback to be passed to the progress writers
+    pub fn processed_fn(&self) -> ProgressProcessedFn {
+        let reporter = self.clone();
+        Arc::new(move |processed| reporter.processed(processed))
+    }
+
+    /// adds the compression picked for a file to the summary of the transfer
+    pub fn compression(&self, report: &CompressionReport) {
+        self.compression.lock().add(report);
+    }
+
+    /// sends the compression summary, if any file was added to it since the last call
+    pub fn notify_compression(&self) {
+        let summary = std::mem::take(&mut *self.compression.lock());
+        if summary.files > 0 {
+            self.notify(TransferEvent::Compression(summary));
+        }
+    }
+
+    pub fn set_total(&self, total: u128) {
+        self.progress.lock().set_total(total);
+    }
+
+    pub fn error(&self, errno: Errno) {
+        log::error!("{}", errno);
+        self.notify(TransferEvent::Error(errno));
+    }
+
+    /// same as `error` but translates the PropErrno with the given paths first
+    pub fn prop_error<P: AsRef<Path>>(&self, properrno: PropErrno, src: P, dst: P) {
+        let mut params = PropErrnoParams::new_with_src_and_dst(
+            src.as_ref().parent_and_current(),
+            dst.as_ref().parent_and_current(),
+        );
+        self.error(Errno::from_prop_errno(properrno, &mut params));
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    io::Result as IOResult,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+use tokio::{fs::File, io::AsyncWrite};
+
+use super::settings::CachePolicy;
+
+/// The files are dropped from the page cache this many bytes at a time
+pub const CACHE_REGION: u64 = 8 * 1024 * 1024; // 8MB
+
+/// Tells the kernel how a file is going to be copied
+/// so it reads ahead of the copy and, based on the policy, forgets what was copied
+/// NOTE: this does nothing outside of linux
+pub struct CacheAdvisor {
+    #[cfg(target_os = "linux")]
+    inner: linux::Advisor,
+}
+
+impl CacheAdvisor {
+    /// None when the policy leaves the page cache alone
+    pub fn new(policy: CachePolicy, src: &File, dst: &File) -> Option<Arc<Self>> {
+        if let CachePolicy::Keep = policy {
+            return None;
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            let evict = policy == CachePolicy::Evict;
+            linux::Advisor::new(src, dst, evict).map(|inner| Arc::new(Self { inner }))
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (src, dst);
+            None
+        }
+    }
+
+    /// the first `written` bytes of the destination reached the kernel,
+    /// every region that is done is dropped from the cache
+    /// NOTE: this makes system calls, it has to run on the blocking pool
+    pub fn written(&self, written: u64) {
+        #[cfg(target_os = "linux")]
+        self.inner.written(written);
+        #[cfg(not(target_os = "linux"))]
+        let _ = written;
+    }
+
+    /// drops whatever is left of both files from the cache
+    /// this waits for the destination to be written so it has to run on the blocking pool
+    pub fn finish(&self) {
+        #[cfg(target_os = "linux")]
+        self.inner.finish();
+    }
+}
+
+/// Tells the advisor how much of the destination reached the kernel as it is written
+/// NOTE: `writer` has to be the destination itself, a tokio file only takes a write
+/// once the one before it is done, so everything before the last write was written
+pub struct AdvisedWriter<W: AsyncWrite + Unpin> {
+    writer: W,
+    advisor: Option<Arc<CacheAdvisor>>,
+    /// bytes taken by the writer
+    accepted: u64,
+    /// bytes the advisor was last told about
+    reported: u64,
+}
+
+impl<W: AsyncWrite + Unpin> AdvisedWriter<W> {
+    pub fn new(writer: W, advisor: Option<Arc<CacheAdvisor>>) -> Self {
+        Self {
+            writer,
+            advisor,
+            accepted: 0,
+            reported: 0,
+        }
+    }
+
+    /// the advisor only hears about regions that are done, on the blocking pool
+    fn report(&mut self, written: u64) {
+        let advisor = match &self.advisor {
+            Some(advisor) => Arc::clone(advisor),
+            None => return,
+        };
+        if written / CACHE_REGION <= self.reported / CACHE_REGION {
+            return;
+        }
+
+        self.reported = written;
+        tokio::task::spawn_blocking(move || advisor.written(written));
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for AdvisedWriter<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IOResult<usize>> {
+        let this = self.get_mut();
+        let n = ready!(Pin::new(&mut this.writer).poll_write(cx, buf))?;
+        this.report(this.accepted);
+        this.accepted += n as u64;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        let this = self.get_mut();
+        ready!(Pin::new(&mut this.writer).poll_flush(cx))?;
+        this.report(this.accepted);
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        let this = self.get_mut();
+        ready!(Pin::new(&mut this.writer).poll_shutdown(cx))?;
+        this.report(this.accepted);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        os::fd::{AsFd, AsRawFd, OwnedFd},
+        sync::atomic::{AtomicU64, Ordering},
+    };
+
+    use tokio::fs::File;
+
+    use super::CACHE_REGION;
+
+    pub struct Advisor {
+        // copies of the handles so this can outlive the files
+        src: OwnedFd,
+        dst: OwnedFd,
+        evict: bool,
+        /// bytes of the destination in the kernel
+        written: AtomicU64,
+    }
+
+    // the hints are only hints, whatever they return the copy goes on the same
+    fn advise(fd: &OwnedFd, offset: u64, len: u64, advice: libc::c_int) {
+        // SAFE because the call only takes integers
+        unsafe {
+            libc::posix_fadvise(
+                fd.as_raw_fd(),
+                offset as libc::off_t,
+                len as libc::off_t,
+                advice,
+            );
+        }
+    }
+
+    fn write_back(fd: &OwnedFd, offset: u64, len: u64, flags: libc::c_uint) {
+        // SAFE because the call only takes integers
+        unsafe {
+            libc::sync_file_range(
+                fd.as_raw_fd(),
+                offset as libc::off64_t,
+                len as libc::off64_t,
+                flags,
+            );
+        }
+    }
+
+    impl Advisor {
+        pub fn new(src: &File, dst: &File, evict: bool) -> Option<Self> {
+            let src = src.as_fd().try_clone_to_owned().ok()?;
+            let dst = dst.as_fd().try_clone_to_owned().ok()?;
+            advise(&src, 0, 0, libc::POSIX_FADV_SEQUENTIAL);
+            // starts reading the first region before the copy asks for it
+            advise(&src, 0, CACHE_REGION, libc::POSIX_FADV_WILLNEED);
+
+            Some(Self {
+                src,
+                dst,
+                evict,
+                written: AtomicU64::new(0),
+            })
+        }
+
+        pub fn written(&self, written: u64) {
+            if !self.evict {
+                return;
+            }
+
+            // the hints can run out of order on the blocking pool
+            let before = self.written.fetch_max(written, Ordering::Relaxed);
+            for region in before / CACHE_REGION..written / CACHE_REGION {
+                let offset = region * CACHE_REGION;
+                // the pages are only dropped once they are written, so the region that just
+                // finished is sent to the disk and the one before it, written by now, is dropped
+                write_back(&self.dst, offset, CACHE_REGION, libc::SYNC_FILE_RANGE_WRITE);
+                if let Some(previous) = offset.checked_sub(CACHE_REGION) {
+                    advise(&self.src, previous, CACHE_REGION, libc::POSIX_FADV_DONTNEED);
+                    advise(&self.dst, previous, CACHE_REGION, libc::POSIX_FADV_DONTNEED);
+                }
+            }
+        }
+
+        pub fn finish(&self) {
+            if !self.evict {
+                return;
+            }
+
+            write_back(
+                &self.dst,
+                0,
+                0,
+                libc::SYNC_FILE_RANGE_WAIT_BEFORE
+                    | libc::SYNC_FILE_RANGE_WRITE
+                    | libc::SYNC_FILE_RANGE_WAIT_AFTER,
+            );
+            advise(&self.src, 0, 0, libc::POSIX_FADV_DONTNEED);
+            advise(&self.dst, 0, 0, libc::POSIX_FADV_DONTNEED);
+        }
+    }
+}
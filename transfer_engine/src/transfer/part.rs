@@ -0,0 +1,257 @@
+use async_rwlock::RwLock;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+    try_join,
+};
+
+use crate::{
+    compression::{algorithm::Algorithm, compress::Compression, policy::CompressionPolicy},
+    encryption::{EncryptWriter, EncryptionKey},
+    errnos::{PropErrno, PropErrnoResult},
+    map_to_properrno,
+    shared::{performance::Performance, progress::ProgressProcessedFn},
+};
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use super::uring;
+use super::{
+    chunk::{Chunk, ChunkAllocator, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE},
+    header::Header,
+    memory::MemoryPermit,
+};
+
+/// the file of the part, or the encryption in front of it
+type PartWriter = Box<dyn AsyncWrite + Unpin + Send>;
+
+/// Where the parts of a file read it from, shared by all of them
+#[derive(Clone)]
+pub enum PartSource {
+    /// the reads take turns since they have to seek first
+    Tokio(Arc<RwLock<File>>),
+    /// every read goes to its offset on its own, through a ring of the worker
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    Uring(Arc<std::fs::File>),
+}
+
+impl PartSource {
+    /// reads `len` bytes at the start of `chunk` into it, less only when the source ends before
+    async fn read(&self, mut chunk: Chunk, len: usize) -> PropErrnoResult<Chunk> {
+        let mut chunk = match self {
+            Self::Tokio(reader) => {
+                let mut reader = reader.write().await;
+                let seek_res = reader.seek(std::io::SeekFrom::Start(*chunk.start())).await;
+                map_to_properrno!(seek_res, PropErrno::Read)?;
+                while chunk.data().len() < len {
+                    // the buffer can hold more than `len`, the bytes after it belong to the next chunk
+                    let left = (len - chunk.data().len()) as u64;
+                    let read_res = (&mut *reader).take(left).read_buf(chunk.mut_data()).await;
+                    if map_to_properrno!(read_res, PropErrno::Read)? == 0 {
+                        break;
+                    }
+                }
+                chunk
+            }
+            #[cfg(all(target_os = "linux", feature = "io-uring"))]
+            Self::Uring(file) => {
+                let file = Arc::clone(file);
+                let read_res = tokio::task::spawn_blocking(move || {
+                    let off = *chunk.start();
+                    uring::read_at(&file, chunk.mut_data(), off, len).map(|_| chunk)
+                })
+                .await
+                .map_err(std::io::Error::other)
+                .and_then(|res| res);
+                map_to_properrno!(read_res, PropErrno::Read)?
+            }
+        };
+
+        chunk.set_end(chunk.start() + chunk.size());
+        Ok(chunk)
+    }
+}
+
+/// Compresses the bytes `start_offset..end_offset` of the source into a file of its own
+/// the part starts with its header, the compressed bytes follow (encrypted when there is a key)
+pub struct Part {
+    dst: Compression<PartWriter>,
+    dst_path: PathBuf,
+    /// written again once the crc of the part is known
+    header: Header,
+    /// another handle to the part to get back to its header
+    header_file: File,
+    /// of the bytes read from the source
+    hasher: crc32fast::Hasher,
+    next_offset: u64,
+    end_offset: u64,
+    /// the chunks wait on the memory budget of the job before reading
+    allocator: ChunkAllocator,
+    /// shared by all the parts of the source
+    reader: PartSource,
+    /// gets the bytes of the source once they are written
+    processed_cb: ProgressProcessedFn,
+    /// the blocks of the compression when it runs on many threads
+    _compression: Option<MemoryPermit>,
+}
+
+impl Part {
+    /// creates the part and writes its `header`, the crc of the part is filled in once it is done
+    /// # Arguments
+    /// * `key` - encrypts the compressed bytes, every part gets its own nonce
+    #[allow(clippy::too_many_arguments)]
+    pub async fn new_from_compression<P: AsRef<Path>>(
+        dst: P,
+        algorithm: &Algorithm,
+        perf: &Performance,
+        policy: &CompressionPolicy,
+        key: Option<&EncryptionKey>,
+        mut header: Header,
+        start_offset: u64,
+        end_offset: u64,
+        reader: PartSource,
+        allocator: ChunkAllocator,
+        processed_cb: ProgressProcessedFn,
+    ) -> PropErrnoResult<Self> {
+        let dst = dst.as_ref();
+        header.set_encrypted(key.is_some());
+        let mut file = PropErrno::from_io_result(File::create(dst).await, Some(dst))?;
+        // the header is never compressed so the part can be recognized without decompressing it
+        PropErrno::from_io_result(file.write_all(&header.bytes()).await, Some(dst))?;
+        let header_file = PropErrno::from_io_result(file.try_clone().await, Some(dst))?;
+        let file: PartWriter = match key {
+            // the header is sealed too, a part can't be passed off as another part
+            Some(key) => {
+                let mut writer = EncryptWriter::new(file, key);
+                PropErrno::from_io_result(writer.write_all(&header.bytes()).await, Some(dst))?;
+                Box::new(writer)
+            }
+            None => Box::new(file),
+        };
+        let size = end_offset - start_offset;
+        let (policy, permit) = allocator
+            .budget()
+            .reserve_compression(policy, algorithm, size);
+        let compression = Compression::from_policy(algorithm, file, perf, &policy, size);
+
+        Ok(Self {
+            dst: PropErrno::from_io_result(compression, Some(dst))?,
+            dst_path: dst.to_path_buf(),
+            header,
+            header_file,
+            hasher: crc32fast::Hasher::new(),
+            next_offset: start_offset,
+            end_offset,
+            allocator,
+            reader,
+            processed_cb,
+            _compression: permit,
+        })
+    }
+
+    async fn write_chunk(
+        dst: &mut Compression<PartWriter>,
+        path: &Path,
+        hasher: &mut crc32fast::Hasher,
+        mut chunk: Chunk,
+        processed_cb: &ProgressProcessedFn,
+    ) -> PropErrnoResult<()> {
+        let len = chunk.size();
+        hasher.update(chunk.data());
+        PropErrno::from_io_result(dst.write_all_buf(&mut chunk).await, Some(path))?;
+        processed_cb(len);
+        Ok(())
+    }
+
+    /// reads and compresses the part, the last chunk read is written while the next one is read
+    pub async fn start(&mut self) -> PropErrnoResult<()> {
+        // the chunks grow so a small part does not take much memory
+        // and a big one does not need many reads
+        let mut chunk_size = MIN_CHUNK_SIZE;
+        let mut pending: Option<Chunk> = None;
+
+        // as long as the next_offset is less than the end_offset
+        while self.next_offset < self.end_offset {
+            let len = (self.end_offset - self.next_offset).min(chunk_size as u64) as usize;
+            let next = self.allocator.try_alloc(self.next_offset, len);
+            let chunk = match (pending.take(), next) {
+                (Some(last), Some(next)) => {
+                    let writing = Self::write_chunk(
+                        &mut self.dst,
+                        &self.dst_path,
+                        &mut self.hasher,
+                        last,
+                        &self.processed_cb,
+                    );
+                    let reading = self.reader.read(next, len);
+                    try_join!(writing, reading)?.1
+                }
+                // no room for both, the memory of the last chunk is given back first
+                (Some(last), None) => {
+                    Self::write_chunk(
+                        &mut self.dst,
+                        &self.dst_path,
+                        &mut self.hasher,
+                        last,
+                        &self.processed_cb,
+                    )
+                    .await?;
+                    let next = self.allocator.alloc(self.next_offset, len).await;
+                    self.reader.read(next, len).await?
+                }
+                (None, Some(next)) => self.reader.read(next, len).await?,
+                (None, None) => {
+                    let next = self.allocator.alloc(self.next_offset, len).await;
+                    self.reader.read(next, len).await?
+                }
+            };
+
+            // the source got shorter since it was split
+            if chunk.size() == 0 {
+                log::error!(
+                    "{}: the source ended at {} instead of {}",
+                    self.dst_path.to_string_lossy(),
+                    self.next_offset,
+                    self.end_offset
+                );
+                return Err(PropErrno::Read);
+            }
+
+            // update the next_offset
+            self.next_offset = *chunk.end();
+            pending = Some(chunk);
+            chunk_size = (chunk_size * 2).min(MAX_CHUNK_SIZE);
+        }
+
+        if let Some(last) = pending {
+            Self::write_chunk(
+                &mut self.dst,
+                &self.dst_path,
+                &mut self.hasher,
+                last,
+                &self.processed_cb,
+            )
+            .await?;
+        }
+
+        // finishes the compressed stream and flushes it to the file
+        PropErrno::from_io_result(self.dst.shutdown().await, Some(&self.dst_path))?;
+        self.write_header().await
+    }
+
+    /// writes the header again with the crc of the part
+    async fn write_header(&mut self) -> PropErrnoResult<()> {
+        let crc = std::mem::take(&mut self.hasher).finalize();
+        self.header.set_part_crc(&crc);
+        let res = async {
+            self.header_file.seek(std::io::SeekFrom::Start(0)).await?;
+            self.header_file.write_all(&self.header.bytes()).await?;
+            self.header_file.flush().await
+        };
+
+        PropErrno::from_io_result(res.await, Some(&self.dst_path))
+    }
+}
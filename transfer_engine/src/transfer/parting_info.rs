@@ -2,7 +2,7 @@ use crate::{
     shared::performance::Performance,
     transfer::{
         chunk::MIN_CHUNK_SIZE,
-        transfer_manager::{MAX_AVERAGE_WORKERS, MAX_FAST_WORKERS, MAX_SLOW_WORKERS},
+        settings::{MAX_AVERAGE_WORKERS, MAX_FAST_WORKERS, MAX_SLOW_WORKERS},
     },
 };
 
@@ -41,13 +41,11 @@ impl PartingInfo {
             };
         }
 
-        let file_size = *file_size as f64;
-
-        // divide into two parts
-        let part_count = Self::worker_threads();
-        let part_size = file_size / part_count as f64;
+        // one part per worker thread, as long as the parts do not get too small.
+        // the size is rounded up so the parts cover the whole file, the last one is shorter
+        let part_count = (*file_size / MIN_PART_SIZE as u64).min(Self::worker_threads() as u64);
         Self {
-            size: part_size as u64,
+            size: file_size.div_ceil(part_count),
             count: part_count as u16,
         }
 
@@ -107,21 +105,22 @@ mod test {
 
     #[test]
     fn calculate_test() {
-        use super::{PartingInfo, Performance, MAX_SLOW_PARTS};
+        use super::{PartingInfo, Performance};
+        let parting_info = PartingInfo::calculate(&1000, &Performance::Fast);
+        assert_eq!(*parting_info.size(), 1000);
+        assert_eq!(*parting_info.count(), 1);
         let parting_info = PartingInfo::calculate(&(MIN_CHUNK_SIZE as u64 * 2), &Performance::Fast);
         assert_eq!(*parting_info.size(), 8192);
         assert_eq!(*parting_info.count(), 2);
         let parting_info = PartingInfo::calculate(&(17 * 1024), &Performance::Average);
-        assert_eq!(*parting_info.size(), 8192);
-        assert_eq!(*parting_info.count(), 3);
-        let parting_info = PartingInfo::calculate(
-            &(8 * 1024 * (MAX_SLOW_PARTS + 1) as u64),
-            &Performance::Slow,
-        );
-        assert_eq!(*parting_info.size(), 8256);
-        assert_eq!(*parting_info.count(), MAX_SLOW_PARTS as u16);
+        assert_eq!(*parting_info.size(), 8704);
+        assert_eq!(*parting_info.count(), 2);
 
-        let parting_info = PartingInfo::calculate(&222123236, &Performance::Fast);
+        let parting_info = PartingInfo::calculate(&222123237, &Performance::Slow);
+        assert_eq!(*parting_info.size(), 55530810);
+        assert_eq!(*parting_info.count(), PartingInfo::worker_threads() as u16);
+        // nothing is left after the last part
+        assert!(*parting_info.size() * *parting_info.count() as u64 >= 222123237);
         println!("{:?}", readable_size(*parting_info.size() as u128));
         println!("{:?}", parting_info);
     }
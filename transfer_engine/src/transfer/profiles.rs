@@ -0,0 +1,241 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::settings::{DurabilityPolicy, Settings};
+use crate::{
+    errnos::{Errno, ErrnoResult},
+    path::PathExt,
+    shared::performance::Performance,
+};
+
+/// Name of the file the settings are saved to inside the config directory
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// What ends up in the settings file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct StoredSettings {
+    settings: Settings,
+    profiles: BTreeMap<String, Settings>,
+}
+
+impl Default for StoredSettings {
+    fn default() -> Self {
+        Self {
+            settings: Settings::default(),
+            profiles: default_profiles(),
+        }
+    }
+}
+
+/// profiles every user starts with, they can be changed or removed like any other
+fn default_profiles() -> BTreeMap<String, Settings> {
+    let mut profiles = BTreeMap::new();
+    profiles.insert("Local disk".to_string(), Settings::new(Performance::Fast));
+    profiles.insert(
+        "USB stick".to_string(),
+        Settings::new(Performance::Slow)
+            .set_workers(Some(2))
+            .set_durability(DurabilityPolicy::Sync),
+    );
+    profiles.insert(
+        "NAS".to_string(),
+        Settings::new(Performance::Average).set_buffer_size(1024 * 1024),
+    );
+    profiles
+}
+
+/// Loads and saves the user settings and the named profiles
+/// every change is written to the file right away
+pub struct SettingsStore {
+    path: PathBuf,
+    stored: StoredSettings,
+}
+
+impl SettingsStore {
+    /// a store with the default settings that will be saved to `path`
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            stored: StoredSettings::default(),
+        }
+    }
+
+    /// reads the settings from `path`, the defaults are used if the file does not exist yet
+    pub fn load<P: AsRef<Path>>(path: P) -> ErrnoResult<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::new(path));
+        }
+
+        let content =
+            std::fs::read_to_string(path).map_err(|_| Errno::read(path.parent_and_current()))?;
+        let stored: StoredSettings = serde_json::from_str(&content)
+            .map_err(|_| Errno::corrupted_file(path.parent_and_current()))?;
+
+        stored.settings.validate()?;
+        for profile in stored.profiles.values() {
+            profile.validate()?;
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            stored,
+        })
+    }
+
+    /// writes to a temporary file first so a crash never leaves a half written file behind
+    pub fn save(&self) -> ErrnoResult<()> {
+        self.write(&self.stored)
+    }
+
+    fn write(&self, stored: &StoredSettings) -> ErrnoResult<()> {
+        let write_err = || Errno::write(self.path.parent_and_current());
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|_| write_err())?;
+        }
+
+        // SAFE because StoredSettings only contains types that serialize to json
+        let content = serde_json::to_string_pretty(stored).unwrap();
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, content).map_err(|_| write_err())?;
+        std::fs::rename(&tmp, &self.path).map_err(|_| write_err())?;
+        Ok(())
+    }
+
+    /// makes the change to a copy of the settings and only keeps it once the copy is saved,
+    /// so what is in memory never differs from the file
+    fn update<F>(&mut self, change: F) -> ErrnoResult<()>
+    where
+        F: FnOnce(&mut StoredSettings) -> ErrnoResult<()>,
+    {
+        let mut stored = self.stored.clone();
+        change(&mut stored)?;
+        self.write(&stored)?;
+        self.stored = stored;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// settings used for every new transfer
+    pub fn settings(&self) -> &Settings {
+        &self.stored.settings
+    }
+
+    pub fn set_settings(&mut self, settings: Settings) -> ErrnoResult<()> {
+        settings.validate()?;
+        self.update(|stored| {
+            stored.settings = settings;
+            Ok(())
+        })
+    }
+
+    pub fn profiles(&self) -> &BTreeMap<String, Settings> {
+        &self.stored.profiles
+    }
+
+    /// adds the profile or replaces the one with the same name
+    pub fn save_profile(&mut self, name: String, settings: Settings) -> ErrnoResult<()> {
+        settings.validate()?;
+        self.update(|stored| {
+            stored.profiles.insert(name, settings);
+            Ok(())
+        })
+    }
+
+    pub fn remove_profile(&mut self, name: &str) -> ErrnoResult<()> {
+        self.update(|stored| {
+            stored
+                .profiles
+                .remove(name)
+                .map(|_| ())
+                .ok_or_else(|| Errno::profile_not_found(name.to_string()))
+        })
+    }
+
+    /// makes the settings of the profile the current settings
+    pub fn apply_profile(&mut self, name: &str) -> ErrnoResult<&Settings> {
+        let settings = self
+            .stored
+            .profiles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Errno::profile_not_found(name.to_string()))?;
+        self.set_settings(settings)?;
+        Ok(self.settings())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tmp::tmp_dir;
+
+    /// the directory of the file is not created yet
+    fn tmp_file(name: &str) -> PathBuf {
+        tmp_dir(name).join("settings").join(SETTINGS_FILE)
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = tmp_file("transfer_engine_settings");
+        let mut store = SettingsStore::load(&path).unwrap();
+        assert_eq!(store.settings(), &Settings::default());
+
+        store.apply_profile("USB stick").unwrap();
+        store
+            .save_profile("Backup".to_string(), Settings::new(Performance::Average))
+            .unwrap();
+
+        let store = SettingsStore::load(&path).unwrap();
+        assert_eq!(store.settings(), &store.profiles()["USB stick"]);
+        assert!(store.profiles().contains_key("Backup"));
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        let path = tmp_file("transfer_engine_invalid_settings");
+        let mut store = SettingsStore::new(&path);
+        let err = store
+            .set_settings(Settings::default().set_workers(Some(1000)))
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), "setting_range_err");
+        assert_eq!(
+            store.apply_profile("nope").err().unwrap().code(),
+            "profile_not_found_err"
+        );
+
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "not json").unwrap();
+        assert_eq!(
+            SettingsStore::load(&path).err().unwrap().code(),
+            "corrupted_file_err"
+        );
+    }
+
+    #[test]
+    fn test_failed_save_keeps_settings() {
+        // the directory of the file can't be created over a file
+        let dir = tmp_dir("transfer_engine_failed_settings");
+        std::fs::write(dir.join("file"), "").unwrap();
+        let mut store = SettingsStore::new(dir.join("file").join(SETTINGS_FILE));
+
+        let settings = Settings::new(Performance::Slow);
+        assert_eq!(
+            store.set_settings(settings.clone()).err().unwrap().code(),
+            "write_err"
+        );
+        assert_eq!(store.settings(), &Settings::default());
+        assert!(store.save_profile("Backup".to_string(), settings).is_err());
+        assert!(store.remove_profile("NAS").is_err());
+        assert_eq!(store.profiles(), &default_profiles());
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Number of files the scheduler looks ahead to pick the next one from
+/// the order is only exact within this many files
+pub const LOOKAHEAD: usize = 4096;
+
+/// In which order the files are handed to the workers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SchedulePolicy {
+    /// the order they are found in
+    WalkOrder,
+    /// keeps every worker busy until the end instead of waiting on one big file
+    LargestFirst,
+    /// quick visible progress
+    SmallestFirst,
+    /// alternates between large and small files to keep both the disk and the cpu busy
+    Interleaved,
+}
+
+/// A file that is ready to be copied
+#[derive(Debug)]
+pub struct Scheduled {
+    pub src: PathBuf,
+    pub dst: PathBuf,
+    pub len: u64,
+}
+
+/// Sits between the traversal and the workers and decides which file goes next
+pub struct Scheduler {
+    policy: SchedulePolicy,
+    /// only used for `WalkOrder`
+    queue: VecDeque<Scheduled>,
+    /// ordered by size, the sequence keeps files of the same size in walk order
+    by_size: BTreeMap<(u64, u64), Scheduled>,
+    seq: u64,
+    /// for `Interleaved`, whether the next file is a large one
+    take_large: bool,
+}
+
+impl Scheduler {
+    pub fn new(policy: SchedulePolicy) -> Self {
+        Self {
+            policy,
+            queue: VecDeque::new(),
+            by_size: BTreeMap::new(),
+            seq: 0,
+            take_large: true,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len() + self.by_size.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// true when there are enough files to choose from
+    /// there is nothing to choose in walk order so a single file is enough
+    pub fn is_full(&self) -> bool {
+        match self.policy {
+            SchedulePolicy::WalkOrder => !self.is_empty(),
+            _ => self.len() >= LOOKAHEAD,
+        }
+    }
+
+    pub fn push(&mut self, scheduled: Scheduled) {
+        if let SchedulePolicy::WalkOrder = self.policy {
+            self.queue.push_back(scheduled);
+            return;
+        }
+
+        self.by_size.insert((scheduled.len, self.seq), scheduled);
+        self.seq += 1;
+    }
+
+    /// the next file to copy based on the policy
+    pub fn pop(&mut self) -> Option<Scheduled> {
+        match self.policy {
+            SchedulePolicy::WalkOrder => self.queue.pop_front(),
+            SchedulePolicy::LargestFirst => self.by_size.pop_last().map(|(_, s)| s),
+            SchedulePolicy::SmallestFirst => self.by_size.pop_first().map(|(_, s)| s),
+            SchedulePolicy::Interleaved => {
+                let take_large = self.take_large;
+                self.take_large = !take_large;
+                if take_large {
+                    self.by_size.pop_last().map(|(_, s)| s)
+                } else {
+                    self.by_size.pop_first().map(|(_, s)| s)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(policy: SchedulePolicy) -> Vec<u64> {
+        let mut scheduler = Scheduler::new(policy);
+        for len in [3, 1, 4, 1, 5] {
+            scheduler.push(Scheduled {
+                src: PathBuf::new(),
+                dst: PathBuf::new(),
+                len,
+            });
+        }
+
+        std::iter::from_fn(|| scheduler.pop().map(|s| s.len)).collect()
+    }
+
+    #[test]
+    fn test_policies() {
+        assert_eq!(order(SchedulePolicy::WalkOrder), vec![3, 1, 4, 1, 5]);
+        assert_eq!(order(SchedulePolicy::LargestFirst), vec![5, 4, 3, 1, 1]);
+        assert_eq!(order(SchedulePolicy::SmallestFirst), vec![1, 1, 3, 4, 5]);
+        assert_eq!(order(SchedulePolicy::Interleaved), vec![5, 1, 4, 1, 3]);
+    }
+}
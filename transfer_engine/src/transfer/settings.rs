@@ -0,0 +1,465 @@
+use serde::{Deserialize, Serialize};
+
+use super::scheduler::SchedulePolicy;
+use crate::{
+    compression::policy::CompressionPolicy,
+    encryption::parse_recipient,
+    errnos::Errno,
+    errnos::ErrnoResult,
+    shared::{performance::Performance, priority::IoPriority},
+};
+
+/// The most files copied at the same time based on the performance
+/// the job tunes the actual number of workers up to these
+pub const MAX_FAST_WORKERS: usize = 25;
+pub const MAX_AVERAGE_WORKERS: usize = 17;
+pub const MAX_SLOW_WORKERS: usize = 10;
+
+/// Limits of the values the user can set
+pub const MIN_WORKERS: usize = 1;
+pub const MAX_WORKERS: usize = 64;
+pub const MIN_BUFFER_SIZE: usize = 8 * 1024; // 8KB
+pub const MAX_BUFFER_SIZE: usize = 16 * 1024 * 1024; // 16MB
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024; // 64KB
+/// small files are read into memory whole, so they cannot be too big
+pub const MAX_BATCH_THRESHOLD: u64 = 4 * 1024 * 1024; // 4MB
+pub const DEFAULT_BATCH_THRESHOLD: u64 = 64 * 1024; // 64KB
+pub const MIN_BATCH_FILES: usize = 1;
+pub const MAX_BATCH_FILES: usize = 4096;
+pub const DEFAULT_BATCH_FILES: usize = 128;
+/// anything slower would take hours for a single photo
+pub const MIN_BANDWIDTH_LIMIT: u64 = 16 * 1024; // 16KB/s
+pub const MAX_BANDWIDTH_LIMIT: u64 = 64 * 1024 * 1024 * 1024; // 64GB/s
+/// has to leave room for at least a few tuned buffers
+pub const MIN_MEMORY_BUDGET: u64 = 16 * 1024 * 1024; // 16MB
+pub const MAX_MEMORY_BUDGET: u64 = 64 * 1024 * 1024 * 1024; // 64GB
+pub const DEFAULT_MEMORY_BUDGET: u64 = 256 * 1024 * 1024; // 256MB
+/// smaller files are better off in the page cache
+pub const MIN_DIRECT_IO_THRESHOLD: u64 = 16 * 1024 * 1024; // 16MB
+pub const MAX_DIRECT_IO_THRESHOLD: u64 = 1024 * 1024 * 1024 * 1024; // 1TB
+pub const DEFAULT_DIRECT_IO_THRESHOLD: u64 = 1024 * 1024 * 1024; // 1GB
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileSplitterKind {
+    /// split the files into chunks
+    /// this inheritedly means the compression is on
+    Split,
+    /// do not split the files into chunks
+    /// but still compress the files
+    Compress,
+    /// do not split the files into chunks
+    /// and do not compress the files
+    /// this is keeps the files in the original format and size
+    None,
+    /// compress the whole source into a single archive in the destination,
+    /// an archive with the same name is replaced
+    Archive,
+    /// cut the files into chunks by their content and add the ones that are new to a chunk
+    /// store in the destination, along with a snapshot of how to put the source back together.
+    /// copying a similar source into the same destination only writes the new chunks
+    Dedup,
+}
+
+/// What to do when a file already exists in the destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    /// ask the decider of the job, files are replaced if there is none
+    Ask,
+    Skip,
+    Replace,
+}
+
+/// Which metadata of the source is carried over to the copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MetadataPolicy {
+    None,
+    /// modified and accessed times
+    /// needed to tell duplicates apart from modified files on the next copy
+    Timestamps,
+    /// timestamps and permissions
+    All,
+}
+
+/// How hard to make sure the copies actually reached the disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DurabilityPolicy {
+    /// leave it to the operating system
+    None,
+    /// sync every file once it is written, slower but safe to unplug right after
+    Sync,
+}
+
+/// What the files are read and written with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CopyBackend {
+    /// the tokio file system, every read and write is a trip to the blocking pool
+    Tokio,
+    /// batches the reads and writes of a file through io_uring.
+    /// falls back to `Tokio` when the app is built without the `io-uring` feature
+    /// or the kernel does not support it
+    IoUring,
+}
+
+/// What the copy tells the kernel about the page cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CachePolicy {
+    /// no hints, the kernel caches the files as it sees fit
+    Keep,
+    /// the sources are read ahead of the copy
+    Sequential,
+    /// read ahead and drop both files from the cache as they are copied,
+    /// long jobs then do not push out what the other programs had cached
+    Evict,
+}
+
+/// This will keep track of all the user settings while transferring process
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    perf: Performance,
+    /// split kind
+    splitter: Option<FileSplitterKind>,
+    /// number of files copied at the same time
+    /// if not set the job tunes it within the limit of the performance
+    workers: Option<usize>,
+    /// size of the read and write buffers of every file in bytes
+    /// when `adaptive_buffer` is on, this is only the size the tuning starts from
+    buffer_size: usize,
+    /// grow the buffers while it makes the copy faster
+    adaptive_buffer: bool,
+    /// files smaller than this are copied in batches by a single worker, 0 turns batching off
+    batch_threshold: u64,
+    /// most files in a batch
+    batch_files: usize,
+    /// order the files are copied in
+    schedule: SchedulePolicy,
+    conflict: ConflictPolicy,
+    metadata: MetadataPolicy,
+    durability: DurabilityPolicy,
+    /// most bytes per second written by all the workers together, None for unlimited
+    bandwidth_limit: Option<u64>,
+    /// priority of the threads doing the copy
+    priority: IoPriority,
+    /// most bytes the buffers of all the workers can use together
+    memory_budget: u64,
+    backend: CopyBackend,
+    /// files at least this big skip the page cache when the file systems allow it, None never does
+    direct_io_threshold: Option<u64>,
+    cache: CachePolicy,
+    compression: CompressionPolicy,
+    /// public keys (`age1...`) the archive is encrypted to, only their private keys can
+    /// restore it. they can be kept in a profile, unlike a password
+    recipients: Vec<String>,
+}
+
+impl Settings {
+    pub fn new(perf: Performance) -> Self {
+        Self {
+            perf,
+            splitter: None,
+            workers: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            adaptive_buffer: true,
+            batch_threshold: DEFAULT_BATCH_THRESHOLD,
+            batch_files: DEFAULT_BATCH_FILES,
+            schedule: SchedulePolicy::WalkOrder,
+            conflict: ConflictPolicy::Ask,
+            metadata: MetadataPolicy::Timestamps,
+            durability: DurabilityPolicy::None,
+            bandwidth_limit: None,
+            priority: IoPriority::Normal,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            backend: CopyBackend::Tokio,
+            direct_io_threshold: Some(DEFAULT_DIRECT_IO_THRESHOLD),
+            cache: CachePolicy::Sequential,
+            compression: CompressionPolicy::default(),
+            recipients: Vec::new(),
+        }
+    }
+
+    pub fn set_splitter(mut self, splitter: Option<FileSplitterKind>) -> Self {
+        self.splitter = splitter;
+        self
+    }
+
+    pub fn set_workers(mut self, workers: Option<usize>) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    pub fn set_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    pub fn set_adaptive_buffer(mut self, adaptive_buffer: bool) -> Self {
+        self.adaptive_buffer = adaptive_buffer;
+        self
+    }
+
+    pub fn set_batch_threshold(mut self, batch_threshold: u64) -> Self {
+        self.batch_threshold = batch_threshold;
+        self
+    }
+
+    pub fn set_batch_files(mut self, batch_files: usize) -> Self {
+        self.batch_files = batch_files;
+        self
+    }
+
+    pub fn set_schedule(mut self, schedule: SchedulePolicy) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    pub fn set_conflict(mut self, conflict: ConflictPolicy) -> Self {
+        self.conflict = conflict;
+        self
+    }
+
+    pub fn set_metadata(mut self, metadata: MetadataPolicy) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn set_durability(mut self, durability: DurabilityPolicy) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    pub fn set_bandwidth_limit(mut self, bandwidth_limit: Option<u64>) -> Self {
+        self.bandwidth_limit = bandwidth_limit;
+        self
+    }
+
+    pub fn set_priority(mut self, priority: IoPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn set_memory_budget(mut self, memory_budget: u64) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    pub fn set_backend(mut self, backend: CopyBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn set_direct_io_threshold(mut self, direct_io_threshold: Option<u64>) -> Self {
+        self.direct_io_threshold = direct_io_threshold;
+        self
+    }
+
+    pub fn set_cache(mut self, cache: CachePolicy) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn set_compression(mut self, compression: CompressionPolicy) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn set_recipients(mut self, recipients: Vec<String>) -> Self {
+        self.recipients = recipients;
+        self
+    }
+
+    pub fn perf(&self) -> &Performance {
+        &self.perf
+    }
+
+    pub fn splitter(&self) -> Option<&FileSplitterKind> {
+        self.splitter.as_ref()
+    }
+
+    /// the fixed number of workers if the user set one
+    pub fn workers(&self) -> Option<usize> {
+        self.workers
+    }
+
+    /// the most workers a job can run at a time
+    pub fn worker_threads(&self) -> usize {
+        if let Some(workers) = self.workers {
+            return workers;
+        }
+
+        match self.perf {
+            Performance::Fast => MAX_FAST_WORKERS,
+            Performance::Average => MAX_AVERAGE_WORKERS,
+            Performance::Slow => MAX_SLOW_WORKERS,
+        }
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    pub fn adaptive_buffer(&self) -> bool {
+        self.adaptive_buffer
+    }
+
+    pub fn batch_threshold(&self) -> u64 {
+        self.batch_threshold
+    }
+
+    pub fn batch_files(&self) -> usize {
+        self.batch_files
+    }
+
+    pub fn schedule(&self) -> SchedulePolicy {
+        self.schedule
+    }
+
+    pub fn conflict(&self) -> ConflictPolicy {
+        self.conflict
+    }
+
+    pub fn metadata(&self) -> MetadataPolicy {
+        self.metadata
+    }
+
+    pub fn durability(&self) -> DurabilityPolicy {
+        self.durability
+    }
+
+    pub fn bandwidth_limit(&self) -> Option<u64> {
+        self.bandwidth_limit
+    }
+
+    pub fn priority(&self) -> IoPriority {
+        self.priority
+    }
+
+    pub fn memory_budget(&self) -> u64 {
+        self.memory_budget
+    }
+
+    pub fn backend(&self) -> CopyBackend {
+        self.backend
+    }
+
+    pub fn direct_io_threshold(&self) -> Option<u64> {
+        self.direct_io_threshold
+    }
+
+    pub fn cache(&self) -> CachePolicy {
+        self.cache
+    }
+
+    pub fn compression(&self) -> &CompressionPolicy {
+        &self.compression
+    }
+
+    pub fn recipients(&self) -> &[String] {
+        &self.recipients
+    }
+
+    /// makes sure all the values are within the limits
+    /// this should be called on anything that comes from the user
+    pub fn validate(&self) -> ErrnoResult<()> {
+        if let Some(workers) = self.workers {
+            if !(MIN_WORKERS..=MAX_WORKERS).contains(&workers) {
+                return Err(Errno::setting_range(
+                    "workers".to_string(),
+                    MIN_WORKERS as u64,
+                    MAX_WORKERS as u64,
+                ));
+            }
+        }
+
+        if !(MIN_BUFFER_SIZE..=MAX_BUFFER_SIZE).contains(&self.buffer_size) {
+            return Err(Errno::setting_range(
+                "buffer_size".to_string(),
+                MIN_BUFFER_SIZE as u64,
+                MAX_BUFFER_SIZE as u64,
+            ));
+        }
+
+        if self.batch_threshold > MAX_BATCH_THRESHOLD {
+            return Err(Errno::setting_range(
+                "batch_threshold".to_string(),
+                0,
+                MAX_BATCH_THRESHOLD,
+            ));
+        }
+
+        if !(MIN_BATCH_FILES..=MAX_BATCH_FILES).contains(&self.batch_files) {
+            return Err(Errno::setting_range(
+                "batch_files".to_string(),
+                MIN_BATCH_FILES as u64,
+                MAX_BATCH_FILES as u64,
+            ));
+        }
+
+        if let Some(limit) = self.bandwidth_limit {
+            if !(MIN_BANDWIDTH_LIMIT..=MAX_BANDWIDTH_LIMIT).contains(&limit) {
+                return Err(Errno::setting_range(
+                    "bandwidth_limit".to_string(),
+                    MIN_BANDWIDTH_LIMIT,
+                    MAX_BANDWIDTH_LIMIT,
+                ));
+            }
+        }
+
+        if !(MIN_MEMORY_BUDGET..=MAX_MEMORY_BUDGET).contains(&self.memory_budget) {
+            return Err(Errno::setting_range(
+                "memory_budget".to_string(),
+                MIN_MEMORY_BUDGET,
+                MAX_MEMORY_BUDGET,
+            ));
+        }
+
+        if let Some(threshold) = self.direct_io_threshold {
+            if !(MIN_DIRECT_IO_THRESHOLD..=MAX_DIRECT_IO_THRESHOLD).contains(&threshold) {
+                return Err(Errno::setting_range(
+                    "direct_io_threshold".to_string(),
+                    MIN_DIRECT_IO_THRESHOLD,
+                    MAX_DIRECT_IO_THRESHOLD,
+                ));
+            }
+        }
+
+        for recipient in &self.recipients {
+            if parse_recipient(recipient).is_err() {
+                return Err(Errno::encrypt(recipient.clone()));
+            }
+        }
+
+        self.compression.validate()
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::new(Performance::Fast)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(Settings::default().validate().is_ok());
+        let err = Settings::default()
+            .set_workers(Some(0))
+            .validate()
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), "setting_range_err");
+        assert!(Settings::default()
+            .set_buffer_size(MAX_BUFFER_SIZE + 1)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_missing_fields_use_defaults() {
+        let settings: Settings = serde_json::from_str(r#"{"perf": "Slow"}"#).unwrap();
+        assert_eq!(settings, Settings::new(Performance::Slow));
+    }
+}
@@ -0,0 +1,408 @@
+// Reads and writes of files through io_uring.
+// A copy keeps a few reads and writes in flight on one ring so it takes a few syscalls per
+// batch instead of one blocking pool hop per buffer. The rings are set up once and kept for
+// the next copy, so every worker ends up with a ring of its own
+use std::{
+    fs::File,
+    io,
+    ops::{Deref, DerefMut},
+    os::fd::AsRawFd,
+    sync::OnceLock,
+};
+
+use bytes::BytesMut;
+use crossbeam_queue::ArrayQueue;
+use io_uring::{cqueue, opcode, squeue, types, IoUring};
+
+use super::{buffer_tuner::MAX_TUNED_SIZE, settings::MAX_WORKERS};
+use crate::shared::throttle::RateLimiter;
+
+/// Number of buffers, and so reads or writes, in flight at a time
+pub const URING_BUFFERS: usize = 4;
+pub const URING_BUFFER_SIZE: usize = 1024 * 1024; // 1MB
+
+// a copy that falls back from O_DIRECT to io_uring has the memory of a tuned copy
+const _: () = assert!(URING_BUFFERS * URING_BUFFER_SIZE <= MAX_TUNED_SIZE);
+
+/// the memory the buffers of a copy of a `len` bytes file take
+pub fn copy_memory(len: u64) -> u64 {
+    let (count, size) = buffers_for(len);
+    (count * size) as u64
+}
+
+/// how many buffers of which size a copy of `len` bytes uses, a small file gets small buffers
+fn buffers_for(len: u64) -> (usize, usize) {
+    let size = len.clamp(1, URING_BUFFER_SIZE as u64);
+    let count = len.div_ceil(size).clamp(1, URING_BUFFERS as u64);
+    (count as usize, size as usize)
+}
+
+/// the rings nobody is using right now
+fn rings() -> &'static ArrayQueue<IoUring> {
+    static RINGS: OnceLock<ArrayQueue<IoUring>> = OnceLock::new();
+    RINGS.get_or_init(|| ArrayQueue::new(MAX_WORKERS))
+}
+
+/// A ring taken from the pool, it goes back once this is dropped
+struct PooledRing {
+    ring: Option<IoUring>,
+    /// a wait failed so the ring might still get the completions of the entries in flight
+    poisoned: bool,
+}
+
+impl PooledRing {
+    fn take() -> io::Result<Self> {
+        let ring = match rings().pop() {
+            Some(ring) => ring,
+            None => IoUring::new(URING_BUFFERS as u32)?,
+        };
+
+        Ok(Self {
+            ring: Some(ring),
+            poisoned: false,
+        })
+    }
+
+    /// pushes the entry and waits until `wait` entries are done
+    /// # Safety
+    /// the buffer of the entry has to live until its completion is popped
+    unsafe fn submit(&mut self, entry: &squeue::Entry, wait: usize) -> io::Result<()> {
+        self.submission().push(entry).map_err(io::Error::other)?;
+        self.wait(wait)
+    }
+
+    fn wait(&mut self, wait: usize) -> io::Result<()> {
+        loop {
+            match self.submit_and_wait(wait) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    self.poisoned = true;
+                    return Err(err);
+                }
+                Ok(_) => return Ok(()),
+            }
+        }
+    }
+
+    /// the entries that are done, they are copied out so more can be pushed while handling them
+    fn completed(&mut self) -> Vec<cqueue::Entry> {
+        self.completion().collect()
+    }
+}
+
+impl Deref for PooledRing {
+    type Target = IoUring;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFE because it is only taken on drop
+        self.ring.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledRing {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFE because it is only taken on drop
+        self.ring.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledRing {
+    fn drop(&mut self) {
+        match self.ring.take() {
+            // the kernel might still write to it, so it is never unmapped
+            Some(ring) if self.poisoned => std::mem::forget(ring),
+            // more rings than workers are not worth keeping
+            Some(ring) => {
+                let _ = rings().push(ring);
+            }
+            None => {}
+        }
+    }
+}
+
+/// true when the kernel lets us use io_uring, this is only checked once
+pub fn is_supported() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    // the ring set up to check is the first one of the pool
+    *SUPPORTED.get_or_init(|| PooledRing::take().is_ok())
+}
+
+/// reads `len` bytes of `file` at `off` to the end of `buf`, less only when the file ends before
+/// NOTE: this blocks, run it on the blocking pool
+pub fn read_at(file: &File, buf: &mut BytesMut, off: u64, len: usize) -> io::Result<()> {
+    let mut ring = PooledRing::take()?;
+    buf.reserve(len);
+    let start = buf.len();
+    while buf.len() - start < len {
+        let done = buf.len() - start;
+        let spare = buf.spare_capacity_mut();
+        let entry = opcode::Read::new(
+            types::Fd(file.as_raw_fd()),
+            spare.as_mut_ptr().cast(),
+            (len - done) as u32,
+        )
+        .offset(off + done as u64)
+        .build();
+        // SAFE because the read is waited for before the buffer is touched again
+        unsafe { ring.submit(&entry, 1)? };
+
+        let res = match ring.completed().first() {
+            Some(cqe) => cqe.result(),
+            None => return Err(io::Error::other("io_uring lost a read")),
+        };
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+        if res == 0 {
+            break;
+        }
+        // SAFE because the kernel wrote that many bytes past the end
+        unsafe { buf.set_len(buf.len() + res as usize) };
+    }
+
+    Ok(())
+}
+
+/// What a buffer is busy with
+#[derive(Clone, Copy)]
+enum Slot {
+    Free,
+    /// reading `len` bytes of the source at `off`
+    Reading {
+        off: u64,
+        len: u32,
+    },
+    /// writing `len` bytes to the destination at `off`, `done` of them are written.
+    /// `rest` is what a short read left to read once this is written
+    Writing {
+        off: u64,
+        len: u32,
+        done: u32,
+        rest: u32,
+    },
+}
+
+fn read_entry(src: &File, buffer: &mut [u8], index: usize, off: u64, len: u32) -> squeue::Entry {
+    opcode::Read::new(types::Fd(src.as_raw_fd()), buffer.as_mut_ptr(), len)
+        .offset(off)
+        .build()
+        .user_data(index as u64)
+}
+
+fn write_entry(
+    dst: &File,
+    buffer: &[u8],
+    index: usize,
+    off: u64,
+    skip: u32,
+    len: u32,
+) -> squeue::Entry {
+    opcode::Write::new(
+        types::Fd(dst.as_raw_fd()),
+        buffer[skip as usize..].as_ptr(),
+        len - skip,
+    )
+    .offset(off + skip as u64)
+    .build()
+    .user_data(index as u64)
+}
+
+/// copies the first `size` bytes of `src` into `dst`
+/// returns false without touching anything when io_uring cannot be set up,
+/// the copy should then go through the usual path.
+/// NOTE: this blocks, run it on the blocking pool
+pub fn copy<F: Fn(u64)>(
+    src: &File,
+    dst: &File,
+    size: u64,
+    limiter: &RateLimiter,
+    processed_cb: F,
+) -> io::Result<bool> {
+    let mut ring = match PooledRing::take() {
+        Ok(ring) => ring,
+        Err(_) => return Ok(false),
+    };
+    let (count, buffer_size) = buffers_for(size);
+    let mut buffers: Vec<Vec<u8>> = (0..count).map(|_| vec![0; buffer_size]).collect();
+    let mut slots = vec![Slot::Free; count];
+    let mut in_flight = 0;
+
+    let res = copy_with(
+        &mut ring,
+        src,
+        dst,
+        size,
+        (&mut buffers, &mut slots, &mut in_flight),
+        limiter,
+        processed_cb,
+    );
+
+    // the kernel still writes to the buffers of whatever is in flight
+    if in_flight > 0 && ring.wait(in_flight).is_err() {
+        log::error!("io_uring: giving up on {} entries in flight", in_flight);
+        std::mem::forget(buffers);
+    } else {
+        ring.completed();
+    }
+
+    res.map(|_| true)
+}
+
+type CopyState<'a> = (&'a mut [Vec<u8>], &'a mut [Slot], &'a mut usize);
+
+fn copy_with<F: Fn(u64)>(
+    ring: &mut PooledRing,
+    src: &File,
+    dst: &File,
+    size: u64,
+    (buffers, slots, in_flight): CopyState,
+    limiter: &RateLimiter,
+    processed_cb: F,
+) -> io::Result<()> {
+    let buffer_size = buffers[0].len() as u64;
+    let mut next: u64 = 0;
+    let push = |ring: &mut PooledRing, entry: &squeue::Entry, in_flight: &mut usize| {
+        // SAFE because the buffers are only dropped once nothing is in flight
+        unsafe { ring.submission().push(entry) }.map_err(io::Error::other)?;
+        *in_flight += 1;
+        Ok::<_, io::Error>(())
+    };
+
+    loop {
+        // every free buffer reads the next region of the source
+        for (index, slot) in slots.iter_mut().enumerate() {
+            if next >= size {
+                break;
+            }
+            if let Slot::Free = slot {
+                let read = (size - next).min(buffer_size) as u32;
+                let entry = read_entry(src, &mut buffers[index], index, next, read);
+                push(ring, &entry, in_flight)?;
+                *slot = Slot::Reading {
+                    off: next,
+                    len: read,
+                };
+                next += read as u64;
+            }
+        }
+
+        if *in_flight == 0 {
+            return Ok(());
+        }
+
+        ring.wait(1)?;
+        let completed = ring.completed();
+        *in_flight -= completed.len();
+        for cqe in completed {
+            if cqe.result() < 0 {
+                return Err(io::Error::from_raw_os_error(-cqe.result()));
+            }
+
+            let index = cqe.user_data() as usize;
+            let res = cqe.result() as u32;
+            slots[index] = match slots[index] {
+                // the source got shorter while copying, copy what is there
+                Slot::Reading { .. } if res == 0 => {
+                    next = size;
+                    Slot::Free
+                }
+                Slot::Reading { off, len } => {
+                    limiter.wait_blocking();
+                    let entry = write_entry(dst, &buffers[index], index, off, 0, res);
+                    push(ring, &entry, in_flight)?;
+                    Slot::Writing {
+                        off,
+                        len: res,
+                        done: 0,
+                        rest: len - res,
+                    }
+                }
+                Slot::Writing {
+                    off,
+                    len,
+                    done,
+                    rest,
+                } => {
+                    limiter.consume(res as u64);
+                    processed_cb(res as u64);
+                    let done = done + res;
+                    if done < len {
+                        let entry = write_entry(dst, &buffers[index], index, off, done, len);
+                        push(ring, &entry, in_flight)?;
+                        Slot::Writing {
+                            off,
+                            len,
+                            done,
+                            rest,
+                        }
+                    } else if rest > 0 {
+                        let off = off + len as u64;
+                        let entry = read_entry(src, &mut buffers[index], index, off, rest);
+                        push(ring, &entry, in_flight)?;
+                        Slot::Reading { off, len: rest }
+                    } else {
+                        Slot::Free
+                    }
+                }
+                Slot::Free => Slot::Free,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tmp::tmp_dir;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[test]
+    fn test_uring_copy() {
+        if !is_supported() {
+            return;
+        }
+
+        let dir = tmp_dir("transfer_engine_uring");
+        // not a multiple of the buffer size so the last read is short
+        let data: Vec<u8> = (0..URING_BUFFERS * URING_BUFFER_SIZE + 12345)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        std::fs::write(dir.join("src"), &data).unwrap();
+
+        let src = File::open(dir.join("src")).unwrap();
+        let dst = File::create(dir.join("dst")).unwrap();
+        let processed = AtomicU64::new(0);
+        let copied = copy(
+            &src,
+            &dst,
+            data.len() as u64,
+            &RateLimiter::default(),
+            |n| {
+                processed.fetch_add(n, Ordering::Relaxed);
+            },
+        )
+        .unwrap();
+
+        assert!(copied);
+        assert_eq!(processed.load(Ordering::Relaxed), data.len() as u64);
+        assert_eq!(std::fs::read(dir.join("dst")).unwrap(), data);
+
+        let mut buf = BytesMut::new();
+        read_at(&src, &mut buf, 10, 100).unwrap();
+        assert_eq!(&buf[..], &data[10..110]);
+        read_at(&src, &mut buf, data.len() as u64 - 5, 100).unwrap();
+        assert_eq!(&buf[100..], &data[data.len() - 5..]);
+    }
+
+    #[test]
+    fn test_buffers_for() {
+        assert_eq!(buffers_for(0), (1, 1));
+        assert_eq!(buffers_for(100), (1, 100));
+        assert_eq!(copy_memory(100), 100);
+        assert_eq!(buffers_for(3 * 1024 * 1024 / 2), (2, URING_BUFFER_SIZE));
+        assert_eq!(
+            copy_memory(u64::MAX),
+            (URING_BUFFERS * URING_BUFFER_SIZE) as u64
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use crate::transfer::{
+    batch::Batch, chunk::ChunkPool, file_copier::FileCopier, file_splitter::FileSplitter,
+    memory::MemoryPermit, observer::Reporter,
+};
+
+use tokio::task::{spawn, JoinHandle};
+// pub enum WorkType {
+//     Splitter(FileSplitter),
+//     Assembler(FileAssembler),
+// }
+
+#[allow(unused)]
+pub enum WorkAction {
+    Abort(u8),
+}
+
+/// A worker copies a single file on its own task
+/// awaiting the worker resolves to its id and how long a file took on average once it is done (or failed)
+pub struct Worker {
+    id: u8,
+    /// number of files the worker copies
+    files: u32,
+    started: Instant,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    /// the memory of the `permit` is given back once the copy is done
+    pub fn create_new_copier(
+        id: u8,
+        mut copier: FileCopier,
+        reporter: Reporter,
+        permit: MemoryPermit,
+    ) -> Self {
+        let handle = Some(spawn(async move {
+            let res = copier.copy().await;
+            drop(permit);
+
+            // if it completes successfully no need to inform because the copier will do that
+            if let Err(err) = res {
+                reporter.error(err);
+            }
+        }));
+
+        Self {
+            handle,
+            id,
+            files: 1,
+            started: Instant::now(),
+        }
+    }
+
+    /// copies all the small files of the batch one after the other
+    pub fn create_new_batch(
+        id: u8,
+        batch: Batch,
+        pool: ChunkPool,
+        reporter: Reporter,
+        permit: MemoryPermit,
+    ) -> Self {
+        let files = batch.len() as u32;
+        let handle = Some(spawn(async move {
+            batch.copy(pool, reporter).await;
+            drop(permit);
+        }));
+
+        Self {
+            handle,
+            id,
+            files,
+            started: Instant::now(),
+        }
+    }
+
+    /// compresses the file of the splitter into parts
+    /// the memory of the parts is reserved chunk by chunk while they are read
+    pub fn create_new_splitter(id: u8, splitter: FileSplitter, reporter: Reporter) -> Self {
+        let handle = Some(spawn(async move {
+            if let Err(err) = splitter.split().await {
+                reporter.prop_error(err, splitter.src(), splitter.dst());
+            }
+        }));
+
+        Self {
+            handle,
+            id,
+            files: 1,
+            started: Instant::now(),
+        }
+    }
+
+    #[allow(unused)]
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    #[allow(unused)]
+    pub fn is_complete(&self) -> bool {
+        if let Some(handle) = &self.handle {
+            return handle.is_finished();
+        }
+
+        // the handle is taken once the worker is done or aborted
+        true
+    }
+
+    #[allow(unused)]
+    pub fn abort(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+        }
+    }
+}
+
+impl Future for Worker {
+    type Output = (u8, Duration);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let id = self.id;
+        if let Some(handle) = self.handle.as_mut() {
+            // a panic or an abort inside of the task still means the worker is done
+            if Pin::new(handle).poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.handle = None;
+        }
+
+        Poll::Ready((id, self.started.elapsed() / self.files.max(1)))
+    }
+}
+
+// pub trait Work {
+//     /// Start the work
+//     fn start(&self);
+//     /// Pause the work
+//     fn pause(&self);
+//     /// Resume the work
+//     fn resume(&self);
+//     /// Cancel the work
+//     fn cancel(&self);
+//     /// Suspend the work
+//     fn suspend(&self);
+//     /// Resume the work from a given offset
+//     /// normally used when the work is suspended and resumed later on by the user
+//     /// or when the part failed to transfer and the work is resumed from the offset wheere it failed
+//     fn resume_from(&self, offset: u64);
+// }
@@ -0,0 +1,3 @@
+pub mod strings;
+#[cfg(test)]
+pub mod tmp;
@@ -0,0 +1,14 @@
+// Scratch directories for the tests.
+// Every call gets its own directory so tests running at the same time, or two runs of the
+// test suite, never write into each other's files
+use std::path::PathBuf;
+
+use super::strings::StringUtils;
+
+/// a new empty directory in the temp directory, `name` only makes it easier to find.
+/// NOTE: it is left behind so the files of a failed test can be looked at
+pub fn tmp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}_{}", name, String::random(10)));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}